@@ -88,6 +88,7 @@ impl Program {
             Uniform::Float(f) => self.set_float(name, f),
             Uniform::Vector3(v) => self.set_vector3(name, v),
             Uniform::TripleFloat(f1, f2, f3) => self.set_vec3(name, f1, f2, f3),
+            Uniform::QuadFloat(f1, f2, f3, f4) => self.set_vec4(name, f1, f2, f3, f4),
             Uniform::Matrix4(m) => self.set_mat4(name, m),
         }
     }
@@ -127,6 +128,12 @@ impl Program {
             .Uniform3f(self.gl.GetUniformLocation(self.id, name.as_ptr()), x, y, z);
     }
 
+    /// float型のユニフォーム変数4つを送る
+    pub unsafe fn set_vec4(&self, name: &CStr, x: f32, y: f32, z: f32, w: f32) {
+        self.gl
+            .Uniform4f(self.gl.GetUniformLocation(self.id, name.as_ptr()), x, y, z, w);
+    }
+
     /// 4次行列型(float)のユニフォーム変数を送る
     pub unsafe fn set_mat4(&self, name: &CStr, mat: &nalgebra::Matrix4<f32>) {
         self.gl.UniformMatrix4fv(
@@ -233,16 +240,19 @@ fn create_whitespace_cstring_with_len(len: usize) -> CString {
 }
 
 /// ユニフォーム変数
+#[derive(Clone, Copy)]
 pub enum Uniform<'a> {
     Bool(bool),
     Int(i32),
     Float(f32),
     Vector3(&'a nalgebra::Vector3<f32>),
     TripleFloat(f32, f32, f32),
+    QuadFloat(f32, f32, f32, f32),
     Matrix4(&'a nalgebra::Matrix4<f32>),
 }
 
 /// ユニフォーム変数のセット
+#[derive(Clone)]
 pub struct UniformVariables<'a> {
     map: HashMap<&'a CStr, Uniform<'a>>,
 }
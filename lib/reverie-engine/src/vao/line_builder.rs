@@ -0,0 +1,113 @@
+//! 線を描画する`Vao`のビルダー
+
+use super::mesh::{Mesh, VertexLayout};
+use super::Vao;
+use crate::gl::Gl;
+use crate::shader::Program;
+
+type Point3 = nalgebra::Point3<f32>;
+
+/// 線を描画する`Vao`のビルダー
+/// 
+/// 頂点属性は位置(3)だけ。インデックスは2つずつで1本の線になる(see: `Vao::draw_lines`)
+pub struct LineBuilder {
+    buffer: Vec<f32>,
+    indices: Vec<u32>,
+    vertex_num: u32,
+}
+
+impl LineBuilder {
+    /// 空の`LineBuilder`を作る
+    pub fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            indices: Vec::new(),
+            vertex_num: 0,
+        }
+    }
+
+    fn add_vertex(&mut self, p: &Point3) -> u32 {
+        self.buffer.extend_from_slice(&[p.x, p.y, p.z]);
+        self.vertex_num += 1;
+        self.vertex_num - 1
+    }
+
+    /// `p1`から`p2`までの線を追加する
+    pub fn add_line(&mut self, p1: &Point3, p2: &Point3) {
+        let i1 = self.add_vertex(p1);
+        let i2 = self.add_vertex(p2);
+        self.indices.extend_from_slice(&[i1, i2]);
+    }
+
+    /// 各辺が軸に並行な直方体の12本の辺を追加する
+    /// 
+    /// `begin`は`end`よりも(-∞, -∞, -∞)に近い
+    pub fn add_cuboid_edges(&mut self, begin: &Point3, end: &Point3) {
+        let base = self.vertex_num;
+        // i番目の頂点は、iのビットが立っている軸で`end`の座標をとる
+        for i in 0..8 {
+            let pick = |axis: usize| if i & (1 << axis) == 0 { begin[axis] } else { end[axis] };
+            self.add_vertex(&Point3::new(pick(0), pick(1), pick(2)));
+        }
+        for i in 0..8u32 {
+            for axis in 0..3 {
+                if i & (1 << axis) == 0 {
+                    self.indices.extend_from_slice(&[base + i, base + (i | (1 << axis))]);
+                }
+            }
+        }
+    }
+
+    /// 頂点属性の並び(see: `LineBuilder`)
+    pub fn layout() -> VertexLayout {
+        VertexLayout::new(vec![3])
+    }
+
+    /// OpenGLを使わずに`Mesh`を作る
+    pub fn build_mesh(self) -> Mesh {
+        Mesh {
+            vertices: self.buffer,
+            indices: self.indices,
+            layout: Self::layout(),
+        }
+    }
+
+    /// `Vao`を作る
+    pub fn build<'a>(self, gl: &Gl, program: &'a Program) -> Vao<'a> {
+        Vao::from_mesh(gl, &self.build_mesh(), program)
+    }
+}
+
+impl Default for LineBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuboid_edges() {
+        let mut builder = LineBuilder::new();
+        builder.add_line(&Point3::new(0.0, 0.0, 0.0), &Point3::new(0.0, 0.0, 1.0));
+        builder.add_cuboid_edges(&Point3::new(1.0, 2.0, 3.0), &Point3::new(2.0, 4.0, 6.0));
+        let mesh = builder.build_mesh();
+        assert_eq!(mesh.vertex_count(), 2 + 8);
+        assert_eq!(mesh.indices.len(), 2 * (1 + 12));
+
+        // どの辺も1つの軸に沿っていて、長さはその軸での直方体の大きさ
+        let size = [1.0, 2.0, 3.0];
+        let mut edges_per_axis = [0; 3];
+        for edge in mesh.indices[2..].chunks(2) {
+            let p1 = mesh.vertex(edge[0] as usize);
+            let p2 = mesh.vertex(edge[1] as usize);
+            let axes: Vec<usize> = (0..3).filter(|&axis| p1[axis] != p2[axis]).collect();
+            assert_eq!(axes.len(), 1);
+            assert_eq!(p2[axes[0]] - p1[axes[0]], size[axes[0]]);
+            edges_per_axis[axes[0]] += 1;
+        }
+        assert_eq!(edges_per_axis, [4, 4, 4]);
+    }
+}
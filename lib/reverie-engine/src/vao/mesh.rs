@@ -0,0 +1,135 @@
+//! GPUに送る前の頂点データ
+
+/// 頂点属性の並び
+///
+/// 属性はすべて`f32`で、`attribute_sizes`の順に詰めて並べる
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct VertexLayout {
+    attribute_sizes: Vec<usize>,
+}
+
+impl VertexLayout {
+    /// 各属性の要素数を指定する
+    pub fn new(attribute_sizes: Vec<usize>) -> Self {
+        Self { attribute_sizes }
+    }
+
+    /// 各属性の要素数
+    pub fn attribute_sizes(&self) -> &[usize] {
+        &self.attribute_sizes
+    }
+
+    /// 1頂点あたりの`f32`の数
+    pub fn stride(&self) -> usize {
+        self.attribute_sizes.iter().sum()
+    }
+
+    /// `attribute`番目の属性が始まる位置(`f32`の数)
+    pub fn offset(&self, attribute: usize) -> usize {
+        self.attribute_sizes[..attribute].iter().sum()
+    }
+}
+
+/// 頂点とインデックスの配列
+///
+/// OpenGLに依存しないので、どのスレッドでも作ることができる。
+/// 描画するには`Vao::from_mesh`でGPUに送る
+#[derive(Clone, PartialEq, Debug)]
+pub struct Mesh {
+    pub vertices: Vec<f32>,
+    /// 3つずつで1つの三角形になる、`vertices`の頂点の番号
+    pub indices: Vec<u32>,
+    pub layout: VertexLayout,
+}
+
+impl Mesh {
+    /// 頂点もインデックスも無い`Mesh`を作る
+    pub fn new(layout: VertexLayout) -> Self {
+        Self {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            layout,
+        }
+    }
+
+    /// 頂点の数
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len() / self.layout.stride()
+    }
+
+    /// `index`番目の頂点の属性
+    pub fn vertex(&self, index: usize) -> &[f32] {
+        let stride = self.layout.stride();
+        &self.vertices[index * stride..(index + 1) * stride]
+    }
+
+    /// 三角形の数
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// 長方形を`eye`から遠い順に並べ替える。半透明な面を正しく重ねて描画するために使う
+    /// 
+    /// インデックスは6つずつで1つの長方形になっていて(see: `VaoBuilder`)、
+    /// 頂点属性の最初の3つが位置でなければならない
+    pub fn sort_quads_back_to_front(&mut self, eye: &[f32; 3]) {
+        let stride = self.layout.stride();
+        let vertices = &self.vertices;
+        let distance = |quad: &[u32]| -> f32 {
+            let mut center = [0.0; 3];
+            for &index in quad {
+                for (i, c) in center.iter_mut().enumerate() {
+                    *c += vertices[index as usize * stride + i] / quad.len() as f32;
+                }
+            }
+            (0..3).map(|i| (center[i] - eye[i]).powi(2)).sum()
+        };
+        let mut quads: Vec<(f32, &[u32])> = self.indices.chunks(6).map(|quad| (distance(quad), quad)).collect();
+        quads.sort_by(|a, b| b.0.total_cmp(&a.0));
+        self.indices = quads.iter().flat_map(|(_, quad)| quad.iter().copied()).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_and_vertices() {
+        let layout = VertexLayout::new(vec![3, 2]);
+        assert_eq!(layout.stride(), 5);
+        assert_eq!(layout.offset(1), 3);
+
+        let mut mesh = Mesh::new(layout);
+        assert!(mesh.is_empty());
+        mesh.vertices = vec![0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0];
+        mesh.indices = vec![0, 1, 2];
+        assert_eq!(mesh.vertex_count(), 3);
+        assert_eq!(mesh.vertex(1), &[1.0, 0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(mesh.triangle_count(), 1);
+    }
+
+    #[test]
+    fn quads_are_sorted_back_to_front() {
+        let mut mesh = Mesh::new(VertexLayout::new(vec![3]));
+        // x = 0, 1, 2の位置にある、yz平面上の3枚の長方形
+        for x in 0..3 {
+            for &(y, z) in &[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+                mesh.vertices.extend_from_slice(&[x as f32, y, z]);
+            }
+            let base = x * 4;
+            mesh.indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+        let first_vertex = |mesh: &Mesh| mesh.indices.chunks(6).map(|quad| quad[0]).collect::<Vec<_>>();
+
+        mesh.sort_quads_back_to_front(&[-5.0, 0.5, 0.5]);
+        assert_eq!(first_vertex(&mesh), vec![8, 4, 0]);
+        mesh.sort_quads_back_to_front(&[1.2, 0.5, 0.5]);
+        assert_eq!(first_vertex(&mesh), vec![0, 8, 4]);
+        assert_eq!(mesh.triangle_count(), 6);
+    }
+}
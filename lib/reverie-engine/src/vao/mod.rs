@@ -8,13 +8,20 @@ use crate::gl::types::{GLenum, GLfloat, GLint, GLsizei, GLsizeiptr};
 use crate::gl::Gl;
 use crate::shader::{Program, UniformVariables};
 
+use mesh::Mesh;
+
+pub mod line_builder;
+pub mod mesh;
 pub mod vao_builder;
 
 /// OpenGLのVertex Array ObjectとVertex Buffer Objectに対応する構造体
 pub struct Vao<'a> {
     gl: Gl,
     vao: u32,
-    _vbo: u32,
+    vbo: u32,
+    /// Element Buffer Object。インデックスを使わずに描画するときは`None`
+    ebo: Option<u32>,
+    /// `ebo`があるときはインデックスの数
     vertex_num: i32,
     program: &'a Program,
 }
@@ -71,18 +78,75 @@ impl<'a> Vao<'a> {
         Vao {
             gl,
             vao,
-            _vbo: vbo,
+            vbo,
+            ebo: None,
             vertex_num,
             program,
         }
     }
 
+    /// `Mesh`をGPUに送る
+    pub fn from_mesh(gl: &Gl, mesh: &Mesh, program: &'a Program) -> Vao<'a> {
+        let layout = &mesh.layout;
+        let mut vao = Vao::new(
+            gl.clone(),
+            (mesh.vertices.len() * mem::size_of::<GLfloat>()) as _,
+            mesh.vertices.as_ptr() as _,
+            gl::STATIC_DRAW,
+            layout.attribute_sizes().len(),
+            vec![gl::FLOAT; layout.attribute_sizes().len()],
+            layout.attribute_sizes().iter().map(|&size| size as GLint).collect(),
+            (layout.stride() * mem::size_of::<GLfloat>()) as _,
+            mesh.indices.len() as i32,
+            program,
+        );
+
+        let mut ebo = 0;
+        unsafe {
+            gl.GenBuffers(1, &mut ebo);
+            gl.BindVertexArray(vao.vao);
+            gl.BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+            gl.BufferData(
+                gl::ELEMENT_ARRAY_BUFFER,
+                (mesh.indices.len() * mem::size_of::<u32>()) as _,
+                mesh.indices.as_ptr() as _,
+                gl::STATIC_DRAW,
+            );
+            // VAOをアンバインドする前にEBOをアンバインドすると、VAOからEBOが外れてしまう
+            gl.BindVertexArray(0);
+            gl.BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
+        }
+        vao.ebo = Some(ebo);
+        vao
+    }
+
+    /// インデックスを並べ替えたものに置き換える
+    /// 
+    /// `from_mesh`で作った`Vao`で、インデックスの数は変えられない
+    pub fn update_indices(&mut self, indices: &[u32]) {
+        assert_eq!(indices.len(), self.vertex_num as usize);
+        unsafe {
+            self.gl.BindVertexArray(self.vao);
+            self.gl.BufferSubData(
+                gl::ELEMENT_ARRAY_BUFFER,
+                0,
+                (indices.len() * mem::size_of::<u32>()) as _,
+                indices.as_ptr() as _,
+            );
+            self.gl.BindVertexArray(0);
+        }
+    }
+
     pub fn draw(&self, uniforms: &UniformVariables, draw_mode: GLenum) {
         unsafe {
             self.program.set_used();
             self.program.set_uniforms(uniforms);
             self.gl.BindVertexArray(self.vao);
-            self.gl.DrawArrays(draw_mode, 0, self.vertex_num);
+            if self.ebo.is_some() {
+                self.gl.DrawElements(draw_mode, self.vertex_num, gl::UNSIGNED_INT, std::ptr::null());
+            } else {
+                self.gl.DrawArrays(draw_mode, 0, self.vertex_num);
+            }
             self.gl.BindVertexArray(0);
         }
     }
@@ -91,4 +155,22 @@ impl<'a> Vao<'a> {
     pub fn draw_triangles(&self, uniforms: &UniformVariables) {
         self.draw(uniforms, gl::TRIANGLES);
     }
+
+    /// 線を描画する。`LineBuilder`で作った`Vao`に使う
+    pub fn draw_lines(&self, uniforms: &UniformVariables) {
+        self.draw(uniforms, gl::LINES);
+    }
+}
+
+impl<'a> Drop for Vao<'a> {
+    /// OpenGLが保持しているVAOとVBO(とEBO)の実体も削除される
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(ebo) = &self.ebo {
+                self.gl.DeleteBuffers(1, ebo);
+            }
+            self.gl.DeleteBuffers(1, &self.vbo);
+            self.gl.DeleteVertexArrays(1, &self.vao);
+        }
+    }
 }
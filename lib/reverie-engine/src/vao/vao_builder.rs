@@ -1,18 +1,23 @@
 //! `Vao`のビルダー
 
-use std::mem;
-
+use super::mesh::{Mesh, VertexLayout};
 use super::Vao;
-use crate::gl;
-use crate::gl::{types::GLfloat, Gl};
+use crate::gl::Gl;
 use crate::shader::Program;
 use crate::texture::texture_atlas::TextureUV;
 
 type Point3 = nalgebra::Point3<f32>;
+type Vector3 = nalgebra::Vector3<f32>;
 
 /// `Vao`のビルダー
+/// 
+/// 頂点属性は位置(3)、法線(3)、UV座標(2)、テクスチャアトラス上の範囲(4)、
+/// 周りのブロックによる遮蔽(ambient occlusion)を考えた明るさ(1)、
+/// 空からの光とブロックからの赤、緑、青の光の強さ(4)
+/// (see: `VertexLight`)
 pub struct VaoBuilder<'a> {
     buffer: Vec<f32>,
+    indices: Vec<u32>,
     vertex_num: i32,
     program: Option<&'a Program>,
 }
@@ -22,6 +27,7 @@ impl<'a> VaoBuilder<'a> {
     pub fn new() -> Self {
         Self {
             buffer: Vec::<f32>::new(),
+            indices: Vec::new(),
             vertex_num: 0,
             program: None,
         }
@@ -31,6 +37,7 @@ impl<'a> VaoBuilder<'a> {
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             buffer: Vec::<f32>::with_capacity(capacity),
+            indices: Vec::new(),
             vertex_num: 0,
             program: None,
         }
@@ -40,80 +47,229 @@ impl<'a> VaoBuilder<'a> {
     /// 
     /// `begin`は`end`よりも(-∞, -∞, -∞)に近い
     pub fn add_cuboid<'b>(&mut self, begin: &Point3, end: &Point3, textures: &CuboidTextures<'b>) {
+        self.add_cuboid_faces(begin, end, textures, &CuboidFaces::ALL);
+    }
+
+    /// 各辺が軸に並行な直方体のうち、`faces`で指定した面だけを追加する
+    /// 
+    /// 他のブロックに隠れて見えない面を省くのに使う(see: `add_cuboid`)
+    pub fn add_cuboid_faces<'b>(
+        &mut self,
+        begin: &Point3,
+        end: &Point3,
+        textures: &CuboidTextures<'b>,
+        faces: &CuboidFaces,
+    ) {
+        self.push_cuboid_faces(begin, end, textures, faces, false, &|_, _| VertexLight::FULL);
+    }
+
+    /// `add_cuboid_faces`と同じだが、テクスチャを引き伸ばさずに長さ1ごとに繰り返す
+    /// 
+    /// 複数のブロックの面をまとめた大きな面に使う(see: `add_tiled_face`)
+    pub fn add_tiled_cuboid_faces<'b>(
+        &mut self,
+        begin: &Point3,
+        end: &Point3,
+        textures: &CuboidTextures<'b>,
+        faces: &CuboidFaces,
+    ) {
+        self.push_cuboid_faces(begin, end, textures, faces, true, &|_, _| VertexLight::FULL);
+    }
+
+    /// `add_cuboid_faces`(`tiled`なら`add_tiled_cuboid_faces`)と同じだが、頂点ごとの明るさを指定する
+    /// 
+    /// `light`は頂点の位置と面の外向きの法線から、その頂点の明るさを返す
+    pub fn add_cuboid_faces_with_light<'b>(
+        &mut self,
+        begin: &Point3,
+        end: &Point3,
+        textures: &CuboidTextures<'b>,
+        faces: &CuboidFaces,
+        tiled: bool,
+        light: &dyn Fn(&Point3, &Vector3) -> VertexLight,
+    ) {
+        self.push_cuboid_faces(begin, end, textures, faces, tiled, light);
+    }
+
+    fn push_cuboid_faces<'b>(
+        &mut self,
+        begin: &Point3,
+        end: &Point3,
+        textures: &CuboidTextures<'b>,
+        faces: &CuboidFaces,
+        tiled: bool,
+        light: &dyn Fn(&Point3, &Vector3) -> VertexLight,
+    ) {
+        let add_face = |builder: &mut Self,
+                        p1: &Point3,
+                        p2: &Point3,
+                        p3: &Point3,
+                        p4: &Point3,
+                        uv: &TextureUV,
+                        rotation: u8| {
+            let (repeat_u, repeat_v) = if tiled { ((p3 - p2).norm(), (p1 - p2).norm()) } else { (1.0, 1.0) };
+            // `face_normal`は直方体の内側を向く
+            let outward = -face_normal(p1, p2, p3, p4);
+            let light = [p1, p2, p3, p4].map(|p| light(p, &outward));
+            builder.push_face(p1, p2, p3, p4, uv, rotation, repeat_u, repeat_v, &light);
+        };
         // 上面
-        self.add_face(
-            &Point3::new(begin.x, end.y, begin.z),
-            &Point3::new(begin.x, end.y, end.z),
-            &end,
-            &Point3::new(end.x, end.y, begin.z),
-            &textures.top,
-        );
+        if faces.top {
+            add_face(
+                self,
+                &Point3::new(begin.x, end.y, begin.z),
+                &Point3::new(begin.x, end.y, end.z),
+                &end,
+                &Point3::new(end.x, end.y, begin.z),
+                &textures.top,
+                textures.rotations.top,
+            );
+        }
 
         // 下面
-        self.add_face(
-            &Point3::new(end.x, begin.y, begin.z),
-            &Point3::new(end.x, begin.y, end.z),
-            &Point3::new(begin.x, begin.y, end.z),
-            &begin,
-            &textures.bottom,
-        );
+        if faces.bottom {
+            add_face(
+                self,
+                &Point3::new(end.x, begin.y, begin.z),
+                &Point3::new(end.x, begin.y, end.z),
+                &Point3::new(begin.x, begin.y, end.z),
+                &begin,
+                &textures.bottom,
+                textures.rotations.bottom,
+            );
+        }
 
         // 南
-        self.add_face(
-            &Point3::new(begin.x, end.y, begin.z),
-            &Point3::new(begin.x, begin.y, begin.z),
-            &Point3::new(begin.x, begin.y, end.z),
-            &Point3::new(begin.x, end.y, end.z),
-            &textures.south,
-        );
+        if faces.south {
+            add_face(
+                self,
+                &Point3::new(begin.x, end.y, begin.z),
+                &Point3::new(begin.x, begin.y, begin.z),
+                &Point3::new(begin.x, begin.y, end.z),
+                &Point3::new(begin.x, end.y, end.z),
+                &textures.south,
+                textures.rotations.south,
+            );
+        }
 
         // 北
-        self.add_face(
-            &Point3::new(end.x, end.y, end.z),
-            &Point3::new(end.x, begin.y, end.z),
-            &Point3::new(end.x, begin.y, begin.z),
-            &Point3::new(end.x, end.y, begin.z),
-            &textures.north,
-        );
+        if faces.north {
+            add_face(
+                self,
+                &Point3::new(end.x, end.y, end.z),
+                &Point3::new(end.x, begin.y, end.z),
+                &Point3::new(end.x, begin.y, begin.z),
+                &Point3::new(end.x, end.y, begin.z),
+                &textures.north,
+                textures.rotations.north,
+            );
+        }
 
         // 西
-        self.add_face(
-            &Point3::new(end.x, end.y, begin.z),
-            &Point3::new(end.x, begin.y, begin.z),
-            &Point3::new(begin.x, begin.y, begin.z),
-            &Point3::new(begin.x, end.y, begin.z),
-            &textures.west,
-        );
+        if faces.west {
+            add_face(
+                self,
+                &Point3::new(end.x, end.y, begin.z),
+                &Point3::new(end.x, begin.y, begin.z),
+                &Point3::new(begin.x, begin.y, begin.z),
+                &Point3::new(begin.x, end.y, begin.z),
+                &textures.west,
+                textures.rotations.west,
+            );
+        }
 
         // 東
-        self.add_face(
-            &Point3::new(begin.x, end.y, end.z),
-            &Point3::new(begin.x, begin.y, end.z),
-            &Point3::new(end.x, begin.y, end.z),
-            &Point3::new(end.x, end.y, end.z),
-            &textures.east,
-        );
+        if faces.east {
+            add_face(
+                self,
+                &Point3::new(begin.x, end.y, end.z),
+                &Point3::new(begin.x, begin.y, end.z),
+                &Point3::new(end.x, begin.y, end.z),
+                &Point3::new(end.x, end.y, end.z),
+                &textures.east,
+                textures.rotations.east,
+            );
+        }
     }
 
     /// 各辺が軸に並行な長方形を追加する
     /// 
     /// `p1`: 左上, `p2`: 左下, `p3`: 右下, `p4`: 右上
     pub fn add_face(&mut self, p1: &Point3, p2: &Point3, p3: &Point3, p4: &Point3, uv: &TextureUV) {
-        let normal = (p3 - p1).cross(&(p2 - p4)).normalize();
-        #[rustfmt::skip]
-        let mut v: Vec<f32> = vec![
-            p1.x, p1.y, p1.z, normal.x, normal.y, normal.z, uv.begin_u, uv.end_v,/* UVはtodo */
-            p2.x, p2.y, p2.z, normal.x, normal.y, normal.z, uv.begin_u, uv.begin_v,
-            p3.x, p3.y, p3.z, normal.x, normal.y, normal.z, uv.end_u, uv.begin_v,
-
-            p1.x, p1.y, p1.z, normal.x, normal.y, normal.z, uv.begin_u, uv.end_v,
-            p3.x, p3.y, p3.z, normal.x, normal.y, normal.z, uv.end_u, uv.begin_v,
-            p4.x, p4.y, p4.z, normal.x, normal.y, normal.z, uv.end_u, uv.end_v,
-        ];
+        self.push_face(p1, p2, p3, p4, uv, 0, 1.0, 1.0, &[VertexLight::FULL; 4]);
+    }
+
+    /// `add_face`と同じだが、頂点ごとの明るさを指定する
+    pub fn add_face_with_light(
+        &mut self,
+        p1: &Point3,
+        p2: &Point3,
+        p3: &Point3,
+        p4: &Point3,
+        uv: &TextureUV,
+        light: &[VertexLight; 4],
+    ) {
+        self.push_face(p1, p2, p3, p4, uv, 0, 1.0, 1.0, light);
+    }
+
+    /// `add_face`と同じだが、テクスチャを引き伸ばさずに長さ1ごとに繰り返す
+    /// 
+    /// 繰り返しはシェーダーで行う。テクスチャアトラス上の範囲は頂点属性`tile`で渡す
+    pub fn add_tiled_face(&mut self, p1: &Point3, p2: &Point3, p3: &Point3, p4: &Point3, uv: &TextureUV) {
+        let repeat_u = (p3 - p2).norm();
+        let repeat_v = (p1 - p2).norm();
+        self.push_face(p1, p2, p3, p4, uv, 0, repeat_u, repeat_v, &[VertexLight::FULL; 4]);
+    }
 
-        self.vertex_num += 6;
+    /// `rotation`はテクスチャを反時計回りに90°ずつ回転させる回数(see: `CuboidRotations`)
+    #[allow(clippy::too_many_arguments)]
+    fn push_face(
+        &mut self,
+        p1: &Point3,
+        p2: &Point3,
+        p3: &Point3,
+        p4: &Point3,
+        uv: &TextureUV,
+        rotation: u8,
+        repeat_u: f32,
+        repeat_v: f32,
+        light: &[VertexLight; 4],
+    ) {
+        let normal = face_normal(p1, p2, p3, p4);
+        let tile = [uv.begin_u, uv.begin_v, uv.end_u, uv.end_v];
+        // 面の上の位置(u, v)に来るテクスチャ上の位置
+        let texcoord = |u: f32, v: f32| {
+            let (s, t) = match rotation % 4 {
+                0 => (u, v),
+                1 => (v, repeat_u - u),
+                2 => (repeat_u - u, repeat_v - v),
+                _ => (repeat_v - v, u),
+            };
+            (uv.begin_u + (uv.end_u - uv.begin_u) * s, uv.begin_v + (uv.end_v - uv.begin_v) * t)
+        };
+        let vertices = [
+            (p1, texcoord(0.0, repeat_v)),
+            (p2, texcoord(0.0, 0.0)),
+            (p3, texcoord(repeat_u, 0.0)),
+            (p4, texcoord(repeat_u, repeat_v)),
+        ];
+        for ((p, (u, v)), light) in vertices.iter().zip(light) {
+            self.buffer.extend_from_slice(&[p.x, p.y, p.z, normal.x, normal.y, normal.z, *u, *v]);
+            self.buffer.extend_from_slice(&tile);
+            self.buffer.extend_from_slice(&[light.ao, light.sky]);
+            self.buffer.extend_from_slice(&light.block);
+        }
 
-        self.buffer.append(&mut v);
+        let base = self.vertex_num as u32;
+        // 明るさは三角形の中で補間されるので、暗い頂点を結ぶ対角線で分けると向きによって模様が変わってしまう。
+        // 明るい方の頂点の組を結ぶ対角線で分ける
+        let brightness = light.map(|light| light.brightness());
+        if brightness[0] + brightness[2] >= brightness[1] + brightness[3] {
+            self.indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        } else {
+            self.indices.extend_from_slice(&[base, base + 1, base + 3, base + 1, base + 2, base + 3]);
+        }
+        self.vertex_num += 4;
     }
 
     /// 正八面体を追加する
@@ -121,7 +277,7 @@ impl<'a> VaoBuilder<'a> {
     /// `r`は中心から頂点までの距離
     pub fn add_octahedron(&mut self, center: &Point3, r: f32, uv: &TextureUV) {
         #[rustfmt::skip]
-        let mut v: Vec<f32> = vec![
+        let v: Vec<f32> = vec![
             center.x+r, center.y  , center.z  ,  1.0,  1.0,  1.0, uv.begin_u, uv.begin_v,
             center.x  , center.y+r, center.z  ,  1.0,  1.0,  1.0, uv.begin_u, uv.end_v,
             center.x  , center.y  , center.z+r,  1.0,  1.0,  1.0, uv.end_u, uv.end_v,
@@ -155,8 +311,29 @@ impl<'a> VaoBuilder<'a> {
             center.x  , center.y-r, center.z  , -1.0, -1.0, -1.0, uv.begin_u, uv.end_v,
         ];
 
+        let tile = [uv.begin_u, uv.begin_v, uv.end_u, uv.end_v];
+        for vertex in v.chunks(8) {
+            self.buffer.extend_from_slice(vertex);
+            self.buffer.extend_from_slice(&tile);
+            let light = VertexLight::FULL;
+            self.buffer.extend_from_slice(&[light.ao, light.sky]);
+            self.buffer.extend_from_slice(&light.block);
+        }
+        let base = self.vertex_num as u32;
+        self.indices.extend(base..base + 24);
         self.vertex_num += 24;
-        self.buffer.append(&mut v);
+    }
+
+    /// 追加した頂点の数
+    /// 
+    /// 長方形は4つの頂点と6つのインデックスになる
+    pub fn vertex_num(&self) -> i32 {
+        self.vertex_num
+    }
+
+    /// 頂点属性の並び(see: `VaoBuilder`)
+    pub fn layout() -> VertexLayout {
+        VertexLayout::new(vec![3, 3, 2, 4, 1, 4])
     }
 
     /// 描画に用いるプログラムを指定する
@@ -171,27 +348,54 @@ impl<'a> VaoBuilder<'a> {
         self.program = Some(program)
     }
 
+    /// OpenGLを使わずに`Mesh`を作る
+    pub fn build_mesh(self) -> Mesh {
+        Mesh {
+            vertices: self.buffer,
+            indices: self.indices,
+            layout: Self::layout(),
+        }
+    }
+
     /// `Vao`を作る
     /// 
     /// # Panics
     /// 
     /// 描画に用いるプログラムが指定されていないとき(see: `attatch_program`)
     pub fn build(self, gl: &Gl) -> Vao<'a> {
-        Vao::new(
-            gl.clone(),
-            (self.buffer.len() * mem::size_of::<GLfloat>()) as _,
-            self.buffer.as_ptr() as _,
-            gl::STATIC_DRAW,
-            3usize,
-            vec![gl::FLOAT, gl::FLOAT, gl::FLOAT],
-            vec![3, 3, 2],
-            ((3 + 3 + 2) * mem::size_of::<GLfloat>()) as _,
-            self.vertex_num,
-            self.program.unwrap(),
-        )
+        let program = self.program.unwrap();
+        Vao::from_mesh(gl, &self.build_mesh(), program)
     }
 }
 
+/// 頂点の明るさ
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct VertexLight {
+    /// 周りのブロックによる遮蔽(ambient occlusion)。1.0で遮られていない
+    pub ao: f32,
+    /// 空からの光の強さ(0.0〜1.0)
+    pub sky: f32,
+    /// 光るブロックからの(赤, 緑, 青)の光の強さ(0.0〜1.0)
+    pub block: [f32; 3],
+}
+
+impl VertexLight {
+    /// 遮られておらず、最も明るい
+    pub const FULL: VertexLight = VertexLight { ao: 1.0, sky: 1.0, block: [1.0; 3] };
+
+    /// 長方形を三角形に分ける対角線を選ぶための明るさの目安
+    fn brightness(&self) -> f32 {
+        self.ao + self.block.iter().fold(self.sky, |a, &b| a.max(b))
+    }
+}
+
+/// 頂点属性の法線
+/// 
+/// `p1`: 左上, `p2`: 左下, `p3`: 右下, `p4`: 右上
+fn face_normal(p1: &Point3, p2: &Point3, p3: &Point3, p4: &Point3) -> Vector3 {
+    (p3 - p1).cross(&(p2 - p4)).normalize()
+}
+
 /// 直方体の各面のテクスチャを指定するための構造体
 /// 
 /// OpenGLは同時に1つのテクスチャしかバインドできないので、
@@ -203,4 +407,116 @@ pub struct CuboidTextures<'a> {
     pub north: &'a TextureUV,
     pub west: &'a TextureUV,
     pub east: &'a TextureUV,
+    /// 各面のテクスチャの回転
+    pub rotations: CuboidRotations,
+}
+
+/// 直方体の各面のテクスチャを反時計回りに90°ずつ回転させる回数
+/// 
+/// 向きのあるブロックを回転させたときに、面と一緒にテクスチャも回すのに使う
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct CuboidRotations {
+    pub top: u8,
+    pub bottom: u8,
+    pub south: u8,
+    pub north: u8,
+    pub west: u8,
+    pub east: u8,
+}
+
+impl CuboidRotations {
+    /// どの面も回転させない
+    pub const NONE: CuboidRotations = CuboidRotations { top: 0, bottom: 0, south: 0, north: 0, west: 0, east: 0 };
+}
+
+/// 直方体のどの面を追加するかを指定するための構造体
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CuboidFaces {
+    pub top: bool,
+    pub bottom: bool,
+    pub south: bool,
+    pub north: bool,
+    pub west: bool,
+    pub east: bool,
+}
+
+impl CuboidFaces {
+    /// すべての面
+    pub const ALL: CuboidFaces = CuboidFaces {
+        top: true,
+        bottom: true,
+        south: true,
+        north: true,
+        west: true,
+        east: true,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quads_are_split_along_brighter_diagonal() {
+        let uv = TextureUV::of_atlas(0, 0, 16, 16, 256, 256);
+        let textures = CuboidTextures {
+            top: &uv,
+            bottom: &uv,
+            south: &uv,
+            north: &uv,
+            west: &uv,
+            east: &uv,
+            rotations: CuboidRotations::NONE,
+        };
+        let top = CuboidFaces { top: true, bottom: false, south: false, north: false, west: false, east: false };
+        let begin = Point3::new(0.0, 0.0, 0.0);
+        let end = Point3::new(1.0, 1.0, 1.0);
+
+        let mut builder = VaoBuilder::new();
+        builder.add_cuboid_faces(&begin, &end, &textures, &top);
+        // 原点の上の頂点だけを暗くする
+        builder.add_cuboid_faces_with_light(&begin, &end, &textures, &top, false, &|p, normal| {
+            assert_eq!(*normal, Vector3::new(0.0, 1.0, 0.0));
+            let ao = if p.x == 0.0 && p.z == 0.0 { 0.25 } else { 1.0 };
+            VertexLight { ao, sky: 0.5, block: [0.0, 0.25, 0.0] }
+        });
+        let mesh = builder.build_mesh();
+
+        assert_eq!(mesh.layout.stride(), 17);
+        assert_eq!(&mesh.vertex(0)[12..], &[1.0; 5]);
+        assert_eq!(&mesh.vertex(4)[12..], &[0.25, 0.5, 0.0, 0.25, 0.0]);
+        assert_eq!(&mesh.indices[..6], &[0, 1, 2, 0, 2, 3]);
+        // 暗い頂点は片方の三角形にしか含まれない
+        assert_eq!(&mesh.indices[6..], &[4, 5, 7, 5, 6, 7]);
+    }
+
+    #[test]
+    fn rotated_textures_turn_counterclockwise() {
+        let uv = TextureUV::of_atlas(0, 0, 16, 16, 256, 256);
+        let (u0, u1, v0, v1) = (uv.begin_u, uv.end_u, uv.begin_v, uv.end_v);
+        let uvs = |rotation: u8| {
+            let rotations = CuboidRotations { top: rotation, ..CuboidRotations::NONE };
+            let textures = CuboidTextures {
+                top: &uv,
+                bottom: &uv,
+                south: &uv,
+                north: &uv,
+                west: &uv,
+                east: &uv,
+                rotations,
+            };
+            let top = CuboidFaces { top: true, bottom: false, south: false, north: false, west: false, east: false };
+            let mut builder = VaoBuilder::new();
+            builder.add_cuboid_faces(&Point3::new(0.0, 0.0, 0.0), &Point3::new(1.0, 1.0, 1.0), &textures, &top);
+            let mesh = builder.build_mesh();
+            (0..4).map(|i| (mesh.vertex(i)[6], mesh.vertex(i)[7])).collect::<Vec<_>>()
+        };
+
+        // 左上, 左下, 右下, 右上
+        assert_eq!(uvs(0), [(u0, v1), (u0, v0), (u1, v0), (u1, v1)]);
+        // テクスチャの左上が面の左下に来る
+        assert_eq!(uvs(1), [(u1, v1), (u0, v1), (u0, v0), (u1, v0)]);
+        assert_eq!(uvs(2), [(u1, v0), (u1, v1), (u0, v1), (u0, v0)]);
+        assert_eq!(uvs(3), [(u0, v0), (u1, v0), (u1, v1), (u0, v1)]);
+    }
 }
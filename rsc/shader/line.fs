@@ -0,0 +1,9 @@
+#version 330 core
+
+/* 視線の先のブロックの枠の色 */
+uniform vec3 uLineColor;
+
+void main()
+{
+    gl_FragColor = vec4(uLineColor, 1.0);
+}
@@ -0,0 +1,12 @@
+#version 330 core
+
+layout (location = 0) in vec3 iPosition;
+
+uniform mat4 uModel;
+uniform mat4 uView;
+uniform mat4 uProjection;
+
+void main()
+{
+    gl_Position = uProjection * uView * uModel * vec4(iPosition, 1.0);
+}
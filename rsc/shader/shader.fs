@@ -16,15 +16,31 @@ in float Alpha;
 in vec3 FragPosition;
 in vec3 Normal;
 in vec2 TexCoords;
+/* テクスチャアトラス上の範囲(始点のUV, 終点のUV) */
+flat in vec4 Tile;
+/* 周りのブロックに遮られた頂点ほど暗い(ambient occlusion) */
+in float Ao;
+/* 空からの光と、ブロックからの(赤, 緑, 青)の光の強さ(0.0〜1.0) */
+in vec4 Light;
 
 uniform sampler2D uScreenTexture;
+/* 不透明度がこれより小さいところは描画しない(see: `ChunkRenderer::draw`) */
+uniform float uAlphaCutoff;
 uniform vec3 uViewPosition;
 uniform Material uMaterial;
 uniform Light uLight;
 
 void main()
 {
-    vec3 texRGB = texture(uScreenTexture, TexCoords).rgb;
+    /* 複数のブロックをまとめた面では、UV座標が範囲の外に出るので繰り返す */
+    vec2 tileSize = Tile.zw - Tile.xy;
+    vec2 uv = Tile.xy + mod(TexCoords - Tile.xy, tileSize);
+    vec4 texRGBA = texture(uScreenTexture, uv);
+    /* 草花などの透明な部分 */
+    if (texRGBA.a < uAlphaCutoff) {
+        discard;
+    }
+    vec3 texRGB = texRGBA.rgb;
 
     /* ambient*/
     vec3 ambient = uLight.ambient * texRGB;
@@ -39,8 +55,14 @@ void main()
     float specularScala = dot(viewDirection, reflectDirection);
     vec3 specular = uLight.specular * pow(max(specularScala, 0.0), uMaterial.shininess) * uMaterial.specular;
 
-    vec3 result = ambient + diffuse + specular;
+    /* 光の強さが1段階下がるごとに明るさを0.8倍にする */
+    vec4 brightness = pow(vec4(0.8), 15.0 * (1.0 - Light));
+    vec3 sun = (ambient + diffuse) * brightness.x;
+    /* ブロックからの光は色ごとに明るさが違う */
+    vec3 lamp = texRGB * brightness.yzw;
+
+    vec3 result = max(sun, lamp) * Ao + specular * brightness.x;
 
     // gl_FragColor = vec4(FragPosition, 1.0);
-    gl_FragColor = vec4(result, Alpha);
+    gl_FragColor = vec4(result, texRGBA.a * Alpha);
 }
@@ -3,16 +3,24 @@
 layout (location = 0) in vec3 iPosition;
 layout (location = 1) in vec3 iNormal;
 layout (location = 2) in vec2 iTexCoords;
+layout (location = 3) in vec4 iTile;
+layout (location = 4) in float iAo;
+layout (location = 5) in vec4 iLight;
 
 uniform mat4 uModel;
 uniform mat4 uView;
 uniform mat4 uProjection;
 uniform float uAlpha;
+/* 頂点の光の強さに掛ける(チャンクは常に1.0、落ちているアイテムはその場所の明るさ) */
+uniform vec4 uLightScale;
 
 out float Alpha;
 out vec3 FragPosition;
 out vec3 Normal;
 out vec2 TexCoords;
+flat out vec4 Tile;
+out float Ao;
+out vec4 Light;
 
 void main()
 {
@@ -20,5 +28,8 @@ void main()
     FragPosition = vec3(uModel * vec4(iPosition, 1.0));
     Normal = mat3(transpose(inverse(uModel))) * iNormal;
     TexCoords = iTexCoords;
+    Tile = iTile;
+    Ao = iAo;
+    Light = iLight * uLightScale;
     gl_Position = uProjection * uView * vec4(FragPosition, 1.0);
 }
@@ -0,0 +1,100 @@
+use nalgebra::Vector3;
+use parry3d::bounding_volume::AABB;
+
+use crate::mymath::BlockPosInWorld;
+
+pub use model::{BlockModel, ModelElement, RenderLayer};
+pub use registry::{BlockDefinition, BlockRegistry, FaceTextures, RegistryError};
+pub use state::{BlockState, Orientation, Property, PropertyKind};
+
+pub mod model;
+pub mod registry;
+pub mod state;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Side {
+    TOP,
+    BOTTOM,
+    NORTH,
+    SOUTH,
+    WEST,
+    EAST,
+}
+
+impl Side {
+    pub const ALL: [Side; 6] = [
+        Side::TOP,
+        Side::BOTTOM,
+        Side::NORTH,
+        Side::SOUTH,
+        Side::WEST,
+        Side::EAST,
+    ];
+
+    /// `Side::ALL`での位置
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// ブロックの種類。`BlockRegistry`で宣言されたID
+///
+/// 名前や性質は`BlockRegistry::get`で調べる
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Block(u16);
+
+impl Block {
+    pub const fn new(id: u16) -> Self {
+        Block(id)
+    }
+
+    pub fn id(&self) -> u16 {
+        self.0
+    }
+
+    /// すべてのプロパティが最初の値である状態
+    pub fn default_state(self) -> BlockState {
+        BlockState::new(self, 0)
+    }
+}
+
+pub fn get_block_aabbs(
+    block: &BlockDefinition,
+    state: BlockState,
+    pos: &BlockPosInWorld,
+) -> Vec<AABB> {
+    if !block.solid {
+        return Vec::new();
+    }
+    let origin = pos.cast::<f32>();
+    block
+        .boxes(state)
+        .into_iter()
+        .map(|(begin, end)| {
+            AABB::new(
+                (origin + Vector3::from(begin)) * 0.5,
+                (origin + Vector3::from(end)) * 0.5,
+            )
+        })
+        .collect()
+}
+
+/// `rsc/blocks.json`のブロックの一覧
+#[cfg(test)]
+pub(crate) fn test_registry() -> std::sync::Arc<BlockRegistry> {
+    static REGISTRY: std::sync::OnceLock<std::sync::Arc<BlockRegistry>> =
+        std::sync::OnceLock::new();
+    REGISTRY
+        .get_or_init(|| {
+            std::sync::Arc::new(
+                BlockRegistry::from_json(include_str!("../../rsc/blocks.json")).unwrap(),
+            )
+        })
+        .clone()
+}
+
+/// `rsc/blocks.json`で宣言された名前のブロック
+#[cfg(test)]
+pub(crate) fn test_block(name: &str) -> Block {
+    test_registry().by_name(name).unwrap()
+}
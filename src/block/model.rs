@@ -0,0 +1,156 @@
+//! ブロックの形
+//!
+//! `rsc/blocks.json`の`model`で宣言する。省略すると1ブロックの立方体になる。
+//!
+//! ```json
+//! "model": "cross"
+//! "model": { "elements": [
+//!   { "from": [6, 0, 6], "to": [10, 16, 10] },
+//!   { "from": [10, 6, 7], "to": [16, 9, 9], "when": { "north": "true" } }
+//! ] }
+//! ```
+//!
+//! `elements`の直方体はブロックを16分割した単位で、`axis=y`、`facing=north`、`half=bottom`の
+//! 向きで宣言する(see: `Orientation`)。`when`を指定した直方体は、プロパティの値が
+//! すべて一致するときだけ使われる。直方体は描画と当たり判定の両方に使う。
+
+use serde::Deserialize;
+
+use super::state::BlockState;
+use super::BlockDefinition;
+
+/// ブロックを描画する順番と方法
+///
+/// `rsc/blocks.json`の`render_layer`で`"opaque"`、`"cutout"`、`"translucent"`のいずれかを宣言する。
+/// 省略すると、`transparent`なブロックは`Cutout`、それ以外は`Opaque`になる
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderLayer {
+    /// 不透明なブロック。最初に描画する
+    Opaque,
+    /// 透明な部分と不透明な部分だけがあるブロック。草花など
+    ///
+    /// 透明な部分は描画しないので、順番を気にせずに描画できる
+    Cutout,
+    /// 半透明なブロック。ガラスなど
+    ///
+    /// 最後に、カメラから遠い面から順に重ねて描画する
+    Translucent,
+}
+
+impl RenderLayer {
+    /// 描画する順
+    pub const ALL: [RenderLayer; 3] = [
+        RenderLayer::Opaque,
+        RenderLayer::Cutout,
+        RenderLayer::Translucent,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// ブロックの形
+#[derive(Clone, PartialEq, Debug)]
+pub enum BlockModel {
+    /// 1ブロックの立方体
+    Cube,
+    /// 対角線上で交差する2枚の板。草花など。当たり判定は無い
+    Cross,
+    /// 直方体の組み合わせ
+    Elements(Vec<ModelElement>),
+}
+
+/// ブロックの形を構成する直方体
+#[derive(Clone, PartialEq, Debug)]
+pub struct ModelElement {
+    /// ブロック内の座標(0.0〜1.0)
+    pub from: [f32; 3],
+    pub to: [f32; 3],
+    /// この直方体が使われるプロパティの値
+    pub when: Vec<(String, String)>,
+}
+
+impl BlockDefinition {
+    /// 隣のブロックの接している面を完全に隠すかどうか
+    pub fn is_opaque_cube(&self) -> bool {
+        self.model == BlockModel::Cube && !self.transparent
+    }
+
+    /// `state`でのブロックの形を構成する直方体。ブロック内の座標(0.0〜1.0)で、向きに合わせて回転済み
+    pub fn boxes(&self, state: BlockState) -> Vec<([f32; 3], [f32; 3])> {
+        let orientation = self.orientation(state);
+        match &self.model {
+            BlockModel::Cube => vec![([0.0, 0.0, 0.0], [1.0, 1.0, 1.0])],
+            BlockModel::Cross => Vec::new(),
+            BlockModel::Elements(elements) => elements
+                .iter()
+                .filter(|element| {
+                    element
+                        .when
+                        .iter()
+                        .all(|(property, value)| self.value(state, property) == Some(value))
+                })
+                .map(|element| orientation.transform_box(element.from, element.to))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block::{test_block, test_registry};
+
+    #[test]
+    fn slab_boxes() {
+        let registry = test_registry();
+        let slab = registry.get(test_block("stone_slab"));
+        let bottom = slab.block.default_state();
+        assert_eq!(slab.boxes(bottom), vec![([0.0, 0.0, 0.0], [1.0, 0.5, 1.0])]);
+        let top = slab.with_value(bottom, "half", "top").unwrap();
+        assert_eq!(slab.boxes(top), vec![([0.0, 0.5, 0.0], [1.0, 1.0, 1.0])]);
+    }
+
+    #[test]
+    fn stairs_boxes() {
+        let registry = test_registry();
+        let stairs = registry.get(test_block("oak_stairs"));
+        let state = stairs
+            .with_value(stairs.block.default_state(), "facing", "south")
+            .unwrap();
+        assert_eq!(
+            stairs.boxes(state),
+            vec![
+                ([0.0, 0.0, 0.0], [1.0, 0.5, 1.0]),
+                ([0.0, 0.5, 0.0], [0.5, 1.0, 1.0])
+            ]
+        );
+    }
+
+    #[test]
+    fn fence_arms_follow_properties() {
+        let registry = test_registry();
+        let fence = registry.get(test_block("oak_fence"));
+        let post = fence.block.default_state();
+        assert_eq!(fence.boxes(post).len(), 1);
+        let state = fence.with_value(post, "north", "true").unwrap();
+        let state = fence.with_value(state, "west", "true").unwrap();
+        let boxes = fence.boxes(state);
+        assert_eq!(boxes.len(), 5);
+        assert!(boxes.iter().any(|(_, to)| to[0] == 1.0));
+        assert!(boxes.iter().any(|(from, _)| from[2] == 0.0));
+    }
+
+    #[test]
+    fn cross_and_cube() {
+        let registry = test_registry();
+        let flower = registry.get(test_block("poppy"));
+        assert!(flower.boxes(flower.block.default_state()).is_empty());
+        let stone = registry.get(test_block("stone"));
+        assert_eq!(
+            stone.boxes(stone.block.default_state()),
+            vec![([0.0, 0.0, 0.0], [1.0, 1.0, 1.0])]
+        );
+    }
+}
@@ -0,0 +1,1006 @@
+//! ブロックの一覧
+//!
+//! ブロックの種類は`rsc/blocks.json`で宣言する。再コンパイルせずにブロックを追加できる。
+//!
+//! ```json
+//! {
+//!   "atlas": {
+//!     "tile_size": 64,
+//!     "tiles": { "stone": [0, 3] }
+//!   },
+//!   "blocks": [
+//!     {
+//!       "id": 3,
+//!       "name": "stone",
+//!       "textures": { "all": "stone" },
+//!       "solid": true,
+//!       "transparent": false,
+//!       "hardness": 1.5,
+//!       "light_emission": 0,
+//!       "max_stack_size": 64
+//!     },
+//!     {
+//!       "id": 4,
+//!       "name": "oak_log",
+//!       "textures": { "top": "log_top", "bottom": "log_top", "side": "log_side" },
+//!       "properties": [{ "name": "axis", "type": "enum", "values": ["y", "x", "z"] }]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! `atlas.tiles`はテクスチャの名前と、テクスチャアトラスでの位置(行, 列)。
+//! `textures`は`all`、`side`(上下以外の4面)、`top`、`bottom`、`north`、`south`、`west`、`east`
+//! を組み合わせて指定する。個別の面の指定が優先される。
+//! `solid`、`transparent`、`hardness`、`light_emission`、`max_stack_size`、`render_layer`は省略できる。
+//! `light_emission`は白い光の強さか、色のついた光の(赤, 緑, 青)の強さ(例: `[15, 3, 3]`)。
+//! `hardness`は0以上の数。
+//!
+//! `properties`はブロックの状態(see: `block::state`)。`type`は`enum`(`values`を指定)、
+//! `bool`、`int`(`min`と`max`を指定)のいずれか。
+//!
+//! `model`はブロックの形、`render_layer`は描画の方法(see: `block::model`)。`connects`が`true`のブロックは、
+//! `north`、`south`、`west`、`east`の真偽値プロパティが隣のブロックとつながっているかを表し、
+//! ブロックを置いたときに更新される。
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::model::{BlockModel, ModelElement, RenderLayer};
+use super::state::{BlockState, Orientation, Property, PropertyKind};
+use super::{Block, Side};
+
+/// 光の強さの最大値
+pub const MAX_LIGHT_LEVEL: u8 = 15;
+
+/// ブロックの名前の最大のバイト数
+///
+/// バージョン1のチャンクの形式では名前の長さをu8で保存していたので、それに収まるようにする
+pub const MAX_NAME_LEN: usize = 255;
+
+/// `max_stack_size`を省略したときの、インベントリの1つのスロットに入る数
+pub const DEFAULT_MAX_STACK_SIZE: u32 = 64;
+
+/// 面ごとのテクスチャの名前
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FaceTextures {
+    pub top: String,
+    pub bottom: String,
+    pub north: String,
+    pub south: String,
+    pub west: String,
+    pub east: String,
+}
+
+impl FaceTextures {
+    pub fn get(&self, side: Side) -> &str {
+        match side {
+            Side::TOP => &self.top,
+            Side::BOTTOM => &self.bottom,
+            Side::NORTH => &self.north,
+            Side::SOUTH => &self.south,
+            Side::WEST => &self.west,
+            Side::EAST => &self.east,
+        }
+    }
+}
+
+/// ブロックの種類ごとの性質
+#[derive(Clone, Debug)]
+pub struct BlockDefinition {
+    pub block: Block,
+    /// セーブデータなどで使う名前
+    pub name: String,
+    pub textures: FaceTextures,
+    /// プレイヤーなどが通り抜けられないかどうか
+    pub solid: bool,
+    /// 奥にあるものが透けて見えるかどうか
+    pub transparent: bool,
+    /// 壊すのにかかる時間の目安(秒)
+    pub hardness: f32,
+    /// ブロックが出す光の(赤, 緑, 青)の強さ(0〜`MAX_LIGHT_LEVEL`)
+    pub light_emission: [u8; 3],
+    /// インベントリの1つのスロットに入る数
+    pub max_stack_size: u32,
+    pub properties: Vec<Property>,
+    pub model: BlockModel,
+    pub render_layer: RenderLayer,
+    /// 隣のブロックとつながるかどうか(フェンスなど)
+    pub connects: bool,
+}
+
+impl BlockDefinition {
+    /// プロパティの値の組み合わせの数
+    pub fn state_count(&self) -> u32 {
+        self.properties
+            .iter()
+            .map(|property| property.values().len() as u32)
+            .product()
+    }
+
+    /// このブロックのすべての状態
+    pub fn states(&self) -> impl Iterator<Item = BlockState> + '_ {
+        (0..self.state_count()).map(move |index| BlockState::new(self.block, index as u16))
+    }
+
+    /// `state`でのプロパティの値の番号。そのようなプロパティが無ければ`None`
+    pub fn value_index(&self, state: BlockState, property: &str) -> Option<usize> {
+        let mut stride = 1;
+        for p in &self.properties {
+            let len = p.values().len();
+            if p.name == property {
+                return Some(state.index() as usize / stride % len);
+            }
+            stride *= len;
+        }
+        None
+    }
+
+    /// `state`でのプロパティの値
+    pub fn value(&self, state: BlockState, property: &str) -> Option<&str> {
+        let p = self.properties.iter().find(|p| p.name == property)?;
+        let index = self.value_index(state, property)?;
+        Some(&p.values()[index])
+    }
+
+    pub fn bool_value(&self, state: BlockState, property: &str) -> Option<bool> {
+        self.value(state, property)?.parse().ok()
+    }
+
+    pub fn int_value(&self, state: BlockState, property: &str) -> Option<i32> {
+        self.value(state, property)?.parse().ok()
+    }
+
+    /// `state`のプロパティを`value`に変えた状態
+    ///
+    /// そのようなプロパティや値が無ければ`None`
+    pub fn with_value(&self, state: BlockState, property: &str, value: &str) -> Option<BlockState> {
+        let mut stride = 1;
+        for p in &self.properties {
+            let len = p.values().len();
+            if p.name == property {
+                let new = p.index_of(value)?;
+                let old = state.index() as usize / stride % len;
+                let index = state.index() as usize - old * stride + new * stride;
+                return Some(BlockState::new(self.block, index as u16));
+            }
+            stride *= len;
+        }
+        None
+    }
+
+    /// `name[property=value,...]`の形の文字列。プロパティが無ければ名前だけ
+    pub fn state_to_string(&self, state: BlockState) -> String {
+        if self.properties.is_empty() {
+            return self.name.clone();
+        }
+        let values: Vec<String> = self
+            .properties
+            .iter()
+            .map(|p| format!("{}={}", p.name, self.value(state, &p.name).unwrap()))
+            .collect();
+        format!("{}[{}]", self.name, values.join(","))
+    }
+
+    /// `state`でのブロックの向き
+    pub fn orientation(&self, state: BlockState) -> Orientation {
+        Orientation::from_values(
+            self.properties
+                .iter()
+                .map(|p| (p.name.as_str(), self.value(state, &p.name).unwrap())),
+        )
+    }
+}
+
+/// ブロックの一覧の読み込みに失敗した理由
+#[derive(Debug)]
+pub enum RegistryError {
+    Io(io::Error),
+    /// JSONとして正しくない
+    Parse(serde_json::Error),
+    /// 同じIDのブロックが複数ある
+    DuplicateId(u16),
+    /// 同じ名前のブロックが複数ある
+    DuplicateName(String),
+    /// 名前が`MAX_NAME_LEN`バイトより長い
+    NameTooLong(String),
+    /// どの面にもテクスチャが指定されていない
+    MissingTexture {
+        block: String,
+        side: &'static str,
+    },
+    /// `atlas.tiles`に無いテクスチャ
+    UnknownTexture {
+        block: String,
+        texture: String,
+    },
+    /// 光の強さが`MAX_LIGHT_LEVEL`より大きい
+    InvalidLightLevel {
+        block: String,
+        level: u8,
+    },
+    /// `max_stack_size`が0
+    InvalidStackSize(String),
+    /// `hardness`が負の数か、有限の数でない
+    InvalidHardness(String),
+    /// プロパティの宣言が正しくない
+    InvalidProperty {
+        block: String,
+        property: String,
+        reason: &'static str,
+    },
+    /// 形の宣言が正しくない
+    InvalidModel {
+        block: String,
+        reason: &'static str,
+    },
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::Io(err) => write!(f, "{}", err),
+            RegistryError::Parse(err) => write!(f, "invalid block list: {}", err),
+            RegistryError::DuplicateId(id) => write!(f, "block id {} is used more than once", id),
+            RegistryError::DuplicateName(name) => {
+                write!(f, "block name '{}' is used more than once", name)
+            }
+            RegistryError::NameTooLong(name) => write!(
+                f,
+                "block name '{}' is {} bytes long (max: {})",
+                name,
+                name.len(),
+                MAX_NAME_LEN
+            ),
+            RegistryError::MissingTexture { block, side } => {
+                write!(f, "block '{}' has no texture for {} side", block, side)
+            }
+            RegistryError::UnknownTexture { block, texture } => {
+                write!(f, "block '{}' uses unknown texture '{}'", block, texture)
+            }
+            RegistryError::InvalidLightLevel { block, level } => write!(
+                f,
+                "block '{}' emits light level {} (max: {})",
+                block, level, MAX_LIGHT_LEVEL
+            ),
+            RegistryError::InvalidStackSize(block) => {
+                write!(f, "max stack size of block '{}' must be at least 1", block)
+            }
+            RegistryError::InvalidHardness(block) => write!(
+                f,
+                "hardness of block '{}' must be a finite non-negative number",
+                block
+            ),
+            RegistryError::InvalidProperty {
+                block,
+                property,
+                reason,
+            } => write!(
+                f,
+                "property '{}' of block '{}' is invalid: {}",
+                property, block, reason
+            ),
+            RegistryError::InvalidModel { block, reason } => {
+                write!(f, "model of block '{}' is invalid: {}", block, reason)
+            }
+        }
+    }
+}
+
+impl Error for RegistryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RegistryError::Io(err) => Some(err),
+            RegistryError::Parse(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// ブロックのIDと性質の対応
+#[derive(Debug)]
+pub struct BlockRegistry {
+    definitions: Vec<BlockDefinition>,
+    /// IDから`definitions`の添字
+    indices: Vec<Option<usize>>,
+    names: HashMap<String, Block>,
+    tile_size: u32,
+    tiles: HashMap<String, (u32, u32)>,
+}
+
+impl BlockRegistry {
+    /// ファイルからブロックの一覧を読み込む
+    pub fn load(path: &Path) -> Result<Self, RegistryError> {
+        let text = std::fs::read_to_string(path).map_err(RegistryError::Io)?;
+        Self::from_json(&text)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, RegistryError> {
+        let file: RegistryFile = serde_json::from_str(text).map_err(RegistryError::Parse)?;
+
+        let mut registry = BlockRegistry {
+            definitions: Vec::with_capacity(file.blocks.len()),
+            indices: Vec::new(),
+            names: HashMap::new(),
+            tile_size: file.atlas.tile_size,
+            tiles: file.atlas.tiles,
+        };
+        for entry in file.blocks {
+            let block = Block::new(entry.id);
+            if registry
+                .indices
+                .get(entry.id as usize)
+                .copied()
+                .flatten()
+                .is_some()
+            {
+                return Err(RegistryError::DuplicateId(entry.id));
+            }
+            if registry.names.contains_key(&entry.name) {
+                return Err(RegistryError::DuplicateName(entry.name));
+            }
+            if entry.name.len() > MAX_NAME_LEN {
+                return Err(RegistryError::NameTooLong(entry.name));
+            }
+            let light_emission = entry.light_emission.resolve();
+            if let Some(&level) = light_emission
+                .iter()
+                .find(|&&level| level > MAX_LIGHT_LEVEL)
+            {
+                return Err(RegistryError::InvalidLightLevel {
+                    block: entry.name,
+                    level,
+                });
+            }
+            if entry.max_stack_size == 0 {
+                return Err(RegistryError::InvalidStackSize(entry.name));
+            }
+            // 負の数やNaNだと一瞬で壊れてしまう(see: `Mining::progress`)
+            if !entry.hardness.is_finite() || entry.hardness < 0.0 {
+                return Err(RegistryError::InvalidHardness(entry.name));
+            }
+            let properties = entry
+                .properties
+                .iter()
+                .map(|property| property.resolve(&entry.name))
+                .collect::<Result<Vec<_>, _>>()?;
+            validate_properties(&entry.name, &properties)?;
+            let model = match &entry.model {
+                Some(model) => model.resolve(&entry.name, &properties)?,
+                None => BlockModel::Cube,
+            };
+            let render_layer = match entry.render_layer {
+                Some(layer) => layer,
+                None if entry.transparent => RenderLayer::Cutout,
+                None => RenderLayer::Opaque,
+            };
+            // 不透明として扱うと、後ろにある面が隠れたことになってしまう
+            if render_layer != RenderLayer::Opaque && !entry.transparent {
+                return Err(RegistryError::InvalidModel {
+                    block: entry.name,
+                    reason:
+                        "only transparent blocks can be drawn in the cutout or translucent layer",
+                });
+            }
+            if entry.connects {
+                for side in &["north", "south", "west", "east"] {
+                    if !properties
+                        .iter()
+                        .any(|p| p.name == *side && p.kind == PropertyKind::Bool)
+                    {
+                        return Err(RegistryError::InvalidProperty {
+                            block: entry.name,
+                            property: side.to_string(),
+                            reason: "connecting blocks need a bool property for each side",
+                        });
+                    }
+                }
+            }
+            let textures = entry.textures.resolve(&entry.name)?;
+            for side in &Side::ALL {
+                let texture = textures.get(*side);
+                if !registry.tiles.contains_key(texture) {
+                    return Err(RegistryError::UnknownTexture {
+                        block: entry.name,
+                        texture: texture.to_string(),
+                    });
+                }
+            }
+
+            if registry.indices.len() <= entry.id as usize {
+                registry.indices.resize(entry.id as usize + 1, None);
+            }
+            registry.indices[entry.id as usize] = Some(registry.definitions.len());
+            registry.names.insert(entry.name.clone(), block);
+            registry.definitions.push(BlockDefinition {
+                block,
+                name: entry.name,
+                textures,
+                solid: entry.solid,
+                transparent: entry.transparent,
+                hardness: entry.hardness,
+                light_emission,
+                max_stack_size: entry.max_stack_size,
+                properties,
+                model,
+                render_layer,
+                connects: entry.connects,
+            });
+        }
+        Ok(registry)
+    }
+
+    /// ブロックの性質
+    ///
+    /// `Block`はこのレジストリから得たものでなければならない。知らないIDならpanicする
+    pub fn get(&self, block: Block) -> &BlockDefinition {
+        self.indices
+            .get(block.id() as usize)
+            .copied()
+            .flatten()
+            .map(|index| &self.definitions[index])
+            .unwrap_or_else(|| panic!("block id {} is not registered", block.id()))
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<Block> {
+        self.names.get(name).copied()
+    }
+
+    /// `BlockDefinition::state_to_string`の逆
+    ///
+    /// 省略されたプロパティはデフォルトの値になる。
+    /// プロパティの並び順やブロックのIDが変わっても同じ状態を指す
+    pub fn parse_state(&self, text: &str) -> Option<BlockState> {
+        let (name, values) = match text.find('[') {
+            Some(begin) => (&text[..begin], text[begin + 1..].strip_suffix(']')?),
+            None => (text, ""),
+        };
+        let definition = self.get(self.by_name(name)?);
+        let mut state = definition.block.default_state();
+        for pair in values.split(',').filter(|pair| !pair.is_empty()) {
+            let (property, value) = pair.split_once('=')?;
+            state = definition.with_value(state, property.trim(), value.trim())?;
+        }
+        Some(state)
+    }
+
+    /// 宣言された順のすべてのブロック
+    pub fn definitions(&self) -> impl Iterator<Item = &BlockDefinition> {
+        self.definitions.iter()
+    }
+
+    /// テクスチャアトラスの1マスの大きさ(ピクセル)
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    /// テクスチャの名前と、テクスチャアトラスでの位置(行, 列)
+    pub fn tiles(&self) -> impl Iterator<Item = (&str, (u32, u32))> {
+        self.tiles
+            .iter()
+            .map(|(name, position)| (name.as_str(), *position))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegistryFile {
+    atlas: AtlasEntry,
+    blocks: Vec<BlockEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AtlasEntry {
+    tile_size: u32,
+    tiles: HashMap<String, (u32, u32)>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockEntry {
+    id: u16,
+    name: String,
+    textures: TexturesEntry,
+    #[serde(default = "default_true")]
+    solid: bool,
+    #[serde(default)]
+    transparent: bool,
+    #[serde(default = "default_hardness")]
+    hardness: f32,
+    #[serde(default)]
+    light_emission: LightEntry,
+    #[serde(default = "default_max_stack_size")]
+    max_stack_size: u32,
+    #[serde(default)]
+    properties: Vec<PropertyEntry>,
+    model: Option<ModelEntry>,
+    render_layer: Option<RenderLayer>,
+    #[serde(default)]
+    connects: bool,
+}
+
+/// 白い光の強さか、(赤, 緑, 青)の強さ
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LightEntry {
+    White(u8),
+    Color([u8; 3]),
+}
+
+impl Default for LightEntry {
+    fn default() -> Self {
+        LightEntry::White(0)
+    }
+}
+
+impl LightEntry {
+    fn resolve(&self) -> [u8; 3] {
+        match *self {
+            LightEntry::White(level) => [level; 3],
+            LightEntry::Color(color) => color,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ModelEntry {
+    Named(String),
+    Elements(ElementsEntry),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ElementsEntry {
+    elements: Vec<ElementEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ElementEntry {
+    from: [f32; 3],
+    to: [f32; 3],
+    #[serde(default)]
+    when: BTreeMap<String, String>,
+}
+
+impl ModelEntry {
+    fn resolve(&self, block: &str, properties: &[Property]) -> Result<BlockModel, RegistryError> {
+        let invalid = |reason| RegistryError::InvalidModel {
+            block: block.to_string(),
+            reason,
+        };
+        let elements = match self {
+            ModelEntry::Named(name) => {
+                return match name.as_str() {
+                    "cube" => Ok(BlockModel::Cube),
+                    "cross" => Ok(BlockModel::Cross),
+                    _ => Err(invalid("unknown model")),
+                }
+            }
+            ModelEntry::Elements(entry) => &entry.elements,
+        };
+        if elements.is_empty() {
+            return Err(invalid("no elements"));
+        }
+        elements
+            .iter()
+            .map(|element| {
+                for i in 0..3 {
+                    if !(0.0 <= element.from[i]
+                        && element.from[i] < element.to[i]
+                        && element.to[i] <= 16.0)
+                    {
+                        return Err(invalid("elements must be non-empty boxes within 0 to 16"));
+                    }
+                }
+                for (property, value) in &element.when {
+                    let known = properties
+                        .iter()
+                        .find(|p| &p.name == property)
+                        .is_some_and(|p| p.index_of(value).is_some());
+                    if !known {
+                        return Err(invalid("condition uses unknown property or value"));
+                    }
+                }
+                Ok(ModelElement {
+                    from: element.from.map(|v| v / 16.0),
+                    to: element.to.map(|v| v / 16.0),
+                    when: element.when.clone().into_iter().collect(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(BlockModel::Elements)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum PropertyEntry {
+    Enum { name: String, values: Vec<String> },
+    Bool { name: String },
+    Int { name: String, min: i32, max: i32 },
+}
+
+impl PropertyEntry {
+    fn resolve(&self, block: &str) -> Result<Property, RegistryError> {
+        let invalid = |property: &str, reason| RegistryError::InvalidProperty {
+            block: block.to_string(),
+            property: property.to_string(),
+            reason,
+        };
+        match self {
+            PropertyEntry::Enum { name, values } => {
+                if values.is_empty() {
+                    return Err(invalid(name, "no values"));
+                }
+                for (i, value) in values.iter().enumerate() {
+                    if values[..i].contains(value) {
+                        return Err(invalid(name, "duplicate values"));
+                    }
+                    if !is_valid_identifier(value) {
+                        return Err(invalid(name, "values must be [a-z0-9_-]+"));
+                    }
+                }
+                Ok(Property::new_enum(name, values.clone()))
+            }
+            PropertyEntry::Bool { name } => Ok(Property::new_bool(name)),
+            PropertyEntry::Int { name, min, max } => {
+                if min > max {
+                    return Err(invalid(name, "min is greater than max"));
+                }
+                if (*max as i64 - *min as i64) >= u16::MAX as i64 {
+                    return Err(invalid(name, "too many values"));
+                }
+                Ok(Property::new_int(name, *min, *max))
+            }
+        }
+    }
+}
+
+fn validate_properties(block: &str, properties: &[Property]) -> Result<(), RegistryError> {
+    let invalid = |property: &str, reason| RegistryError::InvalidProperty {
+        block: block.to_string(),
+        property: property.to_string(),
+        reason,
+    };
+    let mut state_count: u64 = 1;
+    for (i, property) in properties.iter().enumerate() {
+        if !is_valid_identifier(&property.name) {
+            return Err(invalid(&property.name, "names must be [a-z0-9_-]+"));
+        }
+        if properties[..i].iter().any(|p| p.name == property.name) {
+            return Err(invalid(&property.name, "declared more than once"));
+        }
+        let allowed: &[&str] = match property.name.as_str() {
+            "axis" => &["x", "y", "z"],
+            "facing" => &["north", "south", "west", "east", "up", "down"],
+            "half" => &["bottom", "top"],
+            _ => &[],
+        };
+        if !allowed.is_empty() && property.values().iter().any(|v| !allowed.contains(&&**v)) {
+            return Err(invalid(&property.name, "unknown direction"));
+        }
+        state_count *= property.values().len() as u64;
+        if state_count > u16::MAX as u64 {
+            return Err(invalid(&property.name, "too many states"));
+        }
+    }
+    Ok(())
+}
+
+/// プロパティの名前や値に使える文字列か
+fn is_valid_identifier(text: &str) -> bool {
+    !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+fn default_max_stack_size() -> u32 {
+    DEFAULT_MAX_STACK_SIZE
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TexturesEntry {
+    all: Option<String>,
+    side: Option<String>,
+    top: Option<String>,
+    bottom: Option<String>,
+    north: Option<String>,
+    south: Option<String>,
+    west: Option<String>,
+    east: Option<String>,
+}
+
+impl TexturesEntry {
+    fn resolve(&self, block: &str) -> Result<FaceTextures, RegistryError> {
+        let pick = |face: &Option<String>, is_side: bool, side: &'static str| {
+            face.as_ref()
+                .or(if is_side { self.side.as_ref() } else { None })
+                .or(self.all.as_ref())
+                .cloned()
+                .ok_or_else(|| RegistryError::MissingTexture {
+                    block: block.to_string(),
+                    side,
+                })
+        };
+        Ok(FaceTextures {
+            top: pick(&self.top, false, "top")?,
+            bottom: pick(&self.bottom, false, "bottom")?,
+            north: pick(&self.north, true, "north")?,
+            south: pick(&self.south, true, "south")?,
+            west: pick(&self.west, true, "west")?,
+            east: pick(&self.east, true, "east")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_blocks(blocks: &str) -> Result<BlockRegistry, RegistryError> {
+        BlockRegistry::from_json(&format!(
+            r#"{{ "atlas": {{ "tile_size": 16, "tiles": {{ "a": [0, 0], "b": [0, 1] }} }},
+                 "blocks": [{}] }}"#,
+            blocks
+        ))
+    }
+
+    #[test]
+    fn builtin_blocks() {
+        let registry = super::super::test_registry();
+        let grass = registry.get(registry.by_name("grass_block").unwrap());
+        assert_eq!(grass.textures.get(Side::TOP), "grass_top");
+        assert_eq!(grass.textures.get(Side::WEST), "grass_side");
+        assert!(grass.solid && !grass.transparent);
+        assert_eq!(grass.max_stack_size, DEFAULT_MAX_STACK_SIZE);
+        let names: Vec<_> = registry.definitions().map(|d| d.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "grass_block",
+                "dirt",
+                "stone",
+                "oak_log",
+                "oak_planks",
+                "stone_slab",
+                "oak_stairs",
+                "oak_fence",
+                "poppy",
+                "torch",
+                "red_lamp",
+                "blue_crystal",
+                "glass"
+            ]
+        );
+        let poppy = registry.get(registry.by_name("poppy").unwrap());
+        assert_eq!(poppy.model, BlockModel::Cross);
+        assert!(!poppy.solid && poppy.transparent);
+        let torch = registry.get(registry.by_name("torch").unwrap());
+        assert_eq!(torch.light_emission, [14, 13, 10]);
+        let lamp = registry.get(registry.by_name("red_lamp").unwrap());
+        assert!(lamp.is_opaque_cube());
+        assert_eq!(lamp.light_emission, [15, 3, 3]);
+        assert_eq!(lamp.render_layer, RenderLayer::Opaque);
+        assert_eq!(poppy.render_layer, RenderLayer::Cutout);
+        let glass = registry.get(registry.by_name("glass").unwrap());
+        assert_eq!(glass.render_layer, RenderLayer::Translucent);
+        assert_eq!(glass.model, BlockModel::Cube);
+    }
+
+    #[test]
+    fn face_textures_and_defaults() {
+        let registry = with_blocks(
+            r#"{ "id": 7, "name": "log", "textures": { "all": "a", "side": "b", "east": "a" },
+                 "transparent": true, "light_emission": 15 }"#,
+        )
+        .unwrap();
+        let log = registry.get(Block::new(7));
+        assert_eq!(log.name, "log");
+        assert_eq!(log.textures.get(Side::TOP), "a");
+        assert_eq!(log.textures.get(Side::NORTH), "b");
+        assert_eq!(log.textures.get(Side::EAST), "a");
+        assert!(log.solid && log.transparent);
+        assert_eq!(log.hardness, 1.0);
+        assert_eq!(log.light_emission, [15; 3]);
+        assert_eq!(registry.by_name("log"), Some(Block::new(7)));
+        assert_eq!(registry.by_name("stone"), None);
+    }
+
+    #[test]
+    fn block_states() {
+        let registry = with_blocks(
+            r#"{ "id": 1, "name": "door", "textures": { "all": "a" }, "properties": [
+                { "name": "facing", "type": "enum", "values": ["north", "south", "west", "east"] },
+                { "name": "open", "type": "bool" },
+                { "name": "power", "type": "int", "min": 0, "max": 15 }
+            ] }"#,
+        )
+        .unwrap();
+        let door = registry.get(Block::new(1));
+        assert_eq!(door.state_count(), 4 * 2 * 16);
+        assert_eq!(door.states().count(), 128);
+
+        let default = Block::new(1).default_state();
+        assert_eq!(door.value(default, "facing"), Some("north"));
+        assert_eq!(door.bool_value(default, "open"), Some(false));
+        assert_eq!(door.int_value(default, "power"), Some(0));
+
+        let state = door.with_value(default, "open", "true").unwrap();
+        let state = door.with_value(state, "facing", "east").unwrap();
+        let state = door.with_value(state, "power", "7").unwrap();
+        assert_eq!(door.value(state, "facing"), Some("east"));
+        assert_eq!(door.bool_value(state, "open"), Some(true));
+        assert_eq!(door.int_value(state, "power"), Some(7));
+        assert_eq!(door.with_value(state, "facing", "up"), None);
+        assert_eq!(door.with_value(state, "color", "red"), None);
+
+        let text = door.state_to_string(state);
+        assert_eq!(text, "door[facing=east,open=true,power=7]");
+        assert_eq!(registry.parse_state(&text), Some(state));
+        assert_eq!(
+            registry.parse_state("door[power=7,open=true,facing=east]"),
+            Some(state)
+        );
+        assert_eq!(registry.parse_state("door"), Some(default));
+        assert_eq!(registry.parse_state("door[open=maybe]"), None);
+        assert_eq!(registry.parse_state("window"), None);
+
+        let all: std::collections::HashSet<_> =
+            door.states().map(|s| door.state_to_string(s)).collect();
+        assert_eq!(all.len(), 128);
+    }
+
+    #[test]
+    fn rejects_invalid_blocks() {
+        let block = |id: u16, name: &str, texture: &str| {
+            format!(
+                r#"{{ "id": {}, "name": "{}", "textures": {{ "all": "{}" }} }}"#,
+                id, name, texture
+            )
+        };
+        assert!(matches!(
+            with_blocks(&format!("{}, {}", block(1, "x", "a"), block(1, "y", "a"))),
+            Err(RegistryError::DuplicateId(1))
+        ));
+        assert!(matches!(
+            with_blocks(&format!("{}, {}", block(1, "x", "a"), block(2, "x", "a"))),
+            Err(RegistryError::DuplicateName(_))
+        ));
+        assert!(matches!(
+            with_blocks(&block(1, "x", "missing")),
+            Err(RegistryError::UnknownTexture { .. })
+        ));
+        assert!(matches!(
+            with_blocks(r#"{ "id": 1, "name": "x", "textures": { "top": "a" } }"#),
+            Err(RegistryError::MissingTexture { .. })
+        ));
+        assert!(matches!(
+            with_blocks(
+                r#"{ "id": 1, "name": "x", "textures": { "all": "a" }, "light_emission": 16 }"#
+            ),
+            Err(RegistryError::InvalidLightLevel { .. })
+        ));
+        assert!(matches!(
+            with_blocks(
+                r#"{ "id": 1, "name": "x", "textures": { "all": "a" }, "light_emission": [1, 2, 16] }"#
+            ),
+            Err(RegistryError::InvalidLightLevel { level: 16, .. })
+        ));
+        assert!(matches!(
+            with_blocks(
+                r#"{ "id": 1, "name": "x", "textures": { "all": "a" }, "max_stack_size": 0 }"#
+            ),
+            Err(RegistryError::InvalidStackSize(_))
+        ));
+        assert!(matches!(
+            with_blocks(
+                r#"{ "id": 1, "name": "x", "textures": { "all": "a" }, "hardness": -1.0 }"#
+            ),
+            Err(RegistryError::InvalidHardness(_))
+        ));
+        assert!(with_blocks(
+            r#"{ "id": 1, "name": "x", "textures": { "all": "a" }, "hardness": 0.0 }"#
+        )
+        .is_ok());
+        assert!(with_blocks(&block(1, &"x".repeat(MAX_NAME_LEN), "a")).is_ok());
+        assert!(matches!(
+            with_blocks(&block(1, &"x".repeat(MAX_NAME_LEN + 1), "a")),
+            Err(RegistryError::NameTooLong(_))
+        ));
+        assert!(matches!(
+            with_blocks(r#"{ "id": 1, "name": "x", "textures": { "all": "a" }, "colour": 1 }"#),
+            Err(RegistryError::Parse(_))
+        ));
+        let property = |json: &str| {
+            with_blocks(&format!(
+                r#"{{ "id": 1, "name": "x", "textures": {{ "all": "a" }}, "properties": [{}] }}"#,
+                json
+            ))
+        };
+        for json in &[
+            r#"{ "name": "axis", "type": "enum", "values": ["x", "up"] }"#,
+            r#"{ "name": "color", "type": "enum", "values": [] }"#,
+            r#"{ "name": "color", "type": "enum", "values": ["red", "red"] }"#,
+            r#"{ "name": "level", "type": "int", "min": 3, "max": 2 }"#,
+            r#"{ "name": "a", "type": "bool" }, { "name": "a", "type": "bool" }"#,
+            r#"{ "name": "a", "type": "int", "min": 0, "max": 255 },
+               { "name": "b", "type": "int", "min": 0, "max": 255 }"#,
+        ] {
+            assert!(
+                matches!(property(json), Err(RegistryError::InvalidProperty { .. })),
+                "{}",
+                json
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_models() {
+        let model = |json: &str| {
+            with_blocks(&format!(
+                r#"{{ "id": 1, "name": "x", "textures": {{ "all": "a" }},
+                     "properties": [{{ "name": "open", "type": "bool" }}], "model": {} }}"#,
+                json
+            ))
+        };
+        assert!(matches!(
+            model(r#""cross""#).unwrap().get(Block::new(1)).model,
+            BlockModel::Cross
+        ));
+        assert!(model(
+            r#"{ "elements": [{ "from": [0, 0, 0], "to": [16, 8, 16],
+                                           "when": { "open": "true" } }] }"#
+        )
+        .is_ok());
+        for json in &[
+            r#""sphere""#,
+            r#"{ "elements": [] }"#,
+            r#"{ "elements": [{ "from": [0, 8, 0], "to": [16, 8, 16] }] }"#,
+            r#"{ "elements": [{ "from": [0, 0, 0], "to": [16, 17, 16] }] }"#,
+            r#"{ "elements": [{ "from": [0, 0, 0], "to": [1, 1, 1], "when": { "lit": "true" } }] }"#,
+            r#"{ "elements": [{ "from": [0, 0, 0], "to": [1, 1, 1], "when": { "open": "yes" } }] }"#,
+        ] {
+            assert!(
+                matches!(model(json), Err(RegistryError::InvalidModel { .. })),
+                "{}",
+                json
+            );
+        }
+        assert!(matches!(
+            with_blocks(
+                r#"{ "id": 1, "name": "x", "textures": { "all": "a" }, "connects": true }"#
+            ),
+            Err(RegistryError::InvalidProperty { .. })
+        ));
+        assert!(matches!(
+            with_blocks(
+                r#"{ "id": 1, "name": "x", "textures": { "all": "a" }, "render_layer": "translucent" }"#
+            ),
+            Err(RegistryError::InvalidModel { .. })
+        ));
+        assert!(matches!(
+            with_blocks(
+                r#"{ "id": 1, "name": "x", "textures": { "all": "a" }, "render_layer": "glass" }"#
+            ),
+            Err(RegistryError::Parse(_))
+        ));
+    }
+}
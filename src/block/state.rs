@@ -0,0 +1,324 @@
+//! ブロックの状態
+//!
+//! ブロックの種類ごとにプロパティ(列挙、真偽値、整数)を宣言できる。
+//! プロパティの値の組み合わせには番号(`BlockState::index`)が振られ、
+//! すべてのプロパティが最初の値である状態が0番になる。
+//!
+//! `axis`、`facing`、`half`という名前のプロパティはブロックの向きを表し、
+//! テクスチャや当たり判定を回転させる(see: `Orientation`)。
+
+use super::{Block, Side};
+
+/// ブロックの種類と、そのプロパティの値の組
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BlockState {
+    block: Block,
+    index: u16,
+}
+
+impl BlockState {
+    pub const fn new(block: Block, index: u16) -> Self {
+        Self { block, index }
+    }
+
+    pub fn block(&self) -> Block {
+        self.block
+    }
+
+    /// プロパティの値の組み合わせの番号
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+}
+
+/// プロパティの種類
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PropertyKind {
+    Enum,
+    Bool,
+    Int { min: i32, max: i32 },
+}
+
+/// ブロックの種類ごとに宣言されたプロパティ
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Property {
+    pub name: String,
+    pub kind: PropertyKind,
+    /// 取りうる値を文字列にしたもの。最初の値がデフォルト
+    values: Vec<String>,
+}
+
+impl Property {
+    pub fn new_enum(name: &str, values: Vec<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            kind: PropertyKind::Enum,
+            values,
+        }
+    }
+
+    pub fn new_bool(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            kind: PropertyKind::Bool,
+            values: vec!["false".to_string(), "true".to_string()],
+        }
+    }
+
+    pub fn new_int(name: &str, min: i32, max: i32) -> Self {
+        Self {
+            name: name.to_string(),
+            kind: PropertyKind::Int { min, max },
+            values: (min..=max).map(|value| value.to_string()).collect(),
+        }
+    }
+
+    pub fn values(&self) -> &[String] {
+        &self.values
+    }
+
+    pub fn index_of(&self, value: &str) -> Option<usize> {
+        self.values.iter().position(|v| v == value)
+    }
+}
+
+/// ブロックの向き
+///
+/// テクスチャや当たり判定は`axis=y`、`facing=north`、`half=bottom`の向きで宣言し、
+/// 状態に合わせてブロックの中心を軸に回転・反転させる
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Orientation {
+    matrix: [[i32; 3]; 3],
+}
+
+impl Orientation {
+    pub const IDENTITY: Orientation = Orientation {
+        matrix: [[1, 0, 0], [0, 1, 0], [0, 0, 1]],
+    };
+
+    /// `half=top`の上下の反転
+    const FLIP: Orientation = Orientation {
+        matrix: [[1, 0, 0], [0, -1, 0], [0, 0, 1]],
+    };
+
+    /// プロパティの値から向きを求める。向きを表すプロパティが無ければ`IDENTITY`
+    pub fn from_values<'a>(values: impl Iterator<Item = (&'a str, &'a str)>) -> Self {
+        let mut rotation = Orientation::IDENTITY;
+        let mut flip = Orientation::IDENTITY;
+        for (name, value) in values {
+            match (name, value) {
+                ("axis", "x") => rotation = Orientation::new([[0, 1, 0], [-1, 0, 0], [0, 0, 1]]),
+                ("axis", "z") => rotation = Orientation::new([[1, 0, 0], [0, 0, -1], [0, 1, 0]]),
+                ("facing", "south") => {
+                    rotation = Orientation::new([[-1, 0, 0], [0, 1, 0], [0, 0, -1]])
+                }
+                ("facing", "west") => {
+                    rotation = Orientation::new([[0, 0, 1], [0, 1, 0], [-1, 0, 0]])
+                }
+                ("facing", "east") => {
+                    rotation = Orientation::new([[0, 0, -1], [0, 1, 0], [1, 0, 0]])
+                }
+                ("facing", "up") => rotation = Orientation::new([[0, -1, 0], [1, 0, 0], [0, 0, 1]]),
+                ("facing", "down") => {
+                    rotation = Orientation::new([[0, 1, 0], [-1, 0, 0], [0, 0, 1]])
+                }
+                ("half", "top") => flip = Orientation::FLIP,
+                _ => {}
+            }
+        }
+        rotation.then_after(&flip)
+    }
+
+    const fn new(matrix: [[i32; 3]; 3]) -> Self {
+        Self { matrix }
+    }
+
+    /// `other`を適用した後に`self`を適用する向き
+    fn then_after(&self, other: &Orientation) -> Orientation {
+        let mut matrix = [[0; 3]; 3];
+        for (r, row) in matrix.iter_mut().enumerate() {
+            for (c, cell) in row.iter_mut().enumerate() {
+                *cell = (0..3).map(|k| self.matrix[r][k] * other.matrix[k][c]).sum();
+            }
+        }
+        Orientation { matrix }
+    }
+
+    fn apply(&self, v: [i32; 3]) -> [i32; 3] {
+        let m = &self.matrix;
+        [0, 1, 2].map(|r| m[r][0] * v[0] + m[r][1] * v[1] + m[r][2] * v[2])
+    }
+
+    /// 宣言したときの面`side`が向く面
+    pub fn rotate_side(&self, side: Side) -> Side {
+        Side::from_normal(self.apply(side.normal())).unwrap()
+    }
+
+    /// 面`side`に来る、宣言したときの面
+    pub fn source_side(&self, side: Side) -> Side {
+        // 回転と反転の行列は直交行列なので、逆行列は転置行列
+        let m = &self.matrix;
+        let transposed = Orientation::new([0, 1, 2].map(|r| [m[0][r], m[1][r], m[2][r]]));
+        transposed.rotate_side(side)
+    }
+
+    /// 面`side`に貼るテクスチャを反時計回りに90°ずつ回転させる回数
+    ///
+    /// 反転(`half=top`)ではテクスチャを裏返さずに、回転の分だけ回す
+    pub fn texture_rotation(&self, side: Side) -> u8 {
+        let m = &self.matrix;
+        let determinant = (0..3)
+            .map(|c| {
+                m[0][c]
+                    * (m[1][(c + 1) % 3] * m[2][(c + 2) % 3]
+                        - m[1][(c + 2) % 3] * m[2][(c + 1) % 3])
+            })
+            .sum::<i32>();
+        // 反転を含むときは、反転させる前の回転だけを使う
+        let rotation = if determinant < 0 {
+            self.then_after(&Orientation::FLIP)
+        } else {
+            *self
+        };
+        let (source_right, _) = rotation.source_side(side).texture_axes();
+        let right = rotation.apply(source_right);
+        let (target_right, target_up) = side.texture_axes();
+        let negate = |v: [i32; 3]| v.map(|x| -x);
+        if right == target_right {
+            0
+        } else if right == target_up {
+            1
+        } else if right == negate(target_right) {
+            2
+        } else {
+            3
+        }
+    }
+
+    /// ブロック内の座標(0.0〜1.0)で表した直方体を回転させる
+    pub fn transform_box(&self, begin: [f32; 3], end: [f32; 3]) -> ([f32; 3], [f32; 3]) {
+        let transform = |p: [f32; 3]| {
+            let m = &self.matrix;
+            let centered = [p[0] - 0.5, p[1] - 0.5, p[2] - 0.5];
+            [0, 1, 2].map(|r| (0..3).map(|c| m[r][c] as f32 * centered[c]).sum::<f32>() + 0.5)
+        };
+        let (a, b) = (transform(begin), transform(end));
+        (
+            [0, 1, 2].map(|i| a[i].min(b[i])),
+            [0, 1, 2].map(|i| a[i].max(b[i])),
+        )
+    }
+}
+
+impl Side {
+    /// 面の外向きの法線
+    ///
+    /// `VaoBuilder::add_cuboid`と同じく、西はzが小さい側、東はzが大きい側
+    pub fn normal(&self) -> [i32; 3] {
+        match self {
+            Side::TOP => [0, 1, 0],
+            Side::BOTTOM => [0, -1, 0],
+            Side::NORTH => [1, 0, 0],
+            Side::SOUTH => [-1, 0, 0],
+            Side::WEST => [0, 0, -1],
+            Side::EAST => [0, 0, 1],
+        }
+    }
+
+    /// 面に貼るテクスチャの右向きと上向き
+    ///
+    /// `VaoBuilder::add_cuboid`でのUV座標の向き
+    pub fn texture_axes(&self) -> ([i32; 3], [i32; 3]) {
+        match self {
+            Side::TOP => ([1, 0, 0], [0, 0, -1]),
+            Side::BOTTOM => ([-1, 0, 0], [0, 0, -1]),
+            Side::NORTH => ([0, 0, -1], [0, 1, 0]),
+            Side::SOUTH => ([0, 0, 1], [0, 1, 0]),
+            Side::WEST => ([-1, 0, 0], [0, 1, 0]),
+            Side::EAST => ([1, 0, 0], [0, 1, 0]),
+        }
+    }
+
+    pub fn from_normal(normal: [i32; 3]) -> Option<Side> {
+        Side::ALL
+            .iter()
+            .copied()
+            .find(|side| side.normal() == normal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn orientation(name: &str, value: &str) -> Orientation {
+        Orientation::from_values(std::iter::once((name, value)))
+    }
+
+    #[test]
+    fn facing_rotates_front() {
+        for &(value, side) in &[
+            ("north", Side::NORTH),
+            ("south", Side::SOUTH),
+            ("west", Side::WEST),
+            ("east", Side::EAST),
+            ("up", Side::TOP),
+            ("down", Side::BOTTOM),
+        ] {
+            let orientation = orientation("facing", value);
+            assert_eq!(orientation.rotate_side(Side::NORTH), side, "{}", value);
+            assert_eq!(orientation.source_side(side), Side::NORTH, "{}", value);
+        }
+        let west = orientation("facing", "west");
+        assert_eq!(west.rotate_side(Side::TOP), Side::TOP);
+    }
+
+    #[test]
+    fn axis_rotates_top() {
+        assert_eq!(orientation("axis", "y"), Orientation::IDENTITY);
+        assert_eq!(orientation("axis", "x").rotate_side(Side::TOP), Side::NORTH);
+        assert_eq!(
+            orientation("axis", "x").rotate_side(Side::BOTTOM),
+            Side::SOUTH
+        );
+        assert_eq!(orientation("axis", "z").rotate_side(Side::TOP), Side::EAST);
+    }
+
+    #[test]
+    fn half_flips_boxes() {
+        let top = orientation("half", "top");
+        assert_eq!(
+            top.transform_box([0.0, 0.0, 0.0], [1.0, 0.5, 1.0]),
+            ([0.0, 0.5, 0.0], [1.0, 1.0, 1.0])
+        );
+        let facing_east_top =
+            Orientation::from_values(vec![("facing", "east"), ("half", "top")].into_iter());
+        assert_eq!(
+            facing_east_top.transform_box([0.5, 0.0, 0.0], [1.0, 0.5, 1.0]),
+            ([0.0, 0.5, 0.5], [1.0, 1.0, 1.0])
+        );
+    }
+
+    #[test]
+    fn textures_turn_with_faces() {
+        let all =
+            |orientation: Orientation| Side::ALL.map(|side| orientation.texture_rotation(side));
+        assert_eq!(all(Orientation::IDENTITY), [0; 6]);
+        assert_eq!(all(orientation("half", "top")), [0; 6]);
+        // 横倒しの丸太の側面の模様は、倒した向きに伸びる
+        let x = orientation("axis", "x");
+        assert_eq!(x.texture_rotation(Side::TOP), 3);
+        assert_eq!(x.texture_rotation(Side::WEST), 1);
+        assert_eq!(x.texture_rotation(Side::EAST), 3);
+        assert_eq!(orientation("axis", "z").texture_rotation(Side::TOP), 2);
+        assert_eq!(orientation("axis", "z").texture_rotation(Side::NORTH), 1);
+        // 水平に向きを変えても、側面のテクスチャは上を向いたまま
+        let west = orientation("facing", "west");
+        assert_eq!(west.texture_rotation(Side::NORTH), 0);
+        assert_eq!(west.texture_rotation(Side::WEST), 0);
+        assert_eq!(west.texture_rotation(Side::TOP), 1);
+        let west_top =
+            Orientation::from_values(vec![("facing", "west"), ("half", "top")].into_iter());
+        assert_eq!(all(west_top), all(west));
+    }
+}
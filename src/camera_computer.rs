@@ -1,4 +1,4 @@
-use nalgebra::Matrix4;
+use nalgebra::{Matrix4, Point3};
 
 use crate::components::{Angle2, Position};
 
@@ -11,9 +11,14 @@ impl CameraComputer {
 
     const PLAYER_EYE_DIFF: nalgebra::Vector3<f32> = nalgebra::Vector3::new(0.0, 0.3, 0.0);
 
+    /// プレイヤーが`pos`にいるときの目の位置
+    pub fn eye_position(pos: &Position) -> Point3<f32> {
+        pos.0 + CameraComputer::PLAYER_EYE_DIFF
+    }
+
     pub fn compute_view_matrix(&self, angle: &Angle2, pos: &Position) -> Matrix4<f32> {
         Matrix4::<f32>::look_at_rh(
-            &(pos.0 + CameraComputer::PLAYER_EYE_DIFF),
+            &CameraComputer::eye_position(pos),
             &(pos.0 + angle.front()),
             &angle.up(),
         )
@@ -0,0 +1,488 @@
+//! チャンクのバイナリ形式
+//!
+//! 数値はすべてリトルエンディアン。
+//!
+//! ```text
+//! magic        [u8; 4]  "RCCK"
+//! version      u16      FORMAT_VERSION
+//! position     i32 × 3  ChunkPosのx, y, z
+//! palette_len  u16      パレットの要素数
+//! palette      palette_len個の (name_len: u16, name: [u8; name_len])
+//!                       ブロックの状態を表す文字列(UTF-8)。`name[property=value,...]`の形。
+//!                       ブロックやプロパティの並び順が変わっても読めるように名前で保存する。
+//!                       バージョン1ではname_lenがu8で、プロパティを持たないブロックの名前だけ
+//! blocks       u16 × 16^3
+//!                       BlockPosInChunk::index()の順に並んだパレットのインデックス。
+//!                       AIR_INDEXは空気
+//! section_count u16     追加のセクションの数
+//! sections     section_count個の (tag: [u8; 4], len: u32, payload: [u8; len])
+//!                       ブロックごとのメタデータなど、後から追加されるデータ。
+//!                       知らないタグのセクションは読み飛ばす
+//! ```
+//!
+//! セクション:
+//!
+//! ```text
+//! LGHT         u16 × 16^3  Chunk::lightと同じ形の光の強さ。BlockPosInChunk::index()の順
+//!                          チャンク全体で同じ強さならu16 1つだけ。
+//!                          このセクションが無いチャンクは、読み込んだときに光を計算する
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::block::{BlockRegistry, BlockState};
+use crate::mymath::{BlockPosInChunk, ChunkPos};
+
+use super::Chunk;
+
+const MAGIC: [u8; 4] = *b"RCCK";
+
+/// 光の強さのセクションのタグ
+const LIGHT_TAG: [u8; 4] = *b"LGHT";
+
+/// 書き出すときのバージョン。これより新しいバージョンは読めない
+pub const FORMAT_VERSION: u16 = 2;
+
+/// 空気を表すパレットのインデックス
+const AIR_INDEX: u16 = u16::MAX;
+
+/// チャンクの読み込みに失敗した理由
+#[derive(Debug)]
+pub enum ChunkFormatError {
+    /// データが途中で終わっている
+    Truncated,
+    /// チャンクのデータではない
+    BadMagic([u8; 4]),
+    /// このバージョンのゲームでは読めない新しい形式
+    UnsupportedVersion(u16),
+    /// 知らない名前のブロック
+    UnknownBlock(String),
+    /// パレットの範囲外のインデックス
+    InvalidPaletteIndex(u16),
+    /// ブロックの状態を表す文字列がUTF-8ではない
+    InvalidName,
+    /// 知っているタグのセクションの長さが正しくない
+    InvalidSection([u8; 4]),
+    /// 読み書き中のI/Oエラー
+    Io(io::Error),
+}
+
+impl fmt::Display for ChunkFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkFormatError::Truncated => write!(f, "chunk data is truncated"),
+            ChunkFormatError::BadMagic(magic) => write!(f, "not a chunk data (magic: {:?})", magic),
+            ChunkFormatError::UnsupportedVersion(version) => write!(
+                f,
+                "chunk format version {} is newer than supported version {}",
+                version, FORMAT_VERSION
+            ),
+            ChunkFormatError::UnknownBlock(name) => write!(f, "unknown block state '{}'", name),
+            ChunkFormatError::InvalidPaletteIndex(index) => {
+                write!(f, "palette index {} is out of range", index)
+            }
+            ChunkFormatError::InvalidName => write!(f, "block name is not valid UTF-8"),
+            ChunkFormatError::InvalidSection(tag) => write!(
+                f,
+                "section '{}' has invalid length",
+                String::from_utf8_lossy(tag)
+            ),
+            ChunkFormatError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for ChunkFormatError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ChunkFormatError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ChunkFormatError {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            ChunkFormatError::Truncated
+        } else {
+            ChunkFormatError::Io(err)
+        }
+    }
+}
+
+impl From<ChunkFormatError> for io::Error {
+    fn from(err: ChunkFormatError) -> Self {
+        match err {
+            ChunkFormatError::Io(err) => err,
+            ChunkFormatError::Truncated => io::Error::new(io::ErrorKind::UnexpectedEof, err),
+            _ => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+impl Chunk {
+    /// チャンクをバイナリ形式で書き出す
+    pub fn write_to<W: Write>(&self, registry: &BlockRegistry, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        for coord in &[self.position.x, self.position.y, self.position.z] {
+            writer.write_all(&coord.to_le_bytes())?;
+        }
+
+        let mut palette: Vec<BlockState> = Vec::new();
+        let mut indices: Vec<u16> = Vec::with_capacity(16 * 16 * 16);
+        for block in self.blocks.iter() {
+            let index = match block {
+                None => AIR_INDEX,
+                Some(block) => match palette.iter().position(|b| *b == block) {
+                    Some(index) => index as u16,
+                    None => {
+                        palette.push(block);
+                        (palette.len() - 1) as u16
+                    }
+                },
+            };
+            indices.push(index);
+        }
+
+        writer.write_all(&(palette.len() as u16).to_le_bytes())?;
+        for block in &palette {
+            let name = registry.get(block.block()).state_to_string(*block);
+            writer.write_all(&(name.len() as u16).to_le_bytes())?;
+            writer.write_all(name.as_bytes())?;
+        }
+        for index in indices {
+            writer.write_all(&index.to_le_bytes())?;
+        }
+
+        if !self.lit {
+            writer.write_all(&0u16.to_le_bytes())?;
+            return Ok(());
+        }
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&LIGHT_TAG)?;
+        let first = self.light.get(0);
+        if self.light.iter().all(|light| light == first) {
+            writer.write_all(&2u32.to_le_bytes())?;
+            writer.write_all(&first.to_le_bytes())?;
+        } else {
+            writer.write_all(&(16 * 16 * 16 * 2u32).to_le_bytes())?;
+            for light in self.light.iter() {
+                writer.write_all(&light.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `write_to`で書き出したチャンクを読み込む
+    ///
+    /// ブロックは名前で`registry`から探す
+    pub fn read_from<R: Read>(
+        registry: &BlockRegistry,
+        reader: &mut R,
+    ) -> Result<Chunk, ChunkFormatError> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(ChunkFormatError::BadMagic(magic));
+        }
+        let version = read_u16(reader)?;
+        if version > FORMAT_VERSION {
+            return Err(ChunkFormatError::UnsupportedVersion(version));
+        }
+
+        let x = read_i32(reader)?;
+        let y = read_i32(reader)?;
+        let z = read_i32(reader)?;
+        let mut chunk = Chunk::new(ChunkPos::new(nalgebra::Point3::new(x, y, z)));
+
+        let palette_len = read_u16(reader)?;
+        let mut palette = Vec::with_capacity(palette_len as usize);
+        for _ in 0..palette_len {
+            let len = if version == 1 {
+                let mut len = [0u8; 1];
+                reader.read_exact(&mut len)?;
+                len[0] as usize
+            } else {
+                read_u16(reader)? as usize
+            };
+            let mut name = vec![0u8; len];
+            reader.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|_| ChunkFormatError::InvalidName)?;
+            let state = registry
+                .parse_state(&name)
+                .ok_or(ChunkFormatError::UnknownBlock(name))?;
+            palette.push(state);
+        }
+
+        for pos in BlockPosInChunk::all() {
+            let index = read_u16(reader)?;
+            if index == AIR_INDEX {
+                continue;
+            }
+            let state = palette
+                .get(index as usize)
+                .ok_or(ChunkFormatError::InvalidPaletteIndex(index))?;
+            chunk.blocks.set(pos.index(), Some(*state));
+        }
+        chunk.compact();
+
+        let section_count = read_u16(reader)?;
+        for _ in 0..section_count {
+            let mut tag = [0u8; 4];
+            reader.read_exact(&mut tag)?;
+            let len = read_u32(reader)?;
+            if tag == LIGHT_TAG {
+                match len {
+                    2 => chunk.light.fill(read_u16(reader)?),
+                    8192 => {
+                        for pos in BlockPosInChunk::all() {
+                            chunk.light.set(pos.index(), read_u16(reader)?);
+                        }
+                        chunk.light.compact();
+                    }
+                    _ => return Err(ChunkFormatError::InvalidSection(tag)),
+                }
+                chunk.lit = true;
+                continue;
+            }
+            let skipped = io::copy(&mut reader.by_ref().take(len as u64), &mut io::sink())?;
+            if skipped < len as u64 {
+                return Err(ChunkFormatError::Truncated);
+            }
+        }
+
+        Ok(chunk)
+    }
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut bytes = [0u8; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_i32<R: Read>(reader: &mut R) -> io::Result<i32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(i32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point3;
+
+    use super::*;
+    use crate::block::{test_block, test_registry};
+    use crate::light::LightKind;
+
+    fn sample_chunk() -> Chunk {
+        let registry = test_registry();
+        let mut chunk = Chunk::new(ChunkPos::new(Point3::new(-3, 1, 70000)));
+        for pos in BlockPosInChunk::all() {
+            let state = match (pos.x + pos.y * 3 + pos.z * 7) % 6 {
+                0 => continue,
+                1 => test_block("stone").default_state(),
+                2 => test_block("dirt").default_state(),
+                3 => registry.parse_state("oak_log[axis=x]").unwrap(),
+                4 => registry.parse_state("oak_log[axis=z]").unwrap(),
+                _ => test_block("grass_block").default_state(),
+            };
+            chunk.set_state(&state, &pos);
+        }
+        chunk
+    }
+
+    fn encode(chunk: &Chunk) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        chunk.write_to(&test_registry(), &mut bytes).unwrap();
+        bytes
+    }
+
+    fn assert_same(a: &Chunk, b: &Chunk) {
+        assert_eq!(a.position(), b.position());
+        assert_eq!(a.is_lit(), b.is_lit());
+        for pos in BlockPosInChunk::all() {
+            assert_eq!(a.get_state(&pos), b.get_state(&pos), "{:?}", pos);
+            assert_eq!(a.lights(&pos), b.lights(&pos), "{:?}", pos);
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let chunk = sample_chunk();
+        let decoded = Chunk::read_from(&test_registry(), &mut &encode(&chunk)[..]).unwrap();
+        assert_same(&chunk, &decoded);
+        assert!(!decoded.is_dirty());
+    }
+
+    #[test]
+    fn roundtrip_empty() {
+        let chunk = Chunk::new(ChunkPos::new(Point3::new(0, -1, 0)));
+        let decoded = Chunk::read_from(&test_registry(), &mut &encode(&chunk)[..]).unwrap();
+        assert_same(&chunk, &decoded);
+    }
+
+    #[test]
+    fn light_is_saved() {
+        let mut chunk = sample_chunk();
+        chunk.mark_lit();
+        for pos in BlockPosInChunk::all() {
+            chunk.set_light(LightKind::Sky, &pos, (pos.y % 16) as u8);
+            chunk.set_light(LightKind::Green, &pos, (pos.x % 3) as u8);
+        }
+        let decoded = Chunk::read_from(&test_registry(), &mut &encode(&chunk)[..]).unwrap();
+        assert_same(&chunk, &decoded);
+
+        // チャンク全体で同じ強さなら1つだけ保存する
+        let mut sky = Chunk::new(ChunkPos::new(Point3::new(0, 5, 0)));
+        sky.mark_lit();
+        for pos in BlockPosInChunk::all() {
+            sky.set_light(LightKind::Sky, &pos, 15);
+        }
+        let bytes = encode(&sky);
+        assert!(bytes.len() < encode(&chunk).len() / 2);
+        let decoded = Chunk::read_from(&test_registry(), &mut &bytes[..]).unwrap();
+        assert_same(&sky, &decoded);
+
+        // 長さの合わないセクションは読めない
+        let mut bytes = encode(&chunk);
+        let len_at = bytes.len() - 8192 - 4;
+        bytes[len_at..len_at + 4].copy_from_slice(&10u32.to_le_bytes());
+        assert!(matches!(
+            Chunk::read_from(&test_registry(), &mut &bytes[..]),
+            Err(ChunkFormatError::InvalidSection(LIGHT_TAG))
+        ));
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = encode(&sample_chunk());
+        // ヘッダー部分はすべての長さを、ブロック部分は間引いて試す
+        let lengths = (0..64)
+            .chain((64..bytes.len()).step_by(97))
+            .chain(bytes.len() - 3..bytes.len());
+        for len in lengths {
+            match Chunk::read_from(&test_registry(), &mut &bytes[..len]) {
+                Err(ChunkFormatError::Truncated) => {}
+                other => panic!("len {}: {:?}", len, other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn future_version() {
+        let mut bytes = encode(&sample_chunk());
+        bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        match Chunk::read_from(&test_registry(), &mut &bytes[..]) {
+            Err(ChunkFormatError::UnsupportedVersion(version)) => {
+                assert_eq!(version, FORMAT_VERSION + 1)
+            }
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut bytes = encode(&sample_chunk());
+        bytes[0] = b'X';
+        assert!(matches!(
+            Chunk::read_from(&test_registry(), &mut &bytes[..]),
+            Err(ChunkFormatError::BadMagic(_))
+        ));
+    }
+
+    #[test]
+    fn blocks_are_found_by_name() {
+        let bytes = encode(&sample_chunk());
+        // IDが変わっても名前が同じなら読める
+        let renumbered = BlockRegistry::from_json(
+            &include_str!("../../rsc/blocks.json")
+                .replace("\"id\": 1,", "\"id\": 100,")
+                .replace("\"id\": 3,", "\"id\": 1,"),
+        )
+        .unwrap();
+        let decoded = Chunk::read_from(&renumbered, &mut &bytes[..]).unwrap();
+        let chunk = sample_chunk();
+        let registry = test_registry();
+        for pos in BlockPosInChunk::all() {
+            let expected = chunk
+                .get_state(&pos)
+                .map(|state| registry.get(state.block()).state_to_string(state));
+            let actual = decoded
+                .get_state(&pos)
+                .map(|state| renumbered.get(state.block()).state_to_string(state));
+            assert_eq!(expected, actual);
+        }
+
+        let without_stone = BlockRegistry::from_json(
+            r#"{ "atlas": { "tile_size": 64, "tiles": { "dirt": [0, 2] } },
+                 "blocks": [{ "id": 2, "name": "dirt", "textures": { "all": "dirt" } }] }"#,
+        )
+        .unwrap();
+        match Chunk::read_from(&without_stone, &mut &bytes[..]) {
+            Err(ChunkFormatError::UnknownBlock(name)) => assert_ne!(name, "dirt"),
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn reads_version_1() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RCCK");
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        for coord in &[1i32, -2, 3] {
+            bytes.extend_from_slice(&coord.to_le_bytes());
+        }
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        for name in &["stone", "oak_log"] {
+            bytes.push(name.len() as u8);
+            bytes.extend_from_slice(name.as_bytes());
+        }
+        for pos in BlockPosInChunk::all() {
+            let index = if pos.y == 0 {
+                pos.x as u16 % 2
+            } else {
+                AIR_INDEX
+            };
+            bytes.extend_from_slice(&index.to_le_bytes());
+        }
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+
+        let chunk = Chunk::read_from(&test_registry(), &mut &bytes[..]).unwrap();
+        assert_eq!(chunk.position(), &ChunkPos::new(Point3::new(1, -2, 3)));
+        let at = |x, y| chunk.get_state(&BlockPosInChunk::new(x, y, 0).unwrap());
+        assert_eq!(at(0, 0), Some(test_block("stone").default_state()));
+        assert_eq!(at(1, 0), Some(test_block("oak_log").default_state()));
+        assert_eq!(at(1, 1), None);
+    }
+
+    #[test]
+    fn unknown_sections_are_skipped() {
+        let chunk = sample_chunk();
+        let mut bytes = encode(&chunk);
+        let count_at = bytes.len() - 2;
+        bytes[count_at..].copy_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(b"TEST");
+        bytes.extend_from_slice(&3u32.to_le_bytes());
+        bytes.extend_from_slice(&[1, 2, 3]);
+
+        let decoded = Chunk::read_from(&test_registry(), &mut &bytes[..]).unwrap();
+        assert_same(&chunk, &decoded);
+
+        bytes.pop();
+        assert!(matches!(
+            Chunk::read_from(&test_registry(), &mut &bytes[..]),
+            Err(ChunkFormatError::Truncated)
+        ));
+    }
+}
@@ -0,0 +1,106 @@
+//! 隣り合う同じテクスチャの面を1枚の大きな面にまとめる(greedy meshing)
+//!
+//! 面の向きごとに、チャンクを16枚の層に分けて16×16の面の表を作る。
+//! 表の左上から順に、同じテクスチャの面が続く限り横に広げ、次に縦に広げた長方形を1枚の面にする。
+
+use nalgebra::{Point3, Vector3};
+
+use re::vao::vao_builder::{CuboidRotations, CuboidTextures, VaoBuilder, VertexLight};
+use reverie_engine as re;
+
+use crate::block::Side;
+use crate::texture::block_texture::BlockTextures;
+
+/// まとめる面のテクスチャの名前と回転(see: `CuboidRotations`)、4つの頂点の明るさ(see: `Chunk::face_light`)
+pub(super) type MergedFace<'a> = (&'a str, u8, [VertexLight; 4]);
+
+/// `faces`の面を、同じテクスチャで同じ回転の面ごとにまとめて`builder`に追加する
+///
+/// `faces`はブロックの添字ごとの、各面(`Side::ALL`の順)の`MergedFace`。
+/// 見えない面や、まとめずに追加する面は`None`。
+/// 4つの頂点の明るさが同じ面だけをまとめる。
+/// `origin`はチャンクの原点の座標。テクスチャは引き伸ばさずに繰り返す
+pub(super) fn add_merged_faces(
+    builder: &mut VaoBuilder,
+    origin: &Point3<f32>,
+    faces: &[[Option<MergedFace>; 6]],
+    textures: &BlockTextures,
+) {
+    for side in Side::ALL {
+        let (a, b, c) = super::face_axes(side);
+        for d in 0..16 {
+            let mut mask: [Option<MergedFace>; 16 * 16] = [None; 16 * 16];
+            for j in 0..16 {
+                for i in 0..16 {
+                    let mut p = [0; 3];
+                    p[a] = d;
+                    p[b] = i;
+                    p[c] = j;
+                    mask[i + 16 * j] = faces[256 * p[1] + 16 * p[2] + p[0]][side.index()];
+                }
+            }
+
+            for j in 0..16 {
+                for i in 0..16 {
+                    let face = match mask[i + 16 * j] {
+                        Some(face) => face,
+                        None => continue,
+                    };
+                    let (texture, rotation, lights) = face;
+                    // 明るさが面の中で変わるときは、まとめると頂点の間の明るさが変わってしまう
+                    let uniform = lights.iter().all(|light| *light == lights[0]);
+                    let mut width = 1;
+                    while uniform && i + width < 16 && mask[i + width + 16 * j] == Some(face) {
+                        width += 1;
+                    }
+                    let mut height = 1;
+                    while uniform
+                        && j + height < 16
+                        && (i..i + width).all(|k| mask[k + 16 * (j + height)] == Some(face))
+                    {
+                        height += 1;
+                    }
+                    for l in j..j + height {
+                        for k in i..i + width {
+                            mask[k + 16 * l] = None;
+                        }
+                    }
+
+                    let mut begin = [0.0; 3];
+                    let mut end = [0.0; 3];
+                    begin[a] = d as f32;
+                    end[a] = (d + 1) as f32;
+                    begin[b] = i as f32;
+                    end[b] = (i + width) as f32;
+                    begin[c] = j as f32;
+                    end[c] = (j + height) as f32;
+                    let uv = textures.get(texture).unwrap();
+                    let begin = origin + Vector3::from(begin);
+                    builder.add_cuboid_faces_with_light(
+                        &begin,
+                        &(origin + Vector3::from(end)),
+                        &CuboidTextures {
+                            top: uv,
+                            bottom: uv,
+                            south: uv,
+                            north: uv,
+                            west: uv,
+                            east: uv,
+                            rotations: CuboidRotations {
+                                top: rotation,
+                                bottom: rotation,
+                                south: rotation,
+                                north: rotation,
+                                west: rotation,
+                                east: rotation,
+                            },
+                        },
+                        &super::cuboid_faces(|s| s == side),
+                        true,
+                        &|vertex, _| super::vertex_light(side, &lights, &begin, vertex),
+                    );
+                }
+            }
+        }
+    }
+}
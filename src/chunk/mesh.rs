@@ -0,0 +1,95 @@
+//! チャンクの頂点データ
+
+use re::vao::mesh::Mesh;
+use reverie_engine as re;
+
+use crate::block::RenderLayer;
+use crate::mymath::ChunkPos;
+
+/// チャンクを描画するための頂点データ(see: `Chunk::build_mesh`)
+///
+/// OpenGLに依存しないので、どのスレッドでも作ることができる。
+/// 描画するには`Vao::from_mesh`でGPUに送る
+#[derive(Clone, Debug)]
+pub struct ChunkMesh {
+    pub position: ChunkPos,
+    /// `RenderLayer::ALL`の順の、描画の方法ごとの頂点データ。頂点属性の並びは`VaoBuilder::layout`
+    pub layers: [Mesh; 3],
+}
+
+impl ChunkMesh {
+    pub fn layer(&self, layer: RenderLayer) -> &Mesh {
+        &self.layers[layer.index()]
+    }
+
+    /// 長方形の面の数
+    pub fn face_count(&self) -> usize {
+        self.layers
+            .iter()
+            .map(|mesh| mesh.triangle_count() / 2)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.iter().all(Mesh::is_empty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point3;
+
+    use crate::block::{test_block, test_registry, RenderLayer};
+    use crate::chunk::{Chunk, MeshingMode};
+    use crate::mymath::{BlockPosInChunk, ChunkPos};
+    use crate::texture::block_texture::get_textures_in_atlas;
+
+    #[test]
+    fn mesh_of_single_block() {
+        let registry = test_registry();
+        let textures = get_textures_in_atlas(&registry, 256, 256);
+        let mut chunk = Chunk::new(ChunkPos::new(Point3::new(1, 0, -1)));
+        chunk.set_block(
+            &test_block("stone"),
+            &BlockPosInChunk::new(2, 3, 4).unwrap(),
+        );
+
+        let mesh = chunk.build_mesh(&registry, &[None; 27], &textures, MeshingMode::Naive);
+        assert_eq!(mesh.position, *chunk.position());
+        assert_eq!(mesh.face_count(), 6);
+        let opaque = mesh.layer(RenderLayer::Opaque);
+        assert_eq!(opaque.vertex_count(), 4 * 6);
+        assert_eq!(opaque.layout.attribute_sizes(), &[3, 3, 2, 4, 1, 4]);
+        assert!(opaque.indices.iter().all(|&i| (i as usize) < 24));
+        assert!(mesh.layer(RenderLayer::Translucent).is_empty());
+
+        let stone = textures.get("stone").unwrap();
+        for i in 0..opaque.vertex_count() {
+            let vertex = opaque.vertex(i);
+            let (position, normal, tile, ao, light) = (
+                &vertex[0..3],
+                &vertex[3..6],
+                &vertex[8..12],
+                vertex[12],
+                &vertex[13..17],
+            );
+            assert!((18.0..=19.0).contains(&position[0]), "{:?}", position);
+            assert!((3.0..=4.0).contains(&position[1]), "{:?}", position);
+            assert!((-12.0..=-11.0).contains(&position[2]), "{:?}", position);
+            assert_eq!(normal.iter().map(|v| v.abs()).sum::<f32>(), 1.0);
+            assert_eq!(
+                tile,
+                &[stone.begin_u, stone.begin_v, stone.end_u, stone.end_v]
+            );
+            // 周りに何も無いので暗くならない
+            assert_eq!(ao, 1.0);
+            // 光を計算していないチャンクは真っ暗
+            assert_eq!(light, &[0.0; 4]);
+        }
+
+        let empty = Chunk::new(ChunkPos::new(Point3::new(0, 0, 0)));
+        assert!(empty
+            .build_mesh(&registry, &[None; 27], &textures, MeshingMode::Greedy)
+            .is_empty());
+    }
+}
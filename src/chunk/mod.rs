@@ -0,0 +1,874 @@
+use nalgebra::Point3;
+
+use re::texture::texture_atlas::TextureUV;
+use re::vao::vao_builder::{CuboidFaces, VaoBuilder, VertexLight};
+use reverie_engine as re;
+
+use crate::block::registry::MAX_LIGHT_LEVEL;
+use crate::block::{
+    Block, BlockDefinition, BlockModel, BlockRegistry, BlockState, RenderLayer, Side,
+};
+use crate::light::LightKind;
+use crate::mymath::BlockPosInChunk;
+use crate::mymath::BlockPosInWorld;
+use crate::mymath::ChunkPos;
+use crate::texture::block_texture;
+use crate::texture::block_texture::BlockTextures;
+
+pub use format::ChunkFormatError;
+pub use mesh::ChunkMesh;
+pub use palette::PalettedStorage;
+
+mod format;
+mod greedy;
+mod mesh;
+mod palette;
+
+#[derive(Clone)]
+pub struct Chunk {
+    blocks: PalettedStorage<Option<BlockState>>,
+    /// 4ビットずつ、上位から空からの光、ブロックからの赤、緑、青の光の強さ(see: `light`)
+    ///
+    /// ブロックと一緒に保存し、読み込んだときは隣のチャンクとの境界だけ計算し直す
+    light: PalettedStorage<u16>,
+    /// `light`を計算済みかどうか
+    lit: bool,
+    position: ChunkPos,
+    /// 最後に保存してから変更されたかどうか
+    dirty: bool,
+}
+
+impl Chunk {
+    pub fn new(position: ChunkPos) -> Chunk {
+        Chunk {
+            blocks: PalettedStorage::new(16 * 16 * 16, None),
+            light: PalettedStorage::new(16 * 16 * 16, 0),
+            lit: false,
+            position,
+            dirty: false,
+        }
+    }
+
+    pub fn position(&self) -> &ChunkPos {
+        &self.position
+    }
+
+    /// ブロックをデフォルトの状態で置く
+    pub fn set_block(&mut self, block: &Block, pos: &BlockPosInChunk) {
+        self.set_state(&block.default_state(), pos);
+    }
+
+    pub fn get_block(&self, pos: &BlockPosInChunk) -> Option<Block> {
+        self.get_state(pos).map(|state| state.block())
+    }
+
+    pub fn set_state(&mut self, state: &BlockState, pos: &BlockPosInChunk) {
+        let _old = self.blocks.set(pos.index(), Some(*state));
+        self.dirty = true;
+    }
+
+    pub fn get_state(&self, pos: &BlockPosInChunk) -> Option<BlockState> {
+        self.blocks.get(pos.index())
+    }
+
+    /// ブロックを取り除いて空気にする
+    pub fn remove_block(&mut self, pos: &BlockPosInChunk) {
+        let _old = self.blocks.set(pos.index(), None);
+        self.dirty = true;
+    }
+
+    pub fn light(&self, kind: LightKind, pos: &BlockPosInChunk) -> u8 {
+        (self.light.get(pos.index()) >> light_shift(kind) & 0xf) as u8
+    }
+
+    /// (空からの光, 赤, 緑, 青)の強さ
+    pub fn lights(&self, pos: &BlockPosInChunk) -> [u8; 4] {
+        LightKind::ALL.map(|kind| self.light(kind, pos))
+    }
+
+    /// 光の強さは`light`で計算するので、ふつうは直接変更しない
+    pub fn set_light(&mut self, kind: LightKind, pos: &BlockPosInChunk, level: u8) {
+        let shift = light_shift(kind);
+        let light = self.light.get(pos.index()) & !(0xf << shift);
+        self.light.set(pos.index(), light | (level as u16) << shift);
+    }
+
+    /// 使われなくなった光の強さの組み合わせを忘れて、メモリ使用量を減らす
+    ///
+    /// 光を広げたり取り除いたりすると途中の強さがパレットに残るので、計算し終わった後に呼ぶ
+    pub fn compact_light(&mut self) {
+        self.light.compact();
+    }
+
+    /// `light`がヒープに確保しているバイト数
+    #[cfg(test)]
+    pub fn light_heap_size(&self) -> usize {
+        self.light.heap_size()
+    }
+
+    /// 光を計算済みかどうか。生成したばかりのチャンクでは`false`
+    pub fn is_lit(&self) -> bool {
+        self.lit
+    }
+
+    /// 光を計算済みとして扱う(see: `Lighting::light_chunk`)
+    pub fn mark_lit(&mut self) {
+        self.lit = true;
+    }
+
+    /// チャンク全体が同じブロック(または空気)ならそのブロック
+    pub fn uniform_block(&self) -> Option<Option<BlockState>> {
+        self.blocks.single_value()
+    }
+
+    /// 使われなくなったブロックの種類を忘れて、メモリ使用量を減らす
+    ///
+    /// 生成や読み込みのようにまとめてブロックを置いた後に呼ぶ
+    pub fn compact(&mut self) {
+        self.blocks.compact();
+    }
+
+    /// 最後に保存してから変更されたかどうか
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// 保存済みとして扱う
+    pub fn clear_dirty(&mut self) {
+        self.dirty = false;
+    }
+
+    /// 描画する頂点データを作る。OpenGLは使わない
+    ///
+    /// `neighbors`は周りのチャンク(see: `GameWorld::chunk_neighbors`)
+    pub fn build_mesh(
+        &self,
+        registry: &BlockRegistry,
+        neighbors: &ChunkNeighbors,
+        textures: &BlockTextures,
+        meshing: MeshingMode,
+    ) -> ChunkMesh {
+        let mut builders = RenderLayer::ALL.map(|_| VaoBuilder::new());
+        self.add_visible_faces(&mut builders, registry, neighbors, textures, meshing);
+        ChunkMesh {
+            position: self.position,
+            layers: builders.map(VaoBuilder::build_mesh),
+        }
+    }
+
+    /// 不透明なブロックに隠れていない面を、ブロックの`render_layer`の`builders`に追加する
+    fn add_visible_faces(
+        &self,
+        builders: &mut [VaoBuilder; 3],
+        registry: &BlockRegistry,
+        neighbors: &ChunkNeighbors,
+        textures: &BlockTextures,
+        meshing: MeshingMode,
+    ) {
+        if self.uniform_block() == Some(None) {
+            return;
+        }
+        // 立方体のブロックの面は、後でまとめて追加する
+        let mut merged = match meshing {
+            MeshingMode::Naive => None,
+            MeshingMode::Greedy => Some(vec![[None; 6]; 16 * 16 * 16]),
+        };
+        for pos in BlockPosInChunk::all() {
+            let state = match self.blocks.get(pos.index()) {
+                Some(state) => state,
+                None => continue,
+            };
+            let block = registry.get(state.block());
+            let builder = &mut builders[block.render_layer.index()];
+            let hidden = Side::ALL.map(|side| {
+                self.neighbor_state(&pos, side, neighbors)
+                    .is_some_and(|neighbor| {
+                        let neighbor = registry.get(neighbor.block());
+                        // 並べたガラスの間の面は見えない
+                        neighbor.is_opaque_cube()
+                            || (neighbor.block == block.block
+                                && block.render_layer == RenderLayer::Translucent
+                                && block.model == BlockModel::Cube)
+                    })
+            });
+            if block.model != BlockModel::Cube {
+                // 立方体でないブロックは、ブロックのある位置の明るさで一様に照らす
+                let light = vertex_light_of(1.0, &[self.lights(&pos).map(f32::from)]);
+                add_block(
+                    builder,
+                    &BlockPosInWorld::from_chunk_pos(&self.position, &pos),
+                    block,
+                    state,
+                    textures,
+                    &hidden,
+                    &[[light; 4]; 6],
+                );
+                continue;
+            }
+            let light = Side::ALL.map(|side| {
+                if hidden[side.index()] {
+                    [VertexLight::FULL; 4]
+                } else {
+                    self.face_light(&pos, side, registry, neighbors)
+                }
+            });
+            // 不透明なブロックの面だけをまとめる
+            if let Some(merged) = merged
+                .as_mut()
+                .filter(|_| block.render_layer == RenderLayer::Opaque)
+            {
+                let orientation = block.orientation(state);
+                merged[pos.index()] = Side::ALL.map(|side| {
+                    (!hidden[side.index()]).then(|| {
+                        (
+                            block.textures.get(orientation.source_side(side)),
+                            orientation.texture_rotation(side),
+                            light[side.index()],
+                        )
+                    })
+                });
+                continue;
+            }
+            add_block(
+                builder,
+                &BlockPosInWorld::from_chunk_pos(&self.position, &pos),
+                block,
+                state,
+                textures,
+                &hidden,
+                &light,
+            );
+        }
+        if let Some(merged) = merged {
+            let origin = BlockPosInWorld::from_chunk_pos(
+                &self.position,
+                &BlockPosInChunk::new(0, 0, 0).unwrap(),
+            );
+            greedy::add_merged_faces(
+                &mut builders[RenderLayer::Opaque.index()],
+                &origin.cast::<f32>(),
+                &merged,
+                textures,
+            );
+        }
+    }
+
+    /// `pos`の面`side`で接しているブロック。チャンクの外なら`neighbors`から探す
+    fn neighbor_state(
+        &self,
+        pos: &BlockPosInChunk,
+        side: Side,
+        neighbors: &ChunkNeighbors,
+    ) -> Option<BlockState> {
+        let normal = side.normal();
+        let coords = coords_of(pos);
+        self.state_at([0, 1, 2].map(|i| coords[i] + normal[i]), neighbors)
+    }
+
+    /// チャンクの原点から`coords`の位置にあるブロックを含むチャンクと、そのチャンク内での位置
+    ///
+    /// チャンクの外なら`neighbors`から探す。`coords`はチャンクから1ブロックまでしかはみ出さない
+    fn locate<'a>(
+        &'a self,
+        coords: [i32; 3],
+        neighbors: &ChunkNeighbors<'a>,
+    ) -> Option<(&'a Chunk, BlockPosInChunk)> {
+        let wrapped = coords.map(|v| v.rem_euclid(16) as u32);
+        let pos = BlockPosInChunk::new(wrapped[0], wrapped[1], wrapped[2]).unwrap();
+        let offset = coords.map(|v| v.div_euclid(16));
+        if offset == [0, 0, 0] {
+            return Some((self, pos));
+        }
+        Some((neighbors[neighbor_index(offset)]?, pos))
+    }
+
+    /// チャンクの原点から`coords`の位置にあるブロック(see: `locate`)
+    ///
+    /// 見つからないブロックは空気として扱う
+    fn state_at(&self, coords: [i32; 3], neighbors: &ChunkNeighbors) -> Option<BlockState> {
+        let (chunk, pos) = self.locate(coords, neighbors)?;
+        chunk.get_state(&pos)
+    }
+
+    /// チャンクの原点から`coords`の位置の光の強さ(see: `lights`, `locate`)
+    fn light_at(&self, coords: [i32; 3], neighbors: &ChunkNeighbors) -> Option<[u8; 4]> {
+        let (chunk, pos) = self.locate(coords, neighbors)?;
+        Some(chunk.lights(&pos))
+    }
+
+    /// `pos`のブロックの面`side`の4つの頂点が、周りのブロックにどれだけ遮られているか
+    ///
+    /// 0(最も暗い)〜3(遮られていない)。頂点は面の手前にある3つのブロックで決まる。
+    /// 並びは`vertex_ao`を参照
+    fn ao_levels(
+        &self,
+        pos: &BlockPosInChunk,
+        side: Side,
+        registry: &BlockRegistry,
+        neighbors: &ChunkNeighbors,
+    ) -> [u8; 4] {
+        let normal = side.normal();
+        let (_, b, c) = face_axes(side);
+        let coords = coords_of(pos);
+        let front = [0, 1, 2].map(|i| coords[i] + normal[i]);
+        let is_opaque = |db: i32, dc: i32| {
+            let mut p = front;
+            p[b] += db;
+            p[c] += dc;
+            self.state_at(p, neighbors)
+                .is_some_and(|state| registry.get(state.block()).is_opaque_cube())
+        };
+        [0, 1, 2, 3].map(|corner| {
+            let db = if corner & 1 == 0 { -1 } else { 1 };
+            let dc = if corner & 2 == 0 { -1 } else { 1 };
+            let (side1, side2) = (is_opaque(db, 0), is_opaque(0, dc));
+            if side1 && side2 {
+                0
+            } else {
+                3 - side1 as u8 - side2 as u8 - is_opaque(db, dc) as u8
+            }
+        })
+    }
+
+    /// `pos`のブロックの面`side`の4つの頂点の明るさ。並びは`ao_levels`と同じ
+    ///
+    /// 光の強さは、面の手前のブロックと、頂点を囲むブロックのうち光を通すものの平均。
+    /// 両側を塞がれた頂点では、斜めのブロックは使わない
+    fn face_light(
+        &self,
+        pos: &BlockPosInChunk,
+        side: Side,
+        registry: &BlockRegistry,
+        neighbors: &ChunkNeighbors,
+    ) -> [VertexLight; 4] {
+        let ao = self.ao_levels(pos, side, registry, neighbors);
+        let normal = side.normal();
+        let (_, b, c) = face_axes(side);
+        let coords = coords_of(pos);
+        let front = [0, 1, 2].map(|i| coords[i] + normal[i]);
+        let around = |db: i32, dc: i32| {
+            let mut p = front;
+            p[b] += db;
+            p[c] += dc;
+            let opaque = self
+                .state_at(p, neighbors)
+                .is_some_and(|state| registry.get(state.block()).is_opaque_cube());
+            (!opaque).then(|| self.light_at(p, neighbors)).flatten()
+        };
+        // 手前のチャンクが読み込まれていなければ、空に開いているとみなす
+        let front_light = self
+            .light_at(front, neighbors)
+            .unwrap_or([MAX_LIGHT_LEVEL, 0, 0, 0]);
+        [0, 1, 2, 3].map(|corner| {
+            let db = if corner & 1 == 0 { -1 } else { 1 };
+            let dc = if corner & 2 == 0 { -1 } else { 1 };
+            let (side1, side2) = (around(db, 0), around(0, dc));
+            let diagonal = if side1.is_none() && side2.is_none() {
+                None
+            } else {
+                around(db, dc)
+            };
+            let lights: Vec<[f32; 4]> = [Some(front_light), side1, side2, diagonal]
+                .iter()
+                .flatten()
+                .map(|light| light.map(f32::from))
+                .collect();
+            vertex_light_of(AO_BRIGHTNESS[ao[corner] as usize], &lights)
+        })
+    }
+}
+
+/// (空からの光, 赤, 緑, 青)の強さの平均の明るさ
+fn vertex_light_of(ao: f32, lights: &[[f32; 4]]) -> VertexLight {
+    let average = |i: usize| {
+        lights.iter().map(|light| light[i]).sum::<f32>()
+            / (lights.len() as f32 * MAX_LIGHT_LEVEL as f32)
+    };
+    VertexLight {
+        ao,
+        sky: average(0),
+        block: [average(1), average(2), average(3)],
+    }
+}
+
+/// `BlockPosInChunk`を、チャンクの外を指せるように符号付きの座標にする
+fn coords_of(pos: &BlockPosInChunk) -> [i32; 3] {
+    [pos.x as i32, pos.y as i32, pos.z as i32]
+}
+
+/// `Chunk::light`で`kind`の光の強さが入っているビットの位置
+fn light_shift(kind: LightKind) -> u32 {
+    match kind {
+        LightKind::Sky => 12,
+        LightKind::Red => 8,
+        LightKind::Green => 4,
+        LightKind::Blue => 0,
+    }
+}
+
+/// 面や辺、頂点で接している周りの26個のチャンク
+///
+/// チャンクの位置の差ごとに`neighbor_index`の位置に入れる。
+/// 読み込まれていないチャンクと、真ん中(差が(0, 0, 0))は`None`
+pub type ChunkNeighbors<'a> = [Option<&'a Chunk>; 27];
+
+/// `ChunkNeighbors`で、チャンクの位置の差が`offset`(各成分が-1〜1)のチャンクが入っている位置
+pub fn neighbor_index(offset: [i32; 3]) -> usize {
+    ((offset[0] + 1) + 3 * (offset[1] + 1) + 9 * (offset[2] + 1)) as usize
+}
+
+/// `neighbor_index`の逆
+pub fn neighbor_offset(index: usize) -> [i32; 3] {
+    let index = index as i32;
+    [index % 3 - 1, index / 3 % 3 - 1, index / 9 - 1]
+}
+
+/// チャンクの頂点の作り方
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MeshingMode {
+    /// ブロックの面ごとに1枚の面を作る
+    Naive,
+    /// 隣り合う同じテクスチャの面を1枚にまとめる(see: `greedy`)
+    Greedy,
+}
+
+type Vector3 = nalgebra::Vector3<f32>;
+
+pub const UP: Vector3 = Vector3::new(0.0, 1.0, 0.0);
+pub const DOWN: Vector3 = Vector3::new(0.0, -1.0, 0.0);
+pub const NORTH: Vector3 = Vector3::new(1.0, 0.0, 0.0);
+pub const SOUTH: Vector3 = Vector3::new(-1.0, 0.0, 0.0);
+pub const WEST: Vector3 = Vector3::new(0.0, 0.0, -1.0);
+pub const EAST: Vector3 = Vector3::new(0.0, 0.0, 1.0);
+
+const BLOCK_SIZE: Vector3 = Vector3::new(1.0, 1.0, 1.0);
+
+/// 遮蔽の度合い(see: `Chunk::ao_levels`)ごとの頂点の明るさ
+const AO_BRIGHTNESS: [f32; 4] = [0.45, 0.65, 0.82, 1.0];
+
+/// 面`side`の法線の軸と、面に沿った2つの軸
+fn face_axes(side: Side) -> (usize, usize, usize) {
+    let a = side.normal().iter().position(|&v| v != 0).unwrap();
+    (a, (a + 1) % 3, (a + 2) % 3)
+}
+
+/// `begin`から始まる面`side`の頂点`vertex`の明るさ
+///
+/// `lights`の並びは、面に沿った軸`(b, c)`(see: `face_axes`)でそれぞれ負の側を0、正の側を1として`b + 2 * c`
+fn vertex_light(
+    side: Side,
+    lights: &[VertexLight; 4],
+    begin: &Point3<f32>,
+    vertex: &Point3<f32>,
+) -> VertexLight {
+    let (_, b, c) = face_axes(side);
+    let corner = (vertex[b] > begin[b]) as usize + 2 * (vertex[c] > begin[c]) as usize;
+    lights[corner]
+}
+
+/// 面の外向きの法線から`Side`を求める
+fn side_of_normal(normal: &Vector3) -> Side {
+    Side::from_normal(normal.map(|v| v.round() as i32).into()).unwrap()
+}
+
+/// `hidden`は`Side::ALL`の順で、その面が隣のブロックに隠れているかどうか。
+/// `light`は各面の頂点の明るさ(see: `Chunk::face_light`)。立方体でないブロックでは一様にする
+fn add_block(
+    builder: &mut VaoBuilder,
+    begin: &BlockPosInWorld,
+    block: &BlockDefinition,
+    state: BlockState,
+    textures: &BlockTextures,
+    hidden: &[bool; 6],
+    light: &[[VertexLight; 4]; 6],
+) {
+    let begin = begin.cast::<f32>();
+    match block.model {
+        BlockModel::Cube => builder.add_cuboid_faces_with_light(
+            &begin,
+            &(begin + BLOCK_SIZE),
+            &block_texture::generate_cuboid_texture(block, state, textures),
+            &cuboid_faces(|side| !hidden[side.index()]),
+            false,
+            &|vertex, normal| {
+                let side = side_of_normal(normal);
+                vertex_light(side, &light[side.index()], &begin, vertex)
+            },
+        ),
+        BlockModel::Cross => add_cross(
+            builder,
+            &begin,
+            textures.get(block.textures.get(Side::NORTH)).unwrap(),
+            &light[0][0],
+        ),
+        BlockModel::Elements(_) => {
+            for (from, to) in block.boxes(state) {
+                // ブロックの境界にある面だけが隣のブロックに隠れる
+                let on_boundary = |side: Side| match side {
+                    Side::TOP => to[1] == 1.0,
+                    Side::BOTTOM => from[1] == 0.0,
+                    Side::NORTH => to[0] == 1.0,
+                    Side::SOUTH => from[0] == 0.0,
+                    Side::WEST => from[2] == 0.0,
+                    Side::EAST => to[2] == 1.0,
+                };
+                let uvs = block_texture::generate_box_textures(block, state, textures, from, to);
+                builder.add_cuboid_faces_with_light(
+                    &(begin + Vector3::from(from)),
+                    &(begin + Vector3::from(to)),
+                    &block_texture::cuboid_textures(
+                        &uvs,
+                        block_texture::cuboid_rotations(block, state),
+                    ),
+                    &cuboid_faces(|side| !(hidden[side.index()] && on_boundary(side))),
+                    false,
+                    &|_, _| light[0][0],
+                );
+            }
+        }
+    }
+}
+
+/// `state`のブロック1つを(0, 0, 0)〜(1, 1, 1)に、すべての面を最大の明るさで追加する。
+/// 落ちているアイテムの描画に使う(明るさはシェーダーの`uLightScale`で変える)
+pub fn add_single_block(
+    builder: &mut VaoBuilder,
+    block: &BlockDefinition,
+    state: BlockState,
+    textures: &BlockTextures,
+) {
+    let light = vertex_light_of(1.0, &[[MAX_LIGHT_LEVEL as f32; 4]]);
+    add_block(
+        builder,
+        &BlockPosInWorld::new(Point3::origin()),
+        block,
+        state,
+        textures,
+        &[false; 6],
+        &[[light; 4]; 6],
+    );
+}
+
+/// `visible`が`true`を返す面
+fn cuboid_faces(visible: impl Fn(Side) -> bool) -> CuboidFaces {
+    CuboidFaces {
+        top: visible(Side::TOP),
+        bottom: visible(Side::BOTTOM),
+        south: visible(Side::SOUTH),
+        north: visible(Side::NORTH),
+        west: visible(Side::WEST),
+        east: visible(Side::EAST),
+    }
+}
+
+/// ブロックの対角線上で交差する2枚の板。裏からも見えるように両面を追加する
+fn add_cross(
+    builder: &mut VaoBuilder,
+    begin: &Point3<f32>,
+    texture: &TextureUV,
+    light: &VertexLight,
+) {
+    let corner = |x: f32, y: f32, z: f32| begin + Vector3::new(x, y, z);
+    for &(x0, z0, x1, z1) in &[(0.0, 0.0, 1.0, 1.0), (0.0, 1.0, 1.0, 0.0)] {
+        for &((xa, za), (xb, zb)) in &[((x0, z0), (x1, z1)), ((x1, z1), (x0, z0))] {
+            builder.add_face_with_light(
+                &corner(xa, 1.0, za),
+                &corner(xa, 0.0, za),
+                &corner(xb, 0.0, zb),
+                &corner(xb, 1.0, zb),
+                texture,
+                &[*light; 4],
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point3;
+
+    use super::*;
+    use crate::block::{test_block, test_registry};
+    use crate::texture::block_texture::get_textures_in_atlas;
+
+    fn chunk_at(x: i32, y: i32, z: i32) -> Chunk {
+        Chunk::new(ChunkPos::new(Point3::new(x, y, z)))
+    }
+
+    fn set(chunk: &mut Chunk, name: &str, x: u32, y: u32, z: u32) {
+        chunk.set_block(&test_block(name), &BlockPosInChunk::new(x, y, z).unwrap());
+    }
+
+    /// 追加された面の数
+    fn face_count_with(chunk: &Chunk, neighbors: &ChunkNeighbors, meshing: MeshingMode) -> usize {
+        let registry = test_registry();
+        let textures = get_textures_in_atlas(&registry, 256, 256);
+        chunk
+            .build_mesh(&registry, neighbors, &textures, meshing)
+            .face_count()
+    }
+
+    fn face_count(chunk: &Chunk, neighbors: &ChunkNeighbors) -> usize {
+        face_count_with(chunk, neighbors, MeshingMode::Naive)
+    }
+
+    fn merged_face_count(chunk: &Chunk, neighbors: &ChunkNeighbors) -> usize {
+        face_count_with(chunk, neighbors, MeshingMode::Greedy)
+    }
+
+    #[test]
+    fn hidden_faces_are_culled() {
+        let mut chunk = chunk_at(0, 0, 0);
+        assert_eq!(face_count(&chunk, &[None; 27]), 0);
+
+        set(&mut chunk, "stone", 3, 3, 3);
+        assert_eq!(face_count(&chunk, &[None; 27]), 6);
+
+        set(&mut chunk, "dirt", 4, 3, 3);
+        assert_eq!(face_count(&chunk, &[None; 27]), 10);
+
+        // 3x3x3の立方体の中心のブロックは見えない
+        let mut cube = chunk_at(0, 0, 0);
+        for pos in BlockPosInChunk::all().filter(|p| p.x < 3 && p.y < 3 && p.z < 3) {
+            set(&mut cube, "stone", pos.x, pos.y, pos.z);
+        }
+        assert_eq!(face_count(&cube, &[None; 27]), 6 * 9);
+    }
+
+    #[test]
+    fn transparent_and_partial_blocks_do_not_hide_faces() {
+        let mut chunk = chunk_at(0, 0, 0);
+        set(&mut chunk, "stone", 3, 3, 3);
+        set(&mut chunk, "poppy", 4, 3, 3);
+        // 草花は両面の板2枚
+        assert_eq!(face_count(&chunk, &[None; 27]), 6 + 4);
+
+        let mut chunk = chunk_at(0, 0, 0);
+        set(&mut chunk, "stone", 3, 3, 3);
+        set(&mut chunk, "stone_slab", 4, 3, 3);
+        set(&mut chunk, "stone", 4, 4, 3);
+        // ハーフブロックの側面は石に隠れるが、上面はブロックの境界に無いので隠れない
+        assert_eq!(face_count(&chunk, &[None; 27]), 6 + 5 + 6);
+    }
+
+    #[test]
+    fn faces_are_culled_across_chunk_borders() {
+        let mut full = chunk_at(0, 0, 0);
+        for pos in BlockPosInChunk::all() {
+            set(&mut full, "stone", pos.x, pos.y, pos.z);
+        }
+        assert_eq!(face_count(&full, &[None; 27]), 6 * 256);
+
+        let mut north = chunk_at(1, 0, 0);
+        for pos in BlockPosInChunk::all() {
+            set(&mut north, "stone", pos.x, pos.y, pos.z);
+        }
+        let mut neighbors: ChunkNeighbors = [None; 27];
+        neighbors[neighbor_index(Side::NORTH.normal())] = Some(&north);
+        assert_eq!(face_count(&full, &neighbors), 5 * 256);
+
+        // 境界に接しているブロックだけが隣のチャンクを調べる
+        let mut single = chunk_at(0, 0, 0);
+        set(&mut single, "stone", 15, 0, 0);
+        set(&mut single, "stone", 14, 0, 0);
+        assert_eq!(face_count(&single, &neighbors), 9);
+        let empty = chunk_at(1, 0, 0);
+        neighbors[neighbor_index(Side::NORTH.normal())] = Some(&empty);
+        assert_eq!(face_count(&single, &neighbors), 10);
+    }
+
+    #[test]
+    fn greedy_meshing_merges_faces() {
+        let mut floor = chunk_at(0, 0, 0);
+        for x in 0..16 {
+            for z in 0..16 {
+                set(&mut floor, "grass_block", x, 0, z);
+            }
+        }
+        assert_eq!(face_count(&floor, &[None; 27]), 256 * 2 + 16 * 4);
+        assert_eq!(merged_face_count(&floor, &[None; 27]), 6);
+
+        // テクスチャが違う面はまとめない
+        let mut checker = chunk_at(0, 0, 0);
+        for x in 0..16 {
+            for z in 0..16 {
+                let name = if (x + z) % 2 == 0 { "stone" } else { "dirt" };
+                set(&mut checker, name, x, 0, z);
+            }
+        }
+        assert_eq!(merged_face_count(&checker, &[None; 27]), 256 * 2 + 16 * 4);
+
+        // 縦横に広げられる部分だけをまとめる
+        let mut l_shape = chunk_at(0, 0, 0);
+        for pos in BlockPosInChunk::all().filter(|p| p.y == 0 && p.x < 4 && p.z < 4) {
+            set(&mut l_shape, "stone", pos.x, 0, pos.z);
+        }
+        set(&mut l_shape, "stone", 4, 0, 0);
+        // (4, 0, 0)の影で明るさが変わる北向きの面(3, 0, 1)はまとめない
+        assert_eq!(merged_face_count(&l_shape, &[None; 27]), 2 + 2 + 7);
+
+        // 立方体でないブロックはまとめずにそのまま追加する
+        set(&mut floor, "poppy", 3, 1, 3);
+        set(&mut floor, "stone_slab", 5, 1, 5);
+        assert_eq!(merged_face_count(&floor, &[None; 27]), 6 + 4 + 5);
+
+        // 隣のチャンクに隠れた面はまとめる前に取り除く
+        let mut neighbors: ChunkNeighbors = [None; 27];
+        let mut above = chunk_at(0, 1, 0);
+        for pos in BlockPosInChunk::all() {
+            set(&mut above, "stone", pos.x, pos.y, pos.z);
+        }
+        neighbors[neighbor_index(Side::TOP.normal())] = Some(&above);
+        let mut full = chunk_at(0, 0, 0);
+        for pos in BlockPosInChunk::all() {
+            set(&mut full, "stone", pos.x, pos.y, pos.z);
+        }
+        assert_eq!(merged_face_count(&full, &neighbors), 5);
+    }
+
+    #[test]
+    fn blocks_are_split_into_render_layers() {
+        let registry = test_registry();
+        let textures = get_textures_in_atlas(&registry, 256, 256);
+        let mut chunk = chunk_at(0, 0, 0);
+        set(&mut chunk, "stone", 3, 3, 3);
+        set(&mut chunk, "glass", 4, 3, 3);
+        set(&mut chunk, "glass", 5, 3, 3);
+        set(&mut chunk, "poppy", 3, 4, 3);
+
+        let faces = |layer: RenderLayer, meshing: MeshingMode| {
+            chunk
+                .build_mesh(&registry, &[None; 27], &textures, meshing)
+                .layer(layer)
+                .triangle_count()
+                / 2
+        };
+        for meshing in [MeshingMode::Naive, MeshingMode::Greedy] {
+            // ガラスは石の面を隠さない
+            assert_eq!(faces(RenderLayer::Opaque, meshing), 6);
+            assert_eq!(faces(RenderLayer::Cutout, meshing), 4);
+            // 並んだガラスの間の面と、石に隠れる面は無い
+            assert_eq!(faces(RenderLayer::Translucent, meshing), 9);
+        }
+    }
+
+    #[test]
+    fn textures_of_rotated_blocks_are_rotated() {
+        let registry = test_registry();
+        let textures = get_textures_in_atlas(&registry, 256, 256);
+        let side = textures.get("log_side").unwrap();
+        let mut chunk = chunk_at(0, 0, 0);
+        let log = registry.parse_state("oak_log[axis=x]").unwrap();
+        chunk.set_state(&log, &BlockPosInChunk::new(3, 3, 3).unwrap());
+
+        for meshing in [MeshingMode::Naive, MeshingMode::Greedy] {
+            let meshes = chunk.build_mesh(&registry, &[None; 27], &textures, meshing);
+            let mesh = meshes.layer(RenderLayer::Opaque);
+            // 頂点属性の法線は面の内側を向く
+            let top: Vec<_> = (0..mesh.vertex_count())
+                .map(|i| mesh.vertex(i))
+                .filter(|v| v[3..6] == [0.0, -1.0, 0.0])
+                .collect();
+            assert_eq!(top.len(), 4);
+            for a in &top {
+                // 上面には側面のテクスチャが貼られる
+                assert!(side.begin_u <= a[6] && a[6] <= side.end_u);
+                assert!(side.begin_v <= a[7] && a[7] <= side.end_v);
+                for b in &top {
+                    // 横倒しの丸太の側面の模様はx軸に沿って伸びる
+                    assert_eq!(b[0].partial_cmp(&a[0]), b[7].partial_cmp(&a[7]));
+                    assert_eq!(b[2].partial_cmp(&a[2]), b[6].partial_cmp(&a[6]));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ambient_occlusion_levels() {
+        let registry = test_registry();
+        let levels_at = |chunk: &Chunk, x: u32, z: u32, neighbors: &ChunkNeighbors| {
+            chunk.ao_levels(
+                &BlockPosInChunk::new(x, 0, z).unwrap(),
+                Side::TOP,
+                &registry,
+                neighbors,
+            )
+        };
+        let levels =
+            |chunk: &Chunk, x: u32, neighbors: &ChunkNeighbors| levels_at(chunk, x, 5, neighbors);
+
+        // 上面の頂点の並びは(z, x)の順に負の側が0、正の側が1
+        let mut chunk = chunk_at(0, 0, 0);
+        set(&mut chunk, "stone", 5, 0, 5);
+        assert_eq!(levels(&chunk, 5, &[None; 27]), [3; 4]);
+        set(&mut chunk, "stone", 6, 1, 5);
+        assert_eq!(levels(&chunk, 5, &[None; 27]), [3, 3, 2, 2]);
+        // 両側を塞がれた頂点は、斜めのブロックに関係なく最も暗い
+        set(&mut chunk, "stone", 5, 1, 4);
+        assert_eq!(levels(&chunk, 5, &[None; 27]), [2, 3, 0, 2]);
+        // 透明なブロックは光を遮らない
+        set(&mut chunk, "poppy", 4, 1, 6);
+        assert_eq!(levels(&chunk, 5, &[None; 27]), [2, 3, 0, 2]);
+
+        // 隣のチャンクのブロックも見る
+        let mut edge = chunk_at(0, 0, 0);
+        set(&mut edge, "stone", 15, 0, 5);
+        let mut north = chunk_at(1, 0, 0);
+        set(&mut north, "stone", 0, 1, 5);
+        let mut neighbors: ChunkNeighbors = [None; 27];
+        neighbors[neighbor_index(Side::NORTH.normal())] = Some(&north);
+        assert_eq!(levels(&edge, 15, &neighbors), [3, 3, 2, 2]);
+
+        // 斜めに接しているチャンクのブロックも見る
+        let mut corner = chunk_at(0, 0, 0);
+        set(&mut corner, "stone", 15, 0, 15);
+        let mut diagonal = chunk_at(1, 0, 1);
+        set(&mut diagonal, "stone", 0, 1, 0);
+        let mut neighbors: ChunkNeighbors = [None; 27];
+        assert_eq!(levels_at(&corner, 15, 15, &neighbors), [3; 4]);
+        neighbors[neighbor_index([1, 0, 1])] = Some(&diagonal);
+        assert_eq!(levels_at(&corner, 15, 15, &neighbors), [3, 3, 3, 2]);
+    }
+
+    /// 地形のチャンクを作ったときの頂点の数と時間
+    ///
+    /// `cargo test --release -- --ignored --nocapture meshing_benchmark`で実行する
+    #[test]
+    #[ignore]
+    fn meshing_benchmark() {
+        use crate::world_generator::{FlatGenerator, NoiseGenerator, WorldGenerator};
+        use std::time::Instant;
+
+        const ROUNDS: u32 = 50;
+
+        let registry = test_registry();
+        let textures = get_textures_in_atlas(&registry, 256, 256);
+        let generators: [(&str, Box<dyn WorldGenerator>); 2] = [
+            ("flat", Box::new(FlatGenerator::default())),
+            ("noise", Box::new(NoiseGenerator::default())),
+        ];
+        for (name, generator) in generators.iter() {
+            let mut chunk = chunk_at(0, 0, 0);
+            generator.fill_chunk(&mut chunk, 0, &registry);
+            for meshing in &[MeshingMode::Naive, MeshingMode::Greedy] {
+                let start = Instant::now();
+                let mut vertices = 0;
+                for _ in 0..ROUNDS {
+                    vertices = chunk
+                        .build_mesh(&registry, &[None; 27], &textures, *meshing)
+                        .layers
+                        .iter()
+                        .map(|mesh| mesh.vertex_count())
+                        .sum();
+                }
+                println!(
+                    "{:>6} {:>7}: {:>6} vertices, {:>8.3} ms/chunk",
+                    name,
+                    format!("{:?}", meshing),
+                    vertices,
+                    start.elapsed().as_secs_f64() * 1000.0 / ROUNDS as f64
+                );
+            }
+        }
+    }
+}
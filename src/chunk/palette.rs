@@ -0,0 +1,353 @@
+//! パレットとビット詰めしたインデックスによるブロックの保存
+//!
+//! チャンクの中に現れる値の種類は少ないので、値そのものではなくパレットの
+//! インデックスを必要なビット数だけで保存する。
+//! すべて同じ値のチャンク(空気だけ、石だけなど)はパレットすら持たない。
+
+/// 1つの値しか無いときに使わない最小のビット数
+const MIN_BITS: u32 = 4;
+
+/// `len`個の値をパレットとビット詰めしたインデックスで保存する
+#[derive(Clone, Debug)]
+pub struct PalettedStorage<T> {
+    len: usize,
+    data: Data<T>,
+}
+
+#[derive(Clone, Debug)]
+enum Data<T> {
+    /// すべての値が同じ
+    Single(T),
+    /// `words`の各u64に`64 / bits`個のインデックスを下位ビットから詰める。
+    /// インデックスが2つのu64にまたがることはない
+    Packed {
+        palette: Vec<T>,
+        bits: u32,
+        words: Vec<u64>,
+    },
+}
+
+impl<T: Copy + PartialEq> PalettedStorage<T> {
+    /// すべての値が`value`である`len`個の値を作る
+    pub fn new(len: usize, value: T) -> Self {
+        Self {
+            len,
+            data: Data::Single(value),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> T {
+        assert!(index < self.len, "index {} out of range", index);
+        match &self.data {
+            Data::Single(value) => *value,
+            Data::Packed {
+                palette,
+                bits,
+                words,
+            } => palette[read_index(words, *bits, index)],
+        }
+    }
+
+    /// `index`番目の値を`value`にし、元の値を返す
+    pub fn set(&mut self, index: usize, value: T) -> T {
+        assert!(index < self.len, "index {} out of range", index);
+        if let Data::Single(single) = self.data {
+            if single == value {
+                return single;
+            }
+            self.data = Data::Packed {
+                palette: vec![single],
+                bits: MIN_BITS,
+                words: vec![0; words_len(self.len, MIN_BITS)],
+            };
+        }
+
+        let Data::Packed {
+            palette,
+            bits,
+            words,
+        } = &mut self.data
+        else {
+            unreachable!()
+        };
+        let palette_index = match palette.iter().position(|v| *v == value) {
+            Some(palette_index) => palette_index,
+            None => {
+                palette.push(value);
+                let required = bits_for(palette.len());
+                if required > *bits {
+                    *words = repack(words, *bits, required, self.len);
+                    *bits = required;
+                }
+                palette.len() - 1
+            }
+        };
+        let old = read_index(words, *bits, index);
+        write_index(words, *bits, index, palette_index);
+        palette[old]
+    }
+
+    /// すべての値を`value`にする
+    pub fn fill(&mut self, value: T) {
+        self.data = Data::Single(value);
+    }
+
+    /// 使われなくなったパレットの値を取り除き、インデックスのビット数を減らす
+    ///
+    /// すべて同じ値ならパレットも捨てる
+    pub fn compact(&mut self) {
+        let Data::Packed {
+            palette,
+            bits,
+            words,
+        } = &self.data
+        else {
+            return;
+        };
+
+        let mut used = vec![false; palette.len()];
+        for index in 0..self.len {
+            used[read_index(words, *bits, index)] = true;
+        }
+        let used_count = used.iter().filter(|u| **u).count();
+        if used_count == 1 {
+            let value = palette[used.iter().position(|u| *u).unwrap()];
+            self.data = Data::Single(value);
+            return;
+        }
+        if used_count == palette.len() && bits_for(palette.len()) == *bits {
+            return;
+        }
+
+        let mut remap = vec![0; palette.len()];
+        let mut new_palette = Vec::with_capacity(used_count);
+        for (old_index, value) in palette.iter().enumerate() {
+            if used[old_index] {
+                remap[old_index] = new_palette.len();
+                new_palette.push(*value);
+            }
+        }
+        let new_bits = bits_for(new_palette.len());
+        let mut new_words = vec![0; words_len(self.len, new_bits)];
+        for index in 0..self.len {
+            let old = read_index(words, *bits, index);
+            write_index(&mut new_words, new_bits, index, remap[old]);
+        }
+        self.data = Data::Packed {
+            palette: new_palette,
+            bits: new_bits,
+            words: new_words,
+        };
+    }
+
+    /// すべての値が同じならその値
+    pub fn single_value(&self) -> Option<T> {
+        match &self.data {
+            Data::Single(value) => Some(*value),
+            Data::Packed { .. } => None,
+        }
+    }
+
+    /// 値を先頭から順に返す
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..self.len).map(move |index| self.get(index))
+    }
+
+    /// ヒープに確保しているバイト数
+    pub fn heap_size(&self) -> usize {
+        match &self.data {
+            Data::Single(_) => 0,
+            Data::Packed { palette, words, .. } => {
+                palette.capacity() * std::mem::size_of::<T>()
+                    + words.capacity() * std::mem::size_of::<u64>()
+            }
+        }
+    }
+}
+
+/// `palette_len`種類のインデックスを表すのに必要なビット数
+fn bits_for(palette_len: usize) -> u32 {
+    let bits = usize::BITS - (palette_len - 1).leading_zeros();
+    bits.max(MIN_BITS)
+}
+
+fn words_len(len: usize, bits: u32) -> usize {
+    len.div_ceil((64 / bits) as usize)
+}
+
+fn read_index(words: &[u64], bits: u32, index: usize) -> usize {
+    let per_word = (64 / bits) as usize;
+    let shift = (index % per_word) as u32 * bits;
+    let mask = (1u64 << bits) - 1;
+    ((words[index / per_word] >> shift) & mask) as usize
+}
+
+fn write_index(words: &mut [u64], bits: u32, index: usize, value: usize) {
+    let per_word = (64 / bits) as usize;
+    let shift = (index % per_word) as u32 * bits;
+    let mask = ((1u64 << bits) - 1) << shift;
+    let word = &mut words[index / per_word];
+    *word = (*word & !mask) | ((value as u64) << shift);
+}
+
+fn repack(words: &[u64], old_bits: u32, new_bits: u32, len: usize) -> Vec<u64> {
+    let mut new_words = vec![0; words_len(len, new_bits)];
+    for index in 0..len {
+        write_index(
+            &mut new_words,
+            new_bits,
+            index,
+            read_index(words, old_bits, index),
+        );
+    }
+    new_words
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::block::Block;
+
+    const LEN: usize = 16 * 16 * 16;
+
+    #[test]
+    fn single_value() {
+        let mut storage = PalettedStorage::new(LEN, None::<Block>);
+        assert_eq!(storage.single_value(), Some(None));
+        assert_eq!(storage.heap_size(), 0);
+        assert_eq!(storage.set(10, None), None);
+        assert_eq!(storage.single_value(), Some(None));
+        assert!(storage.iter().all(|block| block.is_none()));
+    }
+
+    #[test]
+    fn set_and_get() {
+        let mut storage = PalettedStorage::new(LEN, None);
+        let mut expected = vec![None; LEN];
+        for index in (0..LEN).step_by(3) {
+            let block = Block::new(index as u16 % 3 + 1);
+            assert_eq!(storage.set(index, Some(block)), None);
+            expected[index] = Some(block);
+        }
+        assert_eq!(storage.set(3, None), expected[3]);
+        expected[3] = None;
+        assert!(storage.iter().eq(expected.iter().copied()));
+    }
+
+    #[test]
+    fn grows_past_sixteen_values() {
+        let mut storage = PalettedStorage::new(LEN, 0u32);
+        for index in 0..LEN {
+            storage.set(index, (index % 300) as u32);
+        }
+        for index in 0..LEN {
+            assert_eq!(storage.get(index), (index % 300) as u32);
+        }
+    }
+
+    #[test]
+    fn compact() {
+        let mut storage = PalettedStorage::new(LEN, 0u32);
+        for index in 0..LEN {
+            storage.set(index, (index % 40) as u32);
+        }
+        let large = storage.heap_size();
+        for index in 0..LEN {
+            storage.set(index, (index % 2) as u32);
+        }
+        storage.compact();
+        assert!(storage.heap_size() < large);
+        for index in 0..LEN {
+            assert_eq!(storage.get(index), (index % 2) as u32);
+        }
+
+        storage.fill(7);
+        storage.set(0, 1);
+        storage.set(0, 7);
+        assert_eq!(storage.single_value(), None);
+        storage.compact();
+        assert_eq!(storage.single_value(), Some(7));
+    }
+
+    /// `Vec<Option<Block>>`と比べたメモリ使用量と読み書きの速さ
+    ///
+    /// `cargo test --release -- --ignored --nocapture palette_benchmark`で実行する
+    #[test]
+    #[ignore]
+    fn palette_benchmark() {
+        const ROUNDS: usize = 200;
+
+        type Pattern = fn(usize) -> Option<Block>;
+        let patterns: [(&str, Pattern); 3] = [
+            ("air", |_| None),
+            ("terrain", |index| match index / 256 {
+                0..=5 => Some(Block::new(3)),
+                6..=8 => Some(Block::new(2)),
+                9 => Some(Block::new(1)),
+                _ => None,
+            }),
+            ("mixed", |index| {
+                let n = index.wrapping_mul(2_654_435_761) >> 7;
+                [
+                    None,
+                    Some(Block::new(3)),
+                    Some(Block::new(2)),
+                    Some(Block::new(1)),
+                ][n % 4]
+            }),
+        ];
+
+        for (name, pattern) in patterns.iter() {
+            let start = Instant::now();
+            let mut vec = Vec::new();
+            for _ in 0..ROUNDS {
+                vec = vec![None; LEN];
+                for (index, block) in vec.iter_mut().enumerate() {
+                    *block = pattern(index);
+                }
+            }
+            let vec_write = start.elapsed();
+            let start = Instant::now();
+            let mut vec_count = 0;
+            for _ in 0..ROUNDS {
+                vec_count += vec.iter().filter(|block| block.is_some()).count();
+            }
+            let vec_read = start.elapsed();
+
+            let start = Instant::now();
+            let mut storage = PalettedStorage::new(LEN, None);
+            for _ in 0..ROUNDS {
+                storage = PalettedStorage::new(LEN, None);
+                for index in 0..LEN {
+                    storage.set(index, pattern(index));
+                }
+                storage.compact();
+            }
+            let palette_write = start.elapsed();
+            let start = Instant::now();
+            let mut palette_count = 0;
+            for _ in 0..ROUNDS {
+                palette_count += storage.iter().filter(|block| block.is_some()).count();
+            }
+            let palette_read = start.elapsed();
+            assert_eq!(vec_count, palette_count);
+
+            println!(
+                "{:8} Vec<Option<Block>>: {:6} bytes, write {:?}, read {:?}",
+                name,
+                vec.capacity() * std::mem::size_of::<Option<Block>>(),
+                vec_write / ROUNDS as u32,
+                vec_read / ROUNDS as u32,
+            );
+            println!(
+                "{:8} PalettedStorage:    {:6} bytes, write {:?}, read {:?}",
+                name,
+                storage.heap_size(),
+                palette_write / ROUNDS as u32,
+                palette_read / ROUNDS as u32,
+            );
+        }
+    }
+}
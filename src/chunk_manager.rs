@@ -0,0 +1,320 @@
+//! プレイヤーの周りのチャンクの読み込みと解放
+
+use std::io;
+
+use nalgebra::Point3;
+
+use crate::chunk_workers::{ChunkWorkers, JobKind};
+use crate::mymath::{BlockPosInWorld, ChunkPos};
+use crate::world::GameWorld;
+
+/// プレイヤーを中心とした一定範囲のチャンクだけを読み込んだ状態に保つ
+pub struct ChunkManager {
+    /// 水平方向に読み込むチャンクの数(中心からの距離)
+    radius: i32,
+    /// 垂直方向に読み込むチャンクの数(中心からの距離)
+    vertical_radius: i32,
+    /// 前回`update`したときの中心
+    center: Option<ChunkPos>,
+}
+
+/// `ChunkManager::update`で読み込まれたチャンクと解放されたチャンク
+#[derive(Default, Debug)]
+pub struct ChunkChanges {
+    pub loaded: Vec<ChunkPos>,
+    pub unloaded: Vec<ChunkPos>,
+    /// ブロックや光が変わったチャンク(see: `GameWorld::take_changed_chunks`)
+    pub modified: Vec<ChunkPos>,
+}
+
+impl ChunkChanges {
+    pub fn is_empty(&self) -> bool {
+        self.loaded.is_empty() && self.unloaded.is_empty() && self.modified.is_empty()
+    }
+}
+
+impl ChunkManager {
+    pub fn new(radius: i32, vertical_radius: i32) -> Self {
+        Self {
+            radius,
+            vertical_radius,
+            center: None,
+        }
+    }
+
+    /// `center`を中心としたときに`pos`が読み込み範囲に入っているか
+    pub fn is_in_range(&self, center: &ChunkPos, pos: &ChunkPos) -> bool {
+        (pos.x - center.x).abs() <= self.radius
+            && (pos.z - center.z).abs() <= self.radius
+            && (pos.y - center.y).abs() <= self.vertical_radius
+    }
+
+    /// `player_pos`の周りのチャンクを読み込み、範囲外になったチャンクを解放する
+    ///
+    /// 保存されていないチャンクは`workers`で生成し、生成し終わったものから読み込む。
+    /// プレイヤーのいるチャンクが前回から変わっていなければ、生成し終わったチャンクを読み込むだけ。
+    ///
+    /// 読み込みや保存に失敗したチャンクは飛ばして、ほかのチャンクの変化と最初のエラーを返す。
+    /// 失敗したチャンクは次に`update`を呼んだときにやり直す
+    pub fn update(
+        &mut self,
+        world: &mut GameWorld,
+        player_pos: &Point3<f32>,
+        workers: &mut ChunkWorkers,
+    ) -> (ChunkChanges, Option<io::Error>) {
+        let center = BlockPosInWorld::from_point(player_pos).chunk_pos();
+        let mut changes = ChunkChanges::default();
+        let mut error = None;
+
+        if self.center != Some(center) {
+            let out_of_range: Vec<ChunkPos> = world
+                .loaded_chunks()
+                .filter(|pos| !self.is_in_range(&center, pos))
+                .copied()
+                .collect();
+            for pos in out_of_range {
+                match world.unload_chunk(&pos) {
+                    Ok(()) => changes.unloaded.push(pos),
+                    Err(err) => {
+                        error.get_or_insert(err);
+                    }
+                }
+            }
+            let cancelled: Vec<ChunkPos> = workers
+                .pending(JobKind::Generate)
+                .filter(|pos| !self.is_in_range(&center, pos))
+                .copied()
+                .collect();
+            for pos in cancelled {
+                workers.cancel(JobKind::Generate, &pos);
+            }
+
+            let mut missing = Vec::new();
+            for x in -self.radius..=self.radius {
+                for y in -self.vertical_radius..=self.vertical_radius {
+                    for z in -self.radius..=self.radius {
+                        let pos =
+                            ChunkPos::new(Point3::new(center.x + x, center.y + y, center.z + z));
+                        if !world.is_loaded(&pos) && !workers.is_pending(JobKind::Generate, &pos) {
+                            missing.push(pos);
+                        }
+                    }
+                }
+            }
+            // 近いチャンクから読み込む
+            missing.sort_by_key(|pos| {
+                (pos.x - center.x).abs() + (pos.y - center.y).abs() + (pos.z - center.z).abs()
+            });
+            for pos in missing {
+                match world.load_saved_chunk(&pos) {
+                    Ok(true) => changes.loaded.push(pos),
+                    Ok(false) => workers.generate(
+                        pos,
+                        world.shared_generator(),
+                        world.seed(),
+                        world.registry().clone(),
+                    ),
+                    // 生成し直したチャンクで保存されているチャンクを上書きしない
+                    Err(err) => {
+                        error.get_or_insert(err);
+                    }
+                }
+            }
+
+            if error.is_none() {
+                self.center = Some(center);
+            }
+        }
+
+        for chunk in workers.take_generated() {
+            let pos = *chunk.position();
+            // 生成している間に`set_block`などで読み込まれていれば、そちらを使う
+            if self.is_in_range(&center, &pos) && world.add_chunk(chunk).is_ok() {
+                changes.loaded.push(pos);
+            }
+        }
+        changes.modified = world.take_changed_chunks();
+        (changes, error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::test_registry;
+    use crate::mymath::chunk_pos;
+    use crate::storage::{BrokenStorage, MemoryChunkStorage};
+    use crate::world_generator::FlatGenerator;
+
+    /// 生成が終わるまで待って読み込む
+    fn update(
+        manager: &mut ChunkManager,
+        world: &mut GameWorld,
+        player_pos: &Point3<f32>,
+        workers: &mut ChunkWorkers,
+    ) -> ChunkChanges {
+        let (mut changes, error) = manager.update(world, player_pos, workers);
+        assert!(error.is_none());
+        workers.wait_idle();
+        let (generated, error) = manager.update(world, player_pos, workers);
+        assert!(error.is_none());
+        assert!(generated.unloaded.is_empty());
+        changes.loaded.extend(generated.loaded);
+        changes
+    }
+
+    #[test]
+    fn loads_chunks_around_player() {
+        let mut world =
+            GameWorld::with_generator(test_registry(), Box::new(FlatGenerator::default()), 0);
+        let mut manager = ChunkManager::new(2, 1);
+
+        // 生成し終わる順番が頼んだ順番と同じになるように、ワーカーは1つにする
+        let mut workers = ChunkWorkers::new(1);
+        let changes = update(
+            &mut manager,
+            &mut world,
+            &Point3::new(4.0, 2.5, 4.0),
+            &mut workers,
+        );
+        assert_eq!(changes.loaded.len(), 5 * 3 * 5);
+        assert_eq!(changes.loaded[0], chunk_pos(0, 0, 0));
+        assert!(changes.unloaded.is_empty());
+        assert!(world.is_loaded(&chunk_pos(-2, -1, 2)));
+        assert!(!world.is_loaded(&chunk_pos(3, 0, 0)));
+
+        // 同じチャンク内で動いても何も起きない
+        let changes = update(
+            &mut manager,
+            &mut world,
+            &Point3::new(7.9, 0.1, 0.1),
+            &mut workers,
+        );
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn unloads_far_chunks() {
+        let mut world =
+            GameWorld::with_generator(test_registry(), Box::new(FlatGenerator::default()), 0);
+        let mut manager = ChunkManager::new(1, 0);
+        let mut workers = ChunkWorkers::new(2);
+        update(
+            &mut manager,
+            &mut world,
+            &Point3::new(1.0, 1.0, 1.0),
+            &mut workers,
+        );
+
+        // 1チャンク = 16ブロック = 8.0
+        let changes = update(
+            &mut manager,
+            &mut world,
+            &Point3::new(9.0, 1.0, 1.0),
+            &mut workers,
+        );
+        assert_eq!(changes.loaded.len(), 3);
+        assert_eq!(changes.unloaded.len(), 3);
+        assert!(changes.unloaded.contains(&chunk_pos(-1, 0, 0)));
+        assert!(!world.is_loaded(&chunk_pos(-1, 0, 1)));
+        assert!(world.is_loaded(&chunk_pos(2, 0, -1)));
+        assert_eq!(world.loaded_chunks().count(), 9);
+    }
+
+    #[test]
+    fn keeps_generated_chunks_when_moving() {
+        let mut world =
+            GameWorld::with_generator(test_registry(), Box::new(FlatGenerator::default()), 0);
+        let mut manager = ChunkManager::new(1, 0);
+        let mut workers = ChunkWorkers::new(2);
+        let (changes, _) = manager.update(&mut world, &Point3::new(1.0, 1.0, 1.0), &mut workers);
+        assert!(changes.loaded.is_empty());
+        workers.wait_idle();
+
+        // 生成し終わったチャンクを取り出す前に隣のチャンクへ移動しても、生成し直さない
+        let (changes, _) = manager.update(&mut world, &Point3::new(9.0, 1.0, 1.0), &mut workers);
+        assert_eq!(changes.loaded.len(), 6);
+        assert_eq!(workers.pending(JobKind::Generate).count(), 3);
+        assert!(workers.pending(JobKind::Generate).all(|pos| pos.x == 2));
+    }
+
+    #[test]
+    fn reports_changes_made_before_errors() {
+        let mut world =
+            GameWorld::with_generator(test_registry(), Box::new(FlatGenerator::default()), 0);
+        let mut manager = ChunkManager::new(1, 0);
+        let mut workers = ChunkWorkers::new(2);
+        update(
+            &mut manager,
+            &mut world,
+            &Point3::new(1.0, 1.0, 1.0),
+            &mut workers,
+        );
+
+        // 解放はできるが、新しく入ったチャンクは読み込めない
+        world.set_storage(Box::new(BrokenStorage));
+        let (changes, error) =
+            manager.update(&mut world, &Point3::new(9.0, 1.0, 1.0), &mut workers);
+        assert!(error.is_some());
+        assert_eq!(changes.unloaded.len(), 3);
+        assert!(!world.is_loaded(&chunk_pos(-1, 0, 0)));
+        assert_eq!(workers.pending(JobKind::Generate).count(), 0);
+
+        // 読み込めなかったチャンクは次の`update`でやり直す
+        world.set_storage(Box::new(MemoryChunkStorage::new()));
+        let changes = update(
+            &mut manager,
+            &mut world,
+            &Point3::new(9.0, 1.0, 1.0),
+            &mut workers,
+        );
+        assert_eq!(changes.loaded.len(), 3);
+        assert!(world.is_loaded(&chunk_pos(2, 0, 0)));
+    }
+
+    #[test]
+    fn negative_player_position() {
+        let mut world = GameWorld::new(test_registry());
+        let mut manager = ChunkManager::new(0, 0);
+        let mut workers = ChunkWorkers::new(1);
+        update(
+            &mut manager,
+            &mut world,
+            &Point3::new(-0.1, -0.1, -8.1),
+            &mut workers,
+        );
+        assert!(world.is_loaded(&chunk_pos(-1, -1, -2)));
+    }
+
+    #[test]
+    fn cancels_generation_out_of_range() {
+        let mut world =
+            GameWorld::with_generator(test_registry(), Box::new(FlatGenerator::default()), 0);
+        let mut manager = ChunkManager::new(1, 0);
+        let mut workers = ChunkWorkers::new(1);
+        // どのジョブも実行される前に取り消されるように、ワーカーを止めておく
+        workers.pause();
+        let (changes, _) = manager.update(&mut world, &Point3::new(1.0, 1.0, 1.0), &mut workers);
+        // 保存されていないチャンクは生成し終わるまで読み込まれない
+        assert!(changes.loaded.is_empty());
+        assert_eq!(workers.pending(JobKind::Generate).count(), 9);
+
+        // 生成し終わる前に遠くへ移動する
+        let (changes, _) = manager.update(&mut world, &Point3::new(41.0, 1.0, 1.0), &mut workers);
+        assert!(changes.loaded.is_empty());
+        assert_eq!(workers.pending(JobKind::Generate).count(), 9);
+        assert!(workers
+            .pending(JobKind::Generate)
+            .all(|pos| (4..=6).contains(&pos.x)));
+
+        // 取り消したジョブのチャンクは生成されない
+        workers.resume();
+        workers.wait_idle();
+        let generated = workers.take_generated();
+        assert_eq!(generated.len(), 9);
+        assert!(generated
+            .iter()
+            .all(|chunk| (4..=6).contains(&chunk.position().x)));
+        assert_eq!(world.loaded_chunks().count(), 0);
+    }
+}
@@ -0,0 +1,243 @@
+//! 読み込まれているチャンクの描画
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use c_str_macro::c_str;
+use nalgebra::{Point3, Vector3};
+use re::gl;
+use re::gl::Gl;
+use re::shader::{Program, Uniform, UniformVariables};
+use re::vao::mesh::Mesh;
+use re::vao::Vao;
+use reverie_engine as re;
+
+use crate::block::RenderLayer;
+use crate::chunk::{neighbor_offset, ChunkMesh, MeshingMode};
+use crate::chunk_manager::ChunkChanges;
+use crate::chunk_workers::{ChunkWorkers, JobKind};
+use crate::game_config;
+use crate::mymath::{BlockPosInWorld, ChunkPos};
+use crate::texture::block_texture::BlockTextures;
+use crate::world::GameWorld;
+
+/// チャンクごとの`Vao`を持ち、チャンクの読み込みや解放に合わせて作り直す
+///
+/// 頂点データは`ChunkWorkers`で作り、GPUに送るのは`upload`でメインスレッドから行う
+pub struct ChunkRenderer<'a> {
+    vaos: HashMap<ChunkPos, ChunkVaos<'a>>,
+    meshing: MeshingMode,
+}
+
+/// 1つのチャンクの、`RenderLayer`ごとの`Vao`
+struct ChunkVaos<'a> {
+    /// `RenderLayer::ALL`の順。面が無ければ`None`
+    layers: [Option<Vao<'a>>; 3],
+    /// 半透明な面をカメラの位置に合わせて並べ替えるために残しておく頂点データ
+    translucent: Mesh,
+    /// 最後に半透明な面を並べ替えたときに、カメラがあったブロック
+    sorted_at: Option<BlockPosInWorld>,
+}
+
+impl<'a> ChunkVaos<'a> {
+    fn new(gl: &Gl, mesh: ChunkMesh, shader: &'a Program) -> Self {
+        let layers = mesh.layers.each_ref().map(|layer| {
+            if layer.is_empty() {
+                None
+            } else {
+                Some(Vao::from_mesh(gl, layer, shader))
+            }
+        });
+        let [_, _, translucent] = mesh.layers;
+        Self {
+            layers,
+            translucent,
+            sorted_at: None,
+        }
+    }
+
+    /// カメラが別のブロックに移っていれば、半透明な面を遠い順に並べ替える
+    ///
+    /// `eye`は描画する座標系でのカメラの位置
+    fn sort_translucent(&mut self, eye: &Point3<f32>) {
+        let vao = match &mut self.layers[RenderLayer::Translucent.index()] {
+            Some(vao) => vao,
+            None => return,
+        };
+        let eye_block = BlockPosInWorld::from_point(eye);
+        if self.sorted_at == Some(eye_block) {
+            return;
+        }
+        // 頂点データの座標系では1ブロックの大きさは1.0
+        let eye = eye.coords * 2.0;
+        self.translucent.sort_quads_back_to_front(&eye.into());
+        vao.update_indices(&self.translucent.indices);
+        self.sorted_at = Some(eye_block);
+    }
+}
+
+impl<'a> ChunkRenderer<'a> {
+    pub fn new(meshing: MeshingMode) -> Self {
+        Self {
+            vaos: HashMap::new(),
+            meshing,
+        }
+    }
+
+    pub fn meshing(&self) -> MeshingMode {
+        self.meshing
+    }
+
+    /// 頂点の作り方を変え、すべてのチャンクの頂点データを作り直す
+    pub fn set_meshing(
+        &mut self,
+        meshing: MeshingMode,
+        world: &GameWorld,
+        workers: &mut ChunkWorkers,
+        textures: &Arc<BlockTextures>,
+    ) {
+        self.meshing = meshing;
+        for pos in world.loaded_chunks() {
+            self.rebuild(pos, world, workers, textures);
+        }
+    }
+
+    /// 読み込まれたチャンクの頂点データを作り、解放されたチャンクの`Vao`を捨てる
+    ///
+    /// 隣のチャンクの頂点データも作り直す。作り終わった頂点データは`upload`でGPUに送る
+    pub fn apply_changes(
+        &mut self,
+        changes: &ChunkChanges,
+        world: &GameWorld,
+        workers: &mut ChunkWorkers,
+        textures: &Arc<BlockTextures>,
+    ) {
+        for pos in &changes.unloaded {
+            self.vaos.remove(pos);
+            workers.cancel(JobKind::Mesh, pos);
+        }
+        // 隣のチャンクが読み込まれたり解放されたりすると、境界の面が見えるかどうかが変わる
+        let mut dirty: HashSet<ChunkPos> = changes.loaded.iter().copied().collect();
+        dirty.extend(changes.modified.iter().filter(|pos| world.is_loaded(pos)));
+        // 角の明るさは斜めに接しているチャンクのブロックでも変わる
+        for pos in changes.loaded.iter().chain(&changes.unloaded) {
+            for offset in (0..27).map(neighbor_offset) {
+                let neighbor = ChunkPos::new(**pos + Vector3::from(offset));
+                if offset != [0, 0, 0] && world.is_loaded(&neighbor) {
+                    dirty.insert(neighbor);
+                }
+            }
+        }
+        for pos in &dirty {
+            self.rebuild(pos, world, workers, textures);
+        }
+    }
+
+    /// チャンクの頂点データを作り直すジョブを`workers`に追加する
+    ///
+    /// チャンクが読み込まれていなければ`Vao`を捨てる
+    pub fn rebuild(
+        &mut self,
+        pos: &ChunkPos,
+        world: &GameWorld,
+        workers: &mut ChunkWorkers,
+        textures: &Arc<BlockTextures>,
+    ) {
+        match world.get_chunk(pos) {
+            Some(chunk) => workers.build_mesh(
+                chunk.clone(),
+                world.chunk_neighbors(pos).map(|chunk| chunk.cloned()),
+                Arc::clone(world.registry()),
+                Arc::clone(textures),
+                self.meshing,
+            ),
+            None => {
+                self.vaos.remove(pos);
+                workers.cancel(JobKind::Mesh, pos);
+            }
+        }
+    }
+
+    /// 作り終わった頂点データを`max`個までGPUに送り、送った数を返す
+    pub fn upload(
+        &mut self,
+        workers: &mut ChunkWorkers,
+        gl: &Gl,
+        shader: &'a Program,
+        max: usize,
+    ) -> usize {
+        let meshes = workers.take_meshes(max);
+        let count = meshes.len();
+        for mesh in meshes {
+            self.vaos
+                .insert(mesh.position, ChunkVaos::new(gl, mesh, shader));
+        }
+        count
+    }
+
+    /// `Vao`を持っているチャンクの数
+    pub fn len(&self) -> usize {
+        self.vaos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vaos.is_empty()
+    }
+
+    /// すべてのチャンクを`RenderLayer::ALL`の順に描画する
+    ///
+    /// 半透明な面は、ブレンドを有効にして、`eye`から遠いチャンクの遠い面から順に描画する。
+    /// `eye`は描画する座標系でのカメラの位置
+    pub fn draw(&mut self, gl: &Gl, uniforms: &mut UniformVariables, eye: &Point3<f32>) {
+        for chunk in self.vaos.values_mut() {
+            chunk.sort_translucent(eye);
+        }
+        for layer in RenderLayer::ALL {
+            // テクスチャの不透明度がこれより小さいところは描画しない
+            let alpha_cutoff = match layer {
+                RenderLayer::Opaque => 0.0,
+                RenderLayer::Cutout => 0.5,
+                RenderLayer::Translucent => 1.0 / 255.0,
+            };
+            uniforms.add(c_str!("uAlphaCutoff"), Uniform::Float(alpha_cutoff));
+            let mut chunks: Vec<(&ChunkPos, &Vao)> = self
+                .vaos
+                .iter()
+                .filter_map(|(pos, chunk)| Some((pos, chunk.layers[layer.index()].as_ref()?)))
+                .collect();
+            unsafe {
+                if layer == RenderLayer::Translucent {
+                    gl.Enable(gl::BLEND);
+                    gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+                    // 半透明な面どうしで隠し合わないようにする
+                    gl.DepthMask(gl::FALSE);
+                    chunks.sort_by(|(a, _), (b, _)| {
+                        chunk_distance(b, eye).total_cmp(&chunk_distance(a, eye))
+                    });
+                } else {
+                    gl.Disable(gl::BLEND);
+                }
+            }
+            for (_, vao) in chunks {
+                vao.draw_triangles(uniforms);
+            }
+        }
+        unsafe {
+            gl.DepthMask(gl::TRUE);
+            gl.Disable(gl::BLEND);
+        }
+    }
+}
+
+/// 描画する座標系で、チャンクの中心から`eye`までの距離の2乗
+fn chunk_distance(pos: &ChunkPos, eye: &Point3<f32>) -> f32 {
+    // 1チャンク = 16ブロック = 8.0
+    let center = pos.cast::<f32>() * 8.0 + nalgebra::Vector3::repeat(4.0);
+    (center - eye).norm_squared()
+}
+
+impl<'a> Default for ChunkRenderer<'a> {
+    fn default() -> Self {
+        Self::new(game_config::MESHING_MODE)
+    }
+}
@@ -0,0 +1,421 @@
+//! チャンクの生成と頂点データの作成を別のスレッドで行う
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::block::BlockRegistry;
+use crate::chunk::{Chunk, ChunkMesh, MeshingMode};
+use crate::light;
+use crate::mymath::ChunkPos;
+use crate::texture::block_texture::BlockTextures;
+use crate::world_generator::{self, WorldGenerator};
+
+/// ジョブの種類
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum JobKind {
+    Generate,
+    Mesh,
+}
+
+enum Task {
+    Generate {
+        generator: Arc<dyn WorldGenerator>,
+        seed: u64,
+        registry: Arc<BlockRegistry>,
+    },
+    /// ワールドは別のスレッドから触れないので、チャンクと隣のチャンクは複製して渡す
+    Mesh {
+        chunk: Box<Chunk>,
+        neighbors: Box<[Option<Chunk>; 27]>,
+        registry: Arc<BlockRegistry>,
+        textures: Arc<BlockTextures>,
+        meshing: MeshingMode,
+    },
+}
+
+enum Output {
+    Generated(Box<Chunk>),
+    Mesh(Box<ChunkMesh>),
+}
+
+struct Job {
+    pos: ChunkPos,
+    kind: JobKind,
+    /// `true`になっていれば、ワーカーはジョブを実行せずに捨てる
+    cancelled: Arc<AtomicBool>,
+    task: Task,
+}
+
+struct Finished {
+    pos: ChunkPos,
+    kind: JobKind,
+    cancelled: Arc<AtomicBool>,
+    output: Output,
+}
+
+impl Task {
+    fn run(self, pos: &ChunkPos) -> Output {
+        match self {
+            Task::Generate {
+                generator,
+                seed,
+                registry,
+            } => {
+                let chunk =
+                    world_generator::generate_chunk(generator.as_ref(), pos, seed, &registry);
+                // メインスレッドでは隣のチャンクとの境界だけ計算すればよいように、中の光はここで計算する
+                let open_sky =
+                    |x, z, y| world_generator::is_open_sky(generator.as_ref(), seed, x, z, y);
+                Output::Generated(Box::new(light::light_chunk_alone(
+                    chunk, &registry, &open_sky,
+                )))
+            }
+            Task::Mesh {
+                chunk,
+                neighbors,
+                registry,
+                textures,
+                meshing,
+            } => Output::Mesh(Box::new(chunk.build_mesh(
+                &registry,
+                &neighbors.each_ref().map(Option::as_ref),
+                &textures,
+                meshing,
+            ))),
+        }
+    }
+}
+
+/// チャンクの生成と頂点データの作成を行うスレッドの集まり
+///
+/// 結果はチャネルで受け取り、`take_generated`や`take_meshes`でメインスレッドに取り出す。
+/// 同じチャンクに同じ種類のジョブを追加すると、古いジョブは取り消される
+pub struct ChunkWorkers {
+    jobs: Option<Sender<Job>>,
+    results: Receiver<Finished>,
+    threads: Vec<JoinHandle<()>>,
+    /// `true`の間、ワーカーは受け取ったジョブを実行せずに待つ
+    paused: Arc<(Mutex<bool>, Condvar)>,
+    /// 結果を待っているジョブ
+    pending: HashMap<(JobKind, ChunkPos), Arc<AtomicBool>>,
+    generated: Vec<Chunk>,
+    meshes: VecDeque<ChunkMesh>,
+}
+
+impl ChunkWorkers {
+    /// `threads`個のワーカースレッドを起動する
+    pub fn new(threads: usize) -> Self {
+        let (job_sender, job_receiver) = mpsc::channel::<Job>();
+        let (result_sender, result_receiver) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let paused = Arc::new((Mutex::new(false), Condvar::new()));
+        let threads = (0..threads.max(1))
+            .map(|i| {
+                let jobs = Arc::clone(&job_receiver);
+                let results = result_sender.clone();
+                let paused = Arc::clone(&paused);
+                thread::Builder::new()
+                    .name(format!("chunk-worker-{}", i))
+                    .spawn(move || loop {
+                        let job = match jobs.lock().unwrap().recv() {
+                            Ok(job) => job,
+                            Err(_) => break,
+                        };
+                        let (lock, resumed) = &*paused;
+                        drop(resumed.wait_while(lock.lock().unwrap(), |paused| *paused));
+                        if job.cancelled.load(Ordering::Relaxed) {
+                            continue;
+                        }
+                        let output = job.task.run(&job.pos);
+                        let finished = Finished {
+                            pos: job.pos,
+                            kind: job.kind,
+                            cancelled: job.cancelled,
+                            output,
+                        };
+                        if results.send(finished).is_err() {
+                            break;
+                        }
+                    })
+                    .expect("Failed to spawn chunk worker")
+            })
+            .collect();
+
+        Self {
+            jobs: Some(job_sender),
+            results: result_receiver,
+            threads,
+            paused,
+            pending: HashMap::new(),
+            generated: Vec::new(),
+            meshes: VecDeque::new(),
+        }
+    }
+
+    fn submit(&mut self, pos: ChunkPos, kind: JobKind, task: Task) {
+        self.cancel(kind, &pos);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.pending.insert((kind, pos), Arc::clone(&cancelled));
+        let job = Job {
+            pos,
+            kind,
+            cancelled,
+            task,
+        };
+        // 送れないのはワーカーがすべて止まっているときだけ
+        if self.jobs.as_ref().unwrap().send(job).is_err() {
+            self.pending.remove(&(kind, pos));
+        }
+    }
+
+    /// `pos`の位置のチャンクを生成するジョブを追加する
+    pub fn generate(
+        &mut self,
+        pos: ChunkPos,
+        generator: Arc<dyn WorldGenerator>,
+        seed: u64,
+        registry: Arc<BlockRegistry>,
+    ) {
+        let task = Task::Generate {
+            generator,
+            seed,
+            registry,
+        };
+        self.submit(pos, JobKind::Generate, task);
+    }
+
+    /// `chunk`の頂点データを作るジョブを追加する
+    ///
+    /// `neighbors`は`Chunk::build_mesh`と同じく`neighbor_index`の順の周りのチャンク
+    pub fn build_mesh(
+        &mut self,
+        chunk: Chunk,
+        neighbors: [Option<Chunk>; 27],
+        registry: Arc<BlockRegistry>,
+        textures: Arc<BlockTextures>,
+        meshing: MeshingMode,
+    ) {
+        let pos = *chunk.position();
+        let task = Task::Mesh {
+            chunk: Box::new(chunk),
+            neighbors: Box::new(neighbors),
+            registry,
+            textures,
+            meshing,
+        };
+        self.submit(pos, JobKind::Mesh, task);
+    }
+
+    /// ジョブを取り消す。結果が届いていてまだ取り出されていなければ、それも捨てる
+    pub fn cancel(&mut self, kind: JobKind, pos: &ChunkPos) {
+        if let Some(cancelled) = self.pending.remove(&(kind, *pos)) {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+        match kind {
+            JobKind::Generate => self.generated.retain(|chunk| chunk.position() != pos),
+            JobKind::Mesh => self.meshes.retain(|mesh| mesh.position != *pos),
+        }
+    }
+
+    /// 結果を待っているジョブがあるか、届いた結果がまだ取り出されていないか
+    pub fn is_pending(&self, kind: JobKind, pos: &ChunkPos) -> bool {
+        self.pending.contains_key(&(kind, *pos))
+            || match kind {
+                JobKind::Generate => self.generated.iter().any(|chunk| chunk.position() == pos),
+                JobKind::Mesh => self.meshes.iter().any(|mesh| mesh.position == *pos),
+            }
+    }
+
+    /// 結果を待っているジョブのチャンクの位置
+    pub fn pending(&self, kind: JobKind) -> impl Iterator<Item = &ChunkPos> {
+        self.pending
+            .keys()
+            .filter(move |(k, _)| *k == kind)
+            .map(|(_, pos)| pos)
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    fn receive(&mut self, finished: Finished) {
+        let key = (finished.kind, finished.pos);
+        // 取り消されたジョブや、新しいジョブに置き換えられたジョブの結果は捨てる
+        match self.pending.get(&key) {
+            Some(cancelled) if Arc::ptr_eq(cancelled, &finished.cancelled) => {
+                self.pending.remove(&key);
+            }
+            _ => return,
+        }
+        match finished.output {
+            Output::Generated(chunk) => self.generated.push(*chunk),
+            Output::Mesh(mesh) => self.meshes.push_back(*mesh),
+        }
+    }
+
+    fn receive_all(&mut self) {
+        while let Ok(finished) = self.results.try_recv() {
+            self.receive(finished);
+        }
+    }
+
+    /// 生成が終わったチャンクを取り出す
+    pub fn take_generated(&mut self) -> Vec<Chunk> {
+        self.receive_all();
+        std::mem::take(&mut self.generated)
+    }
+
+    /// 作り終わった頂点データを、終わった順に`max`個まで取り出す
+    pub fn take_meshes(&mut self, max: usize) -> Vec<ChunkMesh> {
+        self.receive_all();
+        let count = max.min(self.meshes.len());
+        self.meshes.drain(..count).collect()
+    }
+
+    /// ワーカーがジョブを実行しないようにする。取り消しを確かめるテストで使う
+    #[cfg(test)]
+    pub fn pause(&self) {
+        *self.paused.0.lock().unwrap() = true;
+    }
+
+    /// `pause`で止めたワーカーを動かす
+    pub fn resume(&self) {
+        let (lock, resumed) = &*self.paused;
+        *lock.lock().unwrap() = false;
+        resumed.notify_all();
+    }
+
+    /// 結果を待っているジョブがすべて終わるまで待つ
+    pub fn wait_idle(&mut self) {
+        while !self.pending.is_empty() {
+            match self.results.recv() {
+                Ok(finished) => self.receive(finished),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl Default for ChunkWorkers {
+    /// メインスレッドの分を残して、CPUのコア数だけワーカーを起動する
+    fn default() -> Self {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get().saturating_sub(1));
+        Self::new(threads)
+    }
+}
+
+impl Drop for ChunkWorkers {
+    fn drop(&mut self) {
+        for cancelled in self.pending.values() {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+        self.resume();
+        // チャネルを閉じるとワーカーはループを抜ける
+        self.jobs = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{test_block, test_registry};
+    use crate::mymath::{chunk_pos, BlockPosInChunk};
+    use crate::texture::block_texture::get_textures_in_atlas;
+    use crate::world_generator::FlatGenerator;
+
+    fn generate(workers: &mut ChunkWorkers, pos: ChunkPos) {
+        workers.generate(pos, Arc::new(FlatGenerator::default()), 0, test_registry());
+    }
+
+    #[test]
+    fn generates_chunks_on_workers() {
+        let mut workers = ChunkWorkers::new(2);
+        for x in 0..4 {
+            generate(&mut workers, chunk_pos(x, -1, 0));
+        }
+        assert!(workers.is_pending(JobKind::Generate, &chunk_pos(3, -1, 0)));
+        workers.wait_idle();
+        assert!(workers.is_idle());
+        // 取り出すまでは結果を待っているものとして扱う
+        assert!(workers.is_pending(JobKind::Generate, &chunk_pos(3, -1, 0)));
+
+        let generated = workers.take_generated();
+        assert_eq!(generated.len(), 4);
+        let expected = world_generator::generate_chunk(
+            &FlatGenerator::default(),
+            &chunk_pos(0, -1, 0),
+            0,
+            &test_registry(),
+        );
+        let chunk = generated
+            .iter()
+            .find(|chunk| *chunk.position() == chunk_pos(0, -1, 0))
+            .unwrap();
+        assert!(BlockPosInChunk::all().all(|pos| chunk.get_block(&pos) == expected.get_block(&pos)));
+        // 中の光はワーカーで計算する
+        assert!(chunk.is_lit());
+        assert!(workers.take_generated().is_empty());
+        assert!(!workers.is_pending(JobKind::Generate, &chunk_pos(3, -1, 0)));
+    }
+
+    #[test]
+    fn cancelled_jobs_are_dropped() {
+        let mut workers = ChunkWorkers::new(1);
+        workers.pause();
+        for x in 0..8 {
+            generate(&mut workers, chunk_pos(x, 0, 0));
+        }
+        workers.cancel(JobKind::Generate, &chunk_pos(7, 0, 0));
+        // 同じチャンクのジョブを追加し直すと、古いジョブの結果は捨てられる
+        generate(&mut workers, chunk_pos(0, 0, 0));
+        workers.resume();
+        workers.wait_idle();
+
+        let generated = workers.take_generated();
+        assert_eq!(generated.len(), 7);
+        assert!(generated
+            .iter()
+            .all(|chunk| *chunk.position() != chunk_pos(7, 0, 0)));
+        assert_eq!(
+            generated
+                .iter()
+                .filter(|chunk| *chunk.position() == chunk_pos(0, 0, 0))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn builds_meshes_with_budget() {
+        let registry = test_registry();
+        let textures = Arc::new(get_textures_in_atlas(&registry, 256, 256));
+        let mut workers = ChunkWorkers::new(2);
+        for x in 0..3 {
+            let mut chunk = Chunk::new(chunk_pos(x, 0, 0));
+            chunk.set_block(
+                &test_block("stone"),
+                &BlockPosInChunk::new(0, 0, 0).unwrap(),
+            );
+            workers.build_mesh(
+                chunk,
+                Default::default(),
+                Arc::clone(&registry),
+                Arc::clone(&textures),
+                MeshingMode::Greedy,
+            );
+        }
+        workers.wait_idle();
+
+        assert_eq!(workers.take_meshes(2).len(), 2);
+        let rest = workers.take_meshes(2);
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].face_count(), 6);
+        assert!(workers.take_meshes(2).is_empty());
+    }
+}
@@ -0,0 +1,57 @@
+use nalgebra::{Point3, Vector3};
+use parry3d::shape::Cuboid;
+use specs::{Builder, Component, Entities, Entity, LazyUpdate, VecStorage};
+
+use super::{Acceleration, Collider, ItemStack, OnGround, Position, Velocity};
+use crate::game_config;
+
+/// 地面に落ちているアイテム
+///
+/// `Position`、`Velocity`、`Acceleration`、`Collider`、`OnGround`と一緒に持たせると、
+/// プレイヤーと同じように落ちて地面に止まる(see: `DroppedItem::spawn`)
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+#[storage(VecStorage)]
+pub struct DroppedItem {
+    pub stack: ItemStack,
+    /// 落ちてからの時間(ミリ秒)
+    pub age: u32,
+}
+
+impl DroppedItem {
+    pub fn new(stack: ItemStack) -> Self {
+        Self { stack, age: 0 }
+    }
+
+    /// 拾えるようになっているかどうか
+    pub fn can_pick_up(&self) -> bool {
+        self.age >= game_config::ITEM_PICKUP_DELAY
+    }
+
+    /// 描画するときの、y軸周りの回転(ラジアン)
+    pub fn spin(&self) -> f32 {
+        self.age as f32 * game_config::ITEM_SPIN_SPEED % std::f32::consts::TAU
+    }
+
+    /// `stack`が`pos`から飛び出すエンティティを作る。エンティティは次の`World::maintain`で追加される
+    pub fn spawn(
+        lazy: &LazyUpdate,
+        entities: &Entities,
+        pos: Point3<f32>,
+        stack: ItemStack,
+    ) -> Entity {
+        // 1ブロックの大きさは0.5
+        let half_extent = game_config::ITEM_SIZE * 0.5 * 0.5;
+        lazy.create_entity(entities)
+            .with(Position(pos))
+            .with(Velocity(Vector3::new(
+                0.0,
+                game_config::ITEM_POP_SPEED,
+                0.0,
+            )))
+            .with(Acceleration::gravity())
+            .with(Collider(Cuboid::new(Vector3::repeat(half_extent))))
+            .with(OnGround(false))
+            .with(DroppedItem::new(stack))
+            .build()
+    }
+}
@@ -1,5 +1,6 @@
 use nalgebra::Vector2;
 use sdl2::keyboard::Scancode;
+use sdl2::mouse::MouseButton;
 use specs::{Component, HashMapStorage};
 
 #[derive(Component, Debug)]
@@ -7,6 +8,12 @@ use specs::{Component, HashMapStorage};
 pub struct Input {
     pub mouse_delta: Vector2<i32>,
     pub pressed_keys: Vec<Scancode>,
+    /// 押されているマウスのボタン
+    pub pressed_buttons: Vec<MouseButton>,
+    /// このフレームで押されたマウスのボタン
+    pub clicked_buttons: Vec<MouseButton>,
+    /// このフレームでマウスのホイールを回した量。上に回すと正
+    pub wheel: i32,
 }
 
 impl Input {
@@ -14,6 +21,9 @@ impl Input {
         Self {
             mouse_delta: Vector2::<i32>::new(0, 0),
             pressed_keys: Vec::new(),
+            pressed_buttons: Vec::new(),
+            clicked_buttons: Vec::new(),
+            wheel: 0,
         }
     }
 }
@@ -0,0 +1,269 @@
+use specs::{Component, HashMapStorage};
+
+use crate::block::{Block, BlockRegistry};
+use crate::storage::level::ItemStackData;
+
+/// ホットバーのスロットの数。インベントリの先頭のスロットがホットバーになる
+pub const HOTBAR_SIZE: usize = 9;
+
+/// インベントリのスロットの数
+pub const INVENTORY_SIZE: usize = 36;
+
+/// 1つのスロットに入っている、同じ種類のアイテムの山
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ItemStack {
+    pub block: Block,
+    /// 1以上、ブロックの`max_stack_size`以下
+    pub count: u32,
+}
+
+impl ItemStack {
+    /// セーブデータの形式にする
+    pub fn to_data(&self, registry: &BlockRegistry) -> ItemStackData {
+        ItemStackData {
+            item: registry.get(self.block).name.clone(),
+            count: self.count,
+        }
+    }
+
+    /// セーブデータから作る
+    ///
+    /// 登録されていないブロックや数が0なら`None`、`max_stack_size`より多ければ減らす
+    pub fn from_data(data: &ItemStackData, registry: &BlockRegistry) -> Option<Self> {
+        let block = registry.by_name(&data.item)?;
+        let count = data.count.min(registry.get(block).max_stack_size);
+        if count == 0 {
+            return None;
+        }
+        Some(ItemStack { block, count })
+    }
+}
+
+/// プレイヤーが持っているアイテムと、ホットバーで選んでいるスロット
+#[derive(Component, Clone, PartialEq, Debug)]
+#[storage(HashMapStorage)]
+pub struct Inventory {
+    slots: Vec<Option<ItemStack>>,
+    /// 0〜`HOTBAR_SIZE - 1`
+    selected: usize,
+}
+
+impl Inventory {
+    /// 空のインベントリ
+    pub fn new() -> Self {
+        Self {
+            slots: vec![None; INVENTORY_SIZE],
+            selected: 0,
+        }
+    }
+
+    pub fn slots(&self) -> &[Option<ItemStack>] {
+        &self.slots
+    }
+
+    pub fn hotbar(&self) -> &[Option<ItemStack>] {
+        &self.slots[..HOTBAR_SIZE]
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// ホットバーの`slot`番目を選ぶ。範囲外なら何もしない
+    pub fn select(&mut self, slot: usize) {
+        if slot < HOTBAR_SIZE {
+            self.selected = slot;
+        }
+    }
+
+    /// 選んでいるスロットを`amount`だけずらす。端まで行ったら反対側に戻る
+    pub fn scroll(&mut self, amount: i32) {
+        self.selected = (self.selected as i32 + amount).rem_euclid(HOTBAR_SIZE as i32) as usize;
+    }
+
+    /// ホットバーで選んでいるスロットのアイテム
+    pub fn selected_item(&self) -> Option<&ItemStack> {
+        self.slots[self.selected].as_ref()
+    }
+
+    /// `block`を`count`個入れる。同じブロックの山に先に入れ、残りは空いているスロットに入れる
+    ///
+    /// 戻り値: 入りきらなかった数
+    pub fn add(&mut self, block: Block, count: u32, registry: &BlockRegistry) -> u32 {
+        let max_stack_size = registry.get(block).max_stack_size;
+        let mut rest = count;
+        for stack in self.slots.iter_mut().flatten() {
+            if rest == 0 {
+                return 0;
+            }
+            if stack.block == block && stack.count < max_stack_size {
+                let moved = rest.min(max_stack_size - stack.count);
+                stack.count += moved;
+                rest -= moved;
+            }
+        }
+        for slot in self.slots.iter_mut().filter(|slot| slot.is_none()) {
+            if rest == 0 {
+                return 0;
+            }
+            let moved = rest.min(max_stack_size);
+            *slot = Some(ItemStack {
+                block,
+                count: moved,
+            });
+            rest -= moved;
+        }
+        rest
+    }
+
+    /// ホットバーで選んでいるスロットからアイテムを1つ取り出す
+    pub fn take_selected(&mut self) -> Option<Block> {
+        let slot = &mut self.slots[self.selected];
+        let stack = slot.as_mut()?;
+        let block = stack.block;
+        stack.count -= 1;
+        if stack.count == 0 {
+            *slot = None;
+        }
+        Some(block)
+    }
+
+    /// セーブデータの形式にする
+    pub fn to_data(&self, registry: &BlockRegistry) -> Vec<Option<ItemStackData>> {
+        self.slots
+            .iter()
+            .map(|slot| slot.map(|stack| stack.to_data(registry)))
+            .collect()
+    }
+
+    /// セーブデータから作る
+    ///
+    /// 登録されていないブロックや数が0の山は捨てる(see: `ItemStack::from_data`)
+    pub fn from_data(
+        slots: &[Option<ItemStackData>],
+        selected: usize,
+        registry: &BlockRegistry,
+    ) -> Self {
+        let mut inventory = Self::new();
+        for (slot, data) in inventory.slots.iter_mut().zip(slots) {
+            *slot = data
+                .as_ref()
+                .and_then(|data| ItemStack::from_data(data, registry));
+        }
+        inventory.select(selected);
+        inventory
+    }
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{test_block, test_registry};
+
+    #[test]
+    fn adds_to_existing_stacks_first() {
+        let registry = test_registry();
+        let stone = test_block("stone");
+        let dirt = test_block("dirt");
+        let mut inventory = Inventory::new();
+
+        assert_eq!(inventory.add(dirt, 1, &registry), 0);
+        assert_eq!(inventory.add(stone, 60, &registry), 0);
+        assert_eq!(inventory.add(stone, 10, &registry), 0);
+        assert_eq!(
+            inventory.hotbar()[..3],
+            [
+                Some(ItemStack {
+                    block: dirt,
+                    count: 1
+                }),
+                Some(ItemStack {
+                    block: stone,
+                    count: 64
+                }),
+                Some(ItemStack {
+                    block: stone,
+                    count: 6
+                }),
+            ]
+        );
+
+        // いっぱいになったら入りきらない数を返す
+        let mut inventory = Inventory::new();
+        let capacity = INVENTORY_SIZE as u32 * 64;
+        assert_eq!(inventory.add(stone, capacity + 5, &registry), 5);
+        assert_eq!(inventory.add(dirt, 1, &registry), 1);
+    }
+
+    #[test]
+    fn selects_hotbar_slots() {
+        let mut inventory = Inventory::new();
+        inventory.select(4);
+        assert_eq!(inventory.selected(), 4);
+        inventory.select(HOTBAR_SIZE);
+        assert_eq!(inventory.selected(), 4);
+        inventory.scroll(5);
+        assert_eq!(inventory.selected(), 0);
+        inventory.scroll(-1);
+        assert_eq!(inventory.selected(), HOTBAR_SIZE - 1);
+    }
+
+    #[test]
+    fn takes_from_selected_slot() {
+        let registry = test_registry();
+        let stone = test_block("stone");
+        let mut inventory = Inventory::new();
+        inventory.add(stone, 2, &registry);
+
+        inventory.select(1);
+        assert_eq!(inventory.take_selected(), None);
+        inventory.select(0);
+        assert_eq!(inventory.take_selected(), Some(stone));
+        assert_eq!(inventory.selected_item().unwrap().count, 1);
+        assert_eq!(inventory.take_selected(), Some(stone));
+        assert_eq!(inventory.selected_item(), None);
+        assert_eq!(inventory.take_selected(), None);
+    }
+
+    #[test]
+    fn converts_to_and_from_save_data() {
+        let registry = test_registry();
+        let mut inventory = Inventory::new();
+        inventory.add(test_block("stone"), 70, &registry);
+        inventory.add(test_block("glass"), 3, &registry);
+        inventory.select(2);
+
+        let data = inventory.to_data(&registry);
+        assert_eq!(data.len(), INVENTORY_SIZE);
+        assert_eq!(
+            data[2],
+            Some(ItemStackData {
+                item: "glass".to_string(),
+                count: 3
+            })
+        );
+        assert_eq!(Inventory::from_data(&data, 2, &registry), inventory);
+
+        // 知らないブロックは捨て、多すぎる数は減らす
+        let data = vec![
+            Some(ItemStackData {
+                item: "unknown".to_string(),
+                count: 1,
+            }),
+            Some(ItemStackData {
+                item: "stone".to_string(),
+                count: 100,
+            }),
+        ];
+        let inventory = Inventory::from_data(&data, 20, &registry);
+        assert_eq!(inventory.slots()[0], None);
+        assert_eq!(inventory.slots()[1].unwrap().count, 64);
+        assert_eq!(inventory.selected(), 0);
+    }
+}
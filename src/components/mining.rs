@@ -0,0 +1,31 @@
+use specs::{Component, HashMapStorage};
+
+use crate::game_config;
+use crate::mymath::BlockPosInWorld;
+
+/// 左ボタンを押し続けて壊している途中のブロック
+#[derive(Component, Default, Debug)]
+#[storage(HashMapStorage)]
+pub struct Mining {
+    /// 何も壊していなければ`None`
+    pub target: Option<BlockPosInWorld>,
+    /// `target`を壊し始めてからの時間(ミリ秒)
+    pub elapsed: u32,
+}
+
+impl Mining {
+    /// 硬さが`hardness`のブロックを壊すのにかかる時間(ミリ秒)
+    pub fn break_time(hardness: f32) -> f32 {
+        hardness * game_config::BREAK_TIME_PER_HARDNESS
+    }
+
+    /// 硬さが`hardness`の`target`を壊し終わるまでの進み具合(0.0〜1.0)
+    pub fn progress(&self, hardness: f32) -> f32 {
+        let break_time = Self::break_time(hardness);
+        if break_time <= 0.0 {
+            1.0
+        } else {
+            (self.elapsed as f32 / break_time).min(1.0)
+        }
+    }
+}
@@ -1,7 +1,10 @@
 pub use acceleration::Acceleration;
 pub use angle2::Angle2;
 pub use collider::Collider;
+pub use dropped_item::DroppedItem;
 pub use input::Input;
+pub use inventory::{Inventory, ItemStack, HOTBAR_SIZE, INVENTORY_SIZE};
+pub use mining::Mining;
 pub use onground::OnGround;
 pub use position::Position;
 pub use velocity::Velocity;
@@ -9,7 +12,10 @@ pub use velocity::Velocity;
 mod acceleration;
 mod angle2;
 mod collider;
+mod dropped_item;
 mod input;
+mod inventory;
+mod mining;
 mod onground;
 mod position;
 mod velocity;
@@ -0,0 +1,587 @@
+//! クラフトのレシピ
+//!
+//! レシピは`rsc/recipes.json`で宣言する。アイテムはブロックの名前で指定する。
+//!
+//! ```json
+//! {
+//!   "recipes": [
+//!     {
+//!       "type": "shapeless",
+//!       "ingredients": ["oak_log"],
+//!       "result": { "item": "oak_planks", "count": 4 }
+//!     },
+//!     {
+//!       "type": "shaped",
+//!       "pattern": ["P  ", "PP ", "PPP"],
+//!       "key": { "P": "oak_planks" },
+//!       "result": { "item": "oak_stairs", "count": 4 }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! `shaped`は`pattern`の形に並べる。`pattern`の空白は何も置かない場所で、他の文字は`key`で
+//! アイテムに対応させる。形はクラフトの枠の中のどこに置いても、左右を反転してもよい。
+//! `shapeless`は`ingredients`を1つずつ、どこに置いてもよい。
+//! `result`の`count`は省略でき、省略すると1個。
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::block::{Block, BlockRegistry};
+use crate::components::ItemStack;
+
+/// クラフトの枠の一辺のスロットの数
+pub const GRID_SIZE: usize = 3;
+
+/// クラフトの枠。スロットは左上から行ごとに並ぶ
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct CraftingGrid {
+    slots: [Option<ItemStack>; GRID_SIZE * GRID_SIZE],
+}
+
+impl CraftingGrid {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn slots(&self) -> &[Option<ItemStack>] {
+        &self.slots
+    }
+
+    /// `index`番目のスロットに`block`を1つ置く。空いているか、同じブロックが
+    /// `max_stack_size`より少なければ置ける
+    ///
+    /// 戻り値: 置いたかどうか
+    pub fn put(&mut self, index: usize, block: Block, registry: &BlockRegistry) -> bool {
+        match &mut self.slots[index] {
+            slot @ None => {
+                *slot = Some(ItemStack { block, count: 1 });
+                true
+            }
+            Some(stack) if stack.block == block => {
+                if stack.count < registry.get(block).max_stack_size {
+                    stack.count += 1;
+                    true
+                } else {
+                    false
+                }
+            }
+            Some(_) => false,
+        }
+    }
+
+    /// `index`番目のスロットのアイテムをすべて取り出す
+    pub fn take(&mut self, index: usize) -> Option<ItemStack> {
+        self.slots[index].take()
+    }
+
+    /// すべてのスロットのアイテムを取り出す
+    pub fn take_all(&mut self) -> Vec<ItemStack> {
+        self.slots.iter_mut().filter_map(Option::take).collect()
+    }
+
+    /// 置かれているブロック。左上から行ごとに並ぶ
+    fn blocks(&self) -> [Option<Block>; GRID_SIZE * GRID_SIZE] {
+        self.slots.map(|slot| slot.map(|stack| stack.block))
+    }
+
+    /// アイテムが置かれている範囲に切り詰めた形。何も置かれていなければ`None`
+    fn trimmed(&self) -> Option<Pattern> {
+        let rows = self
+            .blocks()
+            .chunks(GRID_SIZE)
+            .map(|row| row.to_vec())
+            .collect();
+        Pattern::trim(rows)
+    }
+}
+
+/// `shaped`のレシピの形
+#[derive(Clone, PartialEq, Debug)]
+pub struct Pattern {
+    width: usize,
+    /// 左上から行ごとに並ぶ
+    cells: Vec<Option<Block>>,
+}
+
+impl Pattern {
+    /// 空の行と列を上下左右から取り除く。すべて空なら`None`
+    fn trim(rows: Vec<Vec<Option<Block>>>) -> Option<Pattern> {
+        let filled_rows: Vec<usize> = (0..rows.len())
+            .filter(|&y| rows[y].iter().any(Option::is_some))
+            .collect();
+        let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let filled_columns: Vec<usize> = (0..width)
+            .filter(|&x| {
+                rows.iter()
+                    .any(|row| row.get(x).copied().flatten().is_some())
+            })
+            .collect();
+        let (top, bottom) = (*filled_rows.first()?, *filled_rows.last()?);
+        let (left, right) = (*filled_columns.first()?, *filled_columns.last()?);
+        let cells = (top..=bottom)
+            .flat_map(|y| (left..=right).map(move |x| (x, y)))
+            .map(|(x, y)| rows[y].get(x).copied().flatten())
+            .collect();
+        Some(Pattern {
+            width: right - left + 1,
+            cells,
+        })
+    }
+
+    /// 左右を反転した形
+    fn mirrored(&self) -> Pattern {
+        let cells = self
+            .cells
+            .chunks(self.width)
+            .flat_map(|row| row.iter().rev().copied())
+            .collect();
+        Pattern {
+            width: self.width,
+            cells,
+        }
+    }
+}
+
+/// クラフトのレシピ
+#[derive(Clone, PartialEq, Debug)]
+pub enum Recipe {
+    /// 決まった形に並べる
+    Shaped { pattern: Pattern, result: ItemStack },
+    /// 材料をどこに置いてもよい
+    Shapeless {
+        ingredients: Vec<Block>,
+        result: ItemStack,
+    },
+}
+
+impl Recipe {
+    pub fn result(&self) -> ItemStack {
+        match self {
+            Recipe::Shaped { result, .. } | Recipe::Shapeless { result, .. } => *result,
+        }
+    }
+
+    /// `grid`に置かれているものがこのレシピに合うかどうか
+    pub fn matches(&self, grid: &CraftingGrid) -> bool {
+        match self {
+            Recipe::Shaped { pattern, .. } => grid
+                .trimmed()
+                .is_some_and(|placed| placed == *pattern || placed == pattern.mirrored()),
+            Recipe::Shapeless { ingredients, .. } => {
+                let mut remaining = ingredients.clone();
+                for &block in grid.blocks().iter().flatten() {
+                    match remaining.iter().position(|&b| b == block) {
+                        Some(i) => {
+                            remaining.swap_remove(i);
+                        }
+                        None => return false,
+                    }
+                }
+                remaining.is_empty()
+            }
+        }
+    }
+}
+
+/// レシピの一覧の読み込みに失敗した理由
+#[derive(Debug)]
+pub enum RecipeError {
+    Io(io::Error),
+    /// JSONとして正しくない
+    Parse(serde_json::Error),
+    /// ブロックの一覧に無いアイテム。`recipe`は何番目のレシピか(0から)
+    UnknownItem {
+        recipe: usize,
+        item: String,
+    },
+    /// `pattern`に使われているが`key`に無い文字
+    UndefinedKey {
+        recipe: usize,
+        key: char,
+    },
+    /// 形や材料の宣言が正しくない
+    InvalidRecipe {
+        recipe: usize,
+        reason: &'static str,
+    },
+    /// できるアイテムの数が0か、`max_stack_size`より多い
+    InvalidCount {
+        recipe: usize,
+        count: u32,
+    },
+}
+
+impl fmt::Display for RecipeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecipeError::Io(err) => write!(f, "{}", err),
+            RecipeError::Parse(err) => write!(f, "invalid recipe list: {}", err),
+            RecipeError::UnknownItem { recipe, item } => {
+                write!(f, "recipe #{} uses unknown item '{}'", recipe, item)
+            }
+            RecipeError::UndefinedKey { recipe, key } => {
+                write!(
+                    f,
+                    "pattern of recipe #{} uses undefined key '{}'",
+                    recipe, key
+                )
+            }
+            RecipeError::InvalidRecipe { recipe, reason } => {
+                write!(f, "recipe #{} is invalid: {}", recipe, reason)
+            }
+            RecipeError::InvalidCount { recipe, count } => {
+                write!(f, "recipe #{} makes invalid count {}", recipe, count)
+            }
+        }
+    }
+}
+
+impl Error for RecipeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RecipeError::Io(err) => Some(err),
+            RecipeError::Parse(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// レシピの一覧
+#[derive(Debug)]
+pub struct RecipeBook {
+    recipes: Vec<Recipe>,
+}
+
+impl RecipeBook {
+    /// ファイルからレシピの一覧を読み込む
+    pub fn load(path: &Path, registry: &BlockRegistry) -> Result<Self, RecipeError> {
+        let text = std::fs::read_to_string(path).map_err(RecipeError::Io)?;
+        Self::from_json(&text, registry)
+    }
+
+    pub fn from_json(text: &str, registry: &BlockRegistry) -> Result<Self, RecipeError> {
+        let file: RecipeFile = serde_json::from_str(text).map_err(RecipeError::Parse)?;
+        let recipes = file
+            .recipes
+            .into_iter()
+            .enumerate()
+            .map(|(index, entry)| entry.into_recipe(index, registry))
+            .collect::<Result<_, _>>()?;
+        Ok(RecipeBook { recipes })
+    }
+
+    pub fn recipes(&self) -> impl Iterator<Item = &Recipe> {
+        self.recipes.iter()
+    }
+
+    /// `grid`に合うレシピ。複数あれば先に宣言されたもの
+    pub fn find(&self, grid: &CraftingGrid) -> Option<&Recipe> {
+        self.recipes.iter().find(|recipe| recipe.matches(grid))
+    }
+
+    /// `grid`に合うレシピがあれば、各スロットから材料を1つずつ使ってできたアイテムを返す
+    pub fn craft(&self, grid: &mut CraftingGrid) -> Option<ItemStack> {
+        let result = self.find(grid)?.result();
+        for slot in grid.slots.iter_mut() {
+            if let Some(stack) = slot {
+                stack.count -= 1;
+                if stack.count == 0 {
+                    *slot = None;
+                }
+            }
+        }
+        Some(result)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RecipeFile {
+    recipes: Vec<RecipeEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum RecipeEntry {
+    Shaped {
+        pattern: Vec<String>,
+        key: HashMap<char, String>,
+        result: ResultEntry,
+    },
+    Shapeless {
+        ingredients: Vec<String>,
+        result: ResultEntry,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ResultEntry {
+    item: String,
+    #[serde(default = "default_count")]
+    count: u32,
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+impl RecipeEntry {
+    fn into_recipe(self, recipe: usize, registry: &BlockRegistry) -> Result<Recipe, RecipeError> {
+        let block = |item: &str| {
+            registry
+                .by_name(item)
+                .ok_or_else(|| RecipeError::UnknownItem {
+                    recipe,
+                    item: item.to_string(),
+                })
+        };
+        let invalid = |reason| RecipeError::InvalidRecipe { recipe, reason };
+        let result = |entry: ResultEntry| {
+            let block = block(&entry.item)?;
+            if entry.count == 0 || entry.count > registry.get(block).max_stack_size {
+                return Err(RecipeError::InvalidCount {
+                    recipe,
+                    count: entry.count,
+                });
+            }
+            Ok(ItemStack {
+                block,
+                count: entry.count,
+            })
+        };
+
+        match self {
+            RecipeEntry::Shaped {
+                pattern,
+                key,
+                result: result_entry,
+            } => {
+                if pattern.len() > GRID_SIZE
+                    || pattern.iter().any(|row| row.chars().count() > GRID_SIZE)
+                {
+                    return Err(invalid("pattern is larger than the crafting grid"));
+                }
+                if key.contains_key(&' ') {
+                    return Err(invalid("' ' is reserved for empty slots"));
+                }
+                let key = key
+                    .iter()
+                    .map(|(&c, item)| Ok((c, block(item)?)))
+                    .collect::<Result<HashMap<char, Block>, RecipeError>>()?;
+                let rows = pattern
+                    .iter()
+                    .map(|row| {
+                        row.chars()
+                            .map(|c| match c {
+                                ' ' => Ok(None),
+                                c => key
+                                    .get(&c)
+                                    .map(|&block| Some(block))
+                                    .ok_or(RecipeError::UndefinedKey { recipe, key: c }),
+                            })
+                            .collect::<Result<Vec<_>, _>>()
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let pattern = Pattern::trim(rows).ok_or_else(|| invalid("pattern is empty"))?;
+                Ok(Recipe::Shaped {
+                    pattern,
+                    result: result(result_entry)?,
+                })
+            }
+            RecipeEntry::Shapeless {
+                ingredients,
+                result: result_entry,
+            } => {
+                if ingredients.is_empty() {
+                    return Err(invalid("no ingredients"));
+                }
+                if ingredients.len() > GRID_SIZE * GRID_SIZE {
+                    return Err(invalid("too many ingredients for the crafting grid"));
+                }
+                let ingredients = ingredients
+                    .iter()
+                    .map(|item| block(item))
+                    .collect::<Result<_, _>>()?;
+                Ok(Recipe::Shapeless {
+                    ingredients,
+                    result: result(result_entry)?,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{test_block, test_registry};
+
+    fn recipes(json: &str) -> Result<RecipeBook, RecipeError> {
+        RecipeBook::from_json(&format!(r#"{{ "recipes": [{}] }}"#, json), &test_registry())
+    }
+
+    /// `rows`の文字の位置に`key`のブロックを1つずつ置いた枠
+    fn grid(rows: [&str; GRID_SIZE], key: &[(char, &str)]) -> CraftingGrid {
+        let registry = test_registry();
+        let mut grid = CraftingGrid::new();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if let Some((_, name)) = key.iter().find(|(k, _)| *k == c) {
+                    assert!(grid.put(y * GRID_SIZE + x, test_block(name), &registry));
+                }
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn builtin_recipes_are_valid() {
+        let registry = test_registry();
+        let book = RecipeBook::load(Path::new("rsc/recipes.json"), &registry).unwrap();
+        assert!(book.recipes().count() > 0);
+        let planks = book
+            .find(&grid(["", " L", ""], &[('L', "oak_log")]))
+            .unwrap();
+        assert_eq!(
+            planks.result(),
+            ItemStack {
+                block: test_block("oak_planks"),
+                count: 4
+            }
+        );
+    }
+
+    #[test]
+    fn shaped_recipes_match_anywhere_and_mirrored() {
+        let book = recipes(
+            r#"{ "type": "shaped", "pattern": ["P ", "PP"], "key": { "P": "oak_planks" },
+                 "result": { "item": "oak_stairs", "count": 4 } }"#,
+        )
+        .unwrap();
+        let key = [('P', "oak_planks"), ('S', "stone")];
+        assert!(book.find(&grid(["P  ", "PP ", "   "], &key)).is_some());
+        assert!(book.find(&grid(["   ", " P ", " PP"], &key)).is_some());
+        assert!(book.find(&grid(["   ", "  P", " PP"], &key)).is_some());
+        // 形が違うものや、余分なものがあるものには合わない
+        assert!(book.find(&grid(["PP ", "P  ", "   "], &key)).is_none());
+        assert!(book.find(&grid(["P  ", "PP ", "  S"], &key)).is_none());
+        assert!(book.find(&grid(["P  ", "PP ", "P  "], &key)).is_none());
+        assert!(book.find(&CraftingGrid::new()).is_none());
+    }
+
+    #[test]
+    fn shapeless_recipes_match_in_any_order() {
+        let book = recipes(
+            r#"{ "type": "shapeless", "ingredients": ["dirt", "stone", "dirt"],
+                 "result": { "item": "grass_block" } }"#,
+        )
+        .unwrap();
+        let key = [('D', "dirt"), ('S', "stone")];
+        assert!(book.find(&grid(["D  ", " S ", "  D"], &key)).is_some());
+        assert!(book.find(&grid(["SDD", "   ", "   "], &key)).is_some());
+        assert!(book.find(&grid(["SD ", "   ", "   "], &key)).is_none());
+        assert!(book.find(&grid(["SDD", "D  ", "   "], &key)).is_none());
+        assert_eq!(
+            book.recipes().next().unwrap().result(),
+            ItemStack {
+                block: test_block("grass_block"),
+                count: 1
+            }
+        );
+    }
+
+    #[test]
+    fn crafting_uses_one_of_each_ingredient() {
+        let registry = test_registry();
+        let book = recipes(
+            r#"{ "type": "shapeless", "ingredients": ["oak_log"],
+                 "result": { "item": "oak_planks", "count": 4 } }"#,
+        )
+        .unwrap();
+        let mut grid = CraftingGrid::new();
+        grid.put(4, test_block("oak_log"), &registry);
+        grid.put(4, test_block("oak_log"), &registry);
+        assert!(!grid.put(4, test_block("stone"), &registry));
+
+        assert_eq!(book.craft(&mut grid).unwrap().count, 4);
+        assert_eq!(grid.slots()[4].unwrap().count, 1);
+        assert_eq!(book.craft(&mut grid).unwrap().count, 4);
+        assert_eq!(grid.slots()[4], None);
+        assert_eq!(book.craft(&mut grid), None);
+    }
+
+    #[test]
+    fn rejects_invalid_recipes() {
+        let result = r#""result": { "item": "stone" }"#;
+        assert!(matches!(
+            recipes(&format!(
+                r#"{{ "type": "shapeless", "ingredients": ["diamond"], {} }}"#,
+                result
+            )),
+            Err(RecipeError::UnknownItem { recipe: 0, item }) if item == "diamond"
+        ));
+        assert!(matches!(
+            recipes(
+                r#"{ "type": "shapeless", "ingredients": ["dirt"], "result": { "item": "x" } }"#
+            ),
+            Err(RecipeError::UnknownItem { .. })
+        ));
+        assert!(matches!(
+            recipes(&format!(
+                r#"{{ "type": "shaped", "pattern": ["AB"], "key": {{ "A": "dirt" }}, {} }}"#,
+                result
+            )),
+            Err(RecipeError::UndefinedKey { key: 'B', .. })
+        ));
+        assert!(matches!(
+            recipes(&format!(
+                r#"{{ "type": "shaped", "pattern": ["AAAA"], "key": {{ "A": "dirt" }}, {} }}"#,
+                result
+            )),
+            Err(RecipeError::InvalidRecipe { .. })
+        ));
+        assert!(matches!(
+            recipes(&format!(
+                r#"{{ "type": "shaped", "pattern": ["   "], "key": {{}}, {} }}"#,
+                result
+            )),
+            Err(RecipeError::InvalidRecipe { .. })
+        ));
+        assert!(matches!(
+            recipes(&format!(
+                r#"{{ "type": "shapeless", "ingredients": [], {} }}"#,
+                result
+            )),
+            Err(RecipeError::InvalidRecipe { .. })
+        ));
+        assert!(matches!(
+            recipes(
+                r#"{ "type": "shapeless", "ingredients": ["dirt"], "result": { "item": "stone", "count": 65 } }"#
+            ),
+            Err(RecipeError::InvalidCount { count: 65, .. })
+        ));
+        assert!(matches!(
+            recipes(
+                r#"{ "type": "smelting", "ingredients": ["dirt"], "result": { "item": "stone" } }"#
+            ),
+            Err(RecipeError::Parse(_))
+        ));
+        // 何番目のレシピが間違っているか分かる
+        let valid =
+            r#"{ "type": "shapeless", "ingredients": ["dirt"], "result": { "item": "stone" } }"#;
+        let error = recipes(&format!(
+            r#"{}, {{ "type": "shapeless", "ingredients": ["dirt"], "result": {{ "item": "gold" }} }}"#,
+            valid
+        ))
+        .unwrap_err();
+        assert_eq!(error.to_string(), "recipe #1 uses unknown item 'gold'");
+    }
+}
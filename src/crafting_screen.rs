@@ -0,0 +1,254 @@
+//! クラフトの画面
+//!
+//! クラフトの枠のスロットをクリックすると、ホットバーで選んでいるアイテムを1つ置く。
+//! 置けなければスロットのアイテムをインベントリに戻す。
+//! できるアイテムをクリックすると、枠の材料を使ってインベントリに入れる。
+
+use imgui::{im_str, Condition, Ui, Window};
+
+use crate::block::BlockRegistry;
+use crate::components::{Inventory, ItemStack};
+use crate::crafting::{CraftingGrid, RecipeBook, GRID_SIZE};
+
+/// 開いているかどうかと、クラフトの枠に置いているアイテム
+#[derive(Default, Debug)]
+pub struct CraftingScreen {
+    open: bool,
+    grid: CraftingGrid,
+}
+
+impl CraftingScreen {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn grid(&self) -> &CraftingGrid {
+        &self.grid
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+    }
+
+    /// 閉じて、枠に置いていたアイテムを`inventory`に戻す
+    ///
+    /// 戻り値: インベントリに入りきらなかったアイテム
+    pub fn close(&mut self, inventory: &mut Inventory, registry: &BlockRegistry) -> Vec<ItemStack> {
+        self.open = false;
+        self.grid
+            .take_all()
+            .into_iter()
+            .filter_map(|stack| give(inventory, stack, registry))
+            .collect()
+    }
+
+    /// 枠の`index`番目のスロットをクリックする
+    ///
+    /// 戻り値: インベントリに入りきらなかったアイテム
+    pub fn click_slot(
+        &mut self,
+        index: usize,
+        inventory: &mut Inventory,
+        registry: &BlockRegistry,
+    ) -> Option<ItemStack> {
+        let selected = inventory.selected_item().map(|stack| stack.block);
+        if selected.is_some_and(|block| self.grid.put(index, block, registry)) {
+            inventory.take_selected();
+            None
+        } else {
+            give(inventory, self.grid.take(index)?, registry)
+        }
+    }
+
+    /// できるアイテムをクリックする
+    ///
+    /// 戻り値: インベントリに入りきらなかったアイテム
+    pub fn click_result(
+        &mut self,
+        recipes: &RecipeBook,
+        inventory: &mut Inventory,
+        registry: &BlockRegistry,
+    ) -> Option<ItemStack> {
+        give(inventory, recipes.craft(&mut self.grid)?, registry)
+    }
+
+    /// 画面を描画し、クリックされたスロットを処理する。閉じていれば何もしない
+    ///
+    /// 戻り値: インベントリに入りきらなかったアイテム
+    pub fn show(
+        &mut self,
+        ui: &Ui,
+        recipes: &RecipeBook,
+        inventory: &mut Inventory,
+        registry: &BlockRegistry,
+    ) -> Vec<ItemStack> {
+        if !self.open {
+            return Vec::new();
+        }
+        let label = |stack: &Option<ItemStack>| match stack {
+            Some(stack) => format!("{} x{}", registry.get(stack.block).name, stack.count),
+            None => String::new(),
+        };
+        let result = recipes.find(&self.grid).map(|recipe| recipe.result());
+        let mut clicked_slot = None;
+        let mut clicked_result = false;
+        let display_size = ui.io().display_size;
+        Window::new(im_str!("Crafting"))
+            .position(
+                [display_size[0] / 2.0, display_size[1] / 2.0],
+                Condition::FirstUseEver,
+            )
+            .position_pivot([0.5, 0.5])
+            .always_auto_resize(true)
+            .collapsible(false)
+            .build(ui, || {
+                ui.text(im_str!("Click a slot to put the selected hotbar item"));
+                for (index, stack) in self.grid.slots().iter().enumerate() {
+                    if index % GRID_SIZE != 0 {
+                        ui.same_line(0.0);
+                    }
+                    let text = im_str!("{}##slot{}", label(stack), index);
+                    if ui.button(&text, [120.0, 40.0]) {
+                        clicked_slot = Some(index);
+                    }
+                }
+                ui.separator();
+                let text = im_str!("{}##result", label(&result));
+                if ui.button(&text, [120.0, 40.0]) && result.is_some() {
+                    clicked_result = true;
+                }
+            });
+
+        let mut leftovers = Vec::new();
+        if let Some(index) = clicked_slot {
+            leftovers.extend(self.click_slot(index, inventory, registry));
+        }
+        if clicked_result {
+            leftovers.extend(self.click_result(recipes, inventory, registry));
+        }
+        leftovers
+    }
+}
+
+/// `stack`を`inventory`に入れる。入りきらなければ残りを返す
+fn give(
+    inventory: &mut Inventory,
+    stack: ItemStack,
+    registry: &BlockRegistry,
+) -> Option<ItemStack> {
+    let count = inventory.add(stack.block, stack.count, registry);
+    (count > 0).then_some(ItemStack {
+        block: stack.block,
+        count,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{test_block, test_registry};
+    use crate::components::INVENTORY_SIZE;
+
+    fn planks_recipe() -> RecipeBook {
+        RecipeBook::from_json(
+            r#"{ "recipes": [{ "type": "shapeless", "ingredients": ["oak_log"],
+                 "result": { "item": "oak_planks", "count": 4 } }] }"#,
+            &test_registry(),
+        )
+        .unwrap()
+    }
+
+    /// インベントリに入っているブロックの名前と数
+    fn stacks(inventory: &Inventory) -> Vec<(String, u32)> {
+        let registry = test_registry();
+        inventory
+            .slots()
+            .iter()
+            .flatten()
+            .map(|stack| (registry.get(stack.block).name.clone(), stack.count))
+            .collect()
+    }
+
+    fn expected(stacks: &[(&str, u32)]) -> Vec<(String, u32)> {
+        stacks
+            .iter()
+            .map(|&(name, count)| (name.to_string(), count))
+            .collect()
+    }
+
+    #[test]
+    fn crafts_from_selected_items() {
+        let registry = test_registry();
+        let recipes = planks_recipe();
+        let mut screen = CraftingScreen::new();
+        let mut inventory = Inventory::new();
+        inventory.add(test_block("oak_log"), 3, &registry);
+
+        screen.open();
+        assert_eq!(screen.click_slot(4, &mut inventory, &registry), None);
+        assert_eq!(screen.click_slot(4, &mut inventory, &registry), None);
+        assert_eq!(screen.grid().slots()[4].unwrap().count, 2);
+        assert_eq!(stacks(&inventory), expected(&[("oak_log", 1)]));
+
+        assert_eq!(
+            screen.click_result(&recipes, &mut inventory, &registry),
+            None
+        );
+        assert_eq!(
+            stacks(&inventory),
+            expected(&[("oak_log", 1), ("oak_planks", 4)])
+        );
+
+        // 閉じると枠に残っていたアイテムはインベントリに戻る
+        assert!(screen.close(&mut inventory, &registry).is_empty());
+        assert!(!screen.is_open());
+        assert_eq!(
+            stacks(&inventory),
+            expected(&[("oak_log", 2), ("oak_planks", 4)])
+        );
+        assert!(screen.grid().slots().iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn clicking_slot_without_items_takes_it_back() {
+        let registry = test_registry();
+        let mut screen = CraftingScreen::new();
+        let mut inventory = Inventory::new();
+        inventory.add(test_block("oak_log"), 1, &registry);
+        screen.click_slot(0, &mut inventory, &registry);
+        assert!(inventory.selected_item().is_none());
+
+        screen.click_slot(0, &mut inventory, &registry);
+        assert!(screen.grid().slots()[0].is_none());
+        assert_eq!(stacks(&inventory), expected(&[("oak_log", 1)]));
+        // 合うレシピが無ければ何もできない
+        assert_eq!(
+            screen.click_result(&planks_recipe(), &mut inventory, &registry),
+            None
+        );
+        assert_eq!(stacks(&inventory), expected(&[("oak_log", 1)]));
+    }
+
+    #[test]
+    fn returns_items_that_do_not_fit() {
+        let registry = test_registry();
+        let mut screen = CraftingScreen::new();
+        let mut inventory = Inventory::new();
+        inventory.add(test_block("oak_log"), 1, &registry);
+        screen.click_slot(0, &mut inventory, &registry);
+        inventory.add(test_block("stone"), INVENTORY_SIZE as u32 * 64, &registry);
+
+        let planks = screen.click_result(&planks_recipe(), &mut inventory, &registry);
+        assert_eq!(
+            planks,
+            Some(ItemStack {
+                block: test_block("oak_planks"),
+                count: 4
+            })
+        );
+    }
+}
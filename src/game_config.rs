@@ -1,4 +1,33 @@
+use crate::chunk::MeshingMode;
+
 pub const ROTATE_SPEED: f32 = 0.015f32;
 pub const MOVE_SPEED: f32 = 0.002f32;
 pub const JUMP_SPEED: f32 = 0.004f32;
 pub const GRAVITY: f32 = 0.00001;
+pub const STEP_HEIGHT: f32 = 0.25;
+/// 手が届く距離。5ブロック分
+pub const REACH: f32 = 2.5;
+/// 硬さ1.0のブロックを壊すのにかかる時間(ミリ秒)
+pub const BREAK_TIME_PER_HARDNESS: f32 = 1000.0;
+/// 落ちているアイテムの大きさ(ブロック単位)
+pub const ITEM_SIZE: f32 = 0.25;
+/// 壊したブロックのアイテムが飛び出す速さ
+pub const ITEM_POP_SPEED: f32 = 0.002;
+/// アイテムが回る速さ(ラジアン/ミリ秒)
+pub const ITEM_SPIN_SPEED: f32 = 0.002;
+/// プレイヤーの中心からこの距離の中にあるアイテムを拾う
+pub const ITEM_PICKUP_RADIUS: f32 = 0.75;
+/// アイテムが落ちてから拾えるようになるまでの時間(ミリ秒)
+pub const ITEM_PICKUP_DELAY: u32 = 500;
+/// この距離の中にある同じ種類のアイテムは1つにまとまる
+pub const ITEM_MERGE_RADIUS: f32 = 0.5;
+/// 拾われなかったアイテムが消えるまでの時間(ミリ秒)
+pub const ITEM_LIFETIME: u32 = 300_000;
+pub const WORLD_NAME: &str = "New World";
+pub const CHUNK_LOAD_RADIUS: i32 = 2;
+pub const CHUNK_LOAD_VERTICAL_RADIUS: i32 = 1;
+pub const SAVE_DIR: &str = "saves/world";
+pub const BLOCK_REGISTRY_PATH: &str = "rsc/blocks.json";
+pub const RECIPES_PATH: &str = "rsc/recipes.json";
+pub const MESHING_MODE: MeshingMode = MeshingMode::Greedy;
+pub const MESH_UPLOADS_PER_FRAME: usize = 4;
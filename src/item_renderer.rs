@@ -0,0 +1,106 @@
+//! 落ちているアイテムを、回転する小さなブロックとして描画する
+
+use std::collections::HashMap;
+
+use c_str_macro::c_str;
+use nalgebra::{Matrix4, Point3, Vector3};
+use re::gl::Gl;
+use re::shader::{Program, Uniform, UniformVariables};
+use re::vao::vao_builder::VaoBuilder;
+use re::vao::Vao;
+use reverie_engine as re;
+
+use crate::block::registry::MAX_LIGHT_LEVEL;
+use crate::block::Block;
+use crate::chunk;
+use crate::components::DroppedItem;
+use crate::game_config;
+use crate::light::LightKind;
+use crate::mymath::BlockPosInWorld;
+use crate::texture::block_texture::BlockTextures;
+use crate::world::GameWorld;
+
+/// ブロックの種類ごとに`Vao`を作って使い回す。明るさはアイテムごとにユニフォーム変数で変える
+pub struct ItemRenderer<'a> {
+    shader: &'a Program,
+    vaos: HashMap<Block, Vao<'a>>,
+}
+
+impl<'a> ItemRenderer<'a> {
+    /// チャンクと同じ`shader`で描画する
+    pub fn new(shader: &'a Program) -> Self {
+        Self {
+            shader,
+            vaos: HashMap::new(),
+        }
+    }
+
+    /// `items`を描画する。チャンクより先に描画すると、半透明なブロックの奥にあるアイテムも見える
+    pub fn draw<'b>(
+        &mut self,
+        gl: &Gl,
+        uniforms: &UniformVariables,
+        world: &GameWorld,
+        items: impl Iterator<Item = (&'b Point3<f32>, &'b DroppedItem)>,
+        textures: &BlockTextures,
+    ) {
+        for (pos, item) in items {
+            let block = item.stack.block;
+            let block_pos = BlockPosInWorld::from_point(pos);
+            let [sky, red, green, blue] = LightKind::ALL.map(|kind| {
+                world.light(kind, &block_pos).unwrap_or(0) as f32 / MAX_LIGHT_LEVEL as f32
+            });
+            let shader = self.shader;
+            let vao = self.vaos.entry(block).or_insert_with(|| {
+                let definition = world.registry().get(block);
+                let mut builder = VaoBuilder::new();
+                chunk::add_single_block(&mut builder, definition, block.default_state(), textures);
+                builder.attatch_program(shader);
+                builder.build(gl)
+            });
+
+            let model = item_model_matrix(pos, item.spin());
+            let mut uniforms = uniforms.clone();
+            uniforms.add(c_str!("uModel"), Uniform::Matrix4(&model));
+            uniforms.add(c_str!("uAlphaCutoff"), Uniform::Float(0.5));
+            uniforms.add(
+                c_str!("uLightScale"),
+                Uniform::QuadFloat(sky, red, green, blue),
+            );
+            vao.draw_triangles(&uniforms);
+        }
+    }
+}
+
+/// 中心が`pos`で、y軸周りに`spin`(ラジアン)だけ回転した小さなブロックのモデル行列
+///
+/// メッシュは(0, 0, 0)〜(1, 1, 1)のブロック単位
+pub fn item_model_matrix(pos: &Point3<f32>, spin: f32) -> Matrix4<f32> {
+    // 1ブロックの大きさは0.5
+    let scale = game_config::ITEM_SIZE * 0.5;
+    Matrix4::new_translation(&pos.coords)
+        * Matrix4::from_axis_angle(&Vector3::y_axis(), spin)
+        * Matrix4::new_scaling(scale)
+        * Matrix4::new_translation(&Vector3::repeat(-0.5))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn item_is_centered_on_its_position() {
+        let pos = Point3::new(1.0, -2.0, 3.0);
+        let half = game_config::ITEM_SIZE * 0.5 * 0.5;
+        let model = item_model_matrix(&pos, 0.0);
+        let begin = model.transform_point(&Point3::origin());
+        let end = model.transform_point(&Point3::new(1.0, 1.0, 1.0));
+        assert!((begin - (pos - Vector3::repeat(half))).norm() < 1e-5);
+        assert!((end - (pos + Vector3::repeat(half))).norm() < 1e-5);
+
+        // 回転しても中心は動かない
+        let model = item_model_matrix(&pos, 1.0);
+        let center = model.transform_point(&Point3::new(0.5, 0.5, 0.5));
+        assert!((center - pos).norm() < 1e-5);
+    }
+}
@@ -0,0 +1,585 @@
+//! 空からの光とブロックからの光の伝播
+//!
+//! 光の強さは0〜`MAX_LIGHT_LEVEL`で、ブロックごとにチャンクに記録する。
+//! ブロックからの光は赤、緑、青に分けて、それぞれ別に広げる。
+//! 光は不透明な立方体のブロックを除くすべてのブロックに広がり、1ブロック進むごとに1ずつ弱まる。
+//! ただし最も強い空からの光は、真下へは弱まらずに進む。
+//!
+//! 光の強さは幅優先探索で広げる。ブロックが変わったときは、そのブロックから届いていた光を
+//! 取り除いてから、残った光を広げ直す。読み込まれていないチャンクには広がらない。
+//! OpenGLには依存しないので、テストから直接呼び出せる
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::block::registry::MAX_LIGHT_LEVEL;
+use crate::block::{BlockRegistry, Side};
+use crate::chunk::Chunk;
+use crate::mymath::{BlockPosInChunk, BlockPosInWorld, ChunkPos};
+
+/// 光の種類
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LightKind {
+    /// 空からの光
+    Sky,
+    /// 光るブロックからの赤い光
+    Red,
+    Green,
+    Blue,
+}
+
+impl LightKind {
+    pub const ALL: [LightKind; 4] = [
+        LightKind::Sky,
+        LightKind::Red,
+        LightKind::Green,
+        LightKind::Blue,
+    ];
+
+    /// `emission`(`BlockDefinition::light_emission`)のうち、この種類の光の強さ
+    fn emission(self, emission: &[u8; 3]) -> u8 {
+        match self {
+            LightKind::Sky => 0,
+            LightKind::Red => emission[0],
+            LightKind::Green => emission[1],
+            LightKind::Blue => emission[2],
+        }
+    }
+
+    /// `level`の光が`side`の方向へ1ブロック進んだときの強さ
+    fn spread(self, level: u8, side: Side) -> u8 {
+        if self == LightKind::Sky && side == Side::BOTTOM && level == MAX_LIGHT_LEVEL {
+            MAX_LIGHT_LEVEL
+        } else {
+            level.saturating_sub(1)
+        }
+    }
+}
+
+/// 読み込まれているチャンクの光を計算する
+pub struct Lighting<'a> {
+    chunks: &'a mut HashMap<ChunkPos, Chunk>,
+    registry: &'a BlockRegistry,
+    /// 真上のチャンクが読み込まれていないときに、ワールド座標`(x, z)`の列で
+    /// 高さ`y`より上が空に開いているか
+    open_sky: &'a dyn Fn(i32, i32, i32) -> bool,
+    /// 光が変わって、頂点データを作り直さなければならないチャンク
+    changed: &'a mut HashSet<ChunkPos>,
+}
+
+impl<'a> Lighting<'a> {
+    pub fn new(
+        chunks: &'a mut HashMap<ChunkPos, Chunk>,
+        registry: &'a BlockRegistry,
+        open_sky: &'a dyn Fn(i32, i32, i32) -> bool,
+        changed: &'a mut HashSet<ChunkPos>,
+    ) -> Self {
+        Self {
+            chunks,
+            registry,
+            open_sky,
+            changed,
+        }
+    }
+
+    /// 読み込まれていなければ`None`
+    fn level(&self, kind: LightKind, pos: &BlockPosInWorld) -> Option<u8> {
+        let (chunk_pos, pos) = pos.split();
+        Some(self.chunks.get(&chunk_pos)?.light(kind, &pos))
+    }
+
+    fn set_level(&mut self, kind: LightKind, pos: &BlockPosInWorld, level: u8) {
+        let (chunk_pos, pos_in_chunk) = pos.split();
+        let chunk = self.chunks.get_mut(&chunk_pos).unwrap();
+        chunk.set_light(kind, &pos_in_chunk, level);
+        self.changed.extend(pos.adjacent_chunks());
+    }
+
+    /// 光を通さないか。読み込まれていないブロックも光を通さない
+    fn is_opaque(&self, pos: &BlockPosInWorld) -> bool {
+        let (chunk_pos, pos) = pos.split();
+        match self.chunks.get(&chunk_pos) {
+            Some(chunk) => chunk
+                .get_state(&pos)
+                .is_some_and(|state| self.registry.get(state.block()).is_opaque_cube()),
+            None => true,
+        }
+    }
+
+    fn emission(&self, kind: LightKind, pos: &BlockPosInWorld) -> u8 {
+        let (chunk_pos, pos) = pos.split();
+        self.chunks
+            .get(&chunk_pos)
+            .and_then(|chunk| chunk.get_state(&pos))
+            .map_or(0, |state| {
+                kind.emission(&self.registry.get(state.block()).light_emission)
+            })
+    }
+
+    /// 真上のブロックが読み込まれておらず、空に開いているか
+    fn is_under_open_sky(&self, pos: &BlockPosInWorld) -> bool {
+        let above = pos.neighbor(Side::TOP);
+        !self.chunks.contains_key(&above.chunk_pos()) && (self.open_sky)(above.x, above.z, above.y)
+    }
+
+    /// 生成したばかりのチャンクの中の光を計算する
+    ///
+    /// 隣のチャンクとの間で光を広げるには、続けて`connect_chunk`を呼ぶ
+    pub fn light_chunk(&mut self, chunk_pos: &ChunkPos) {
+        let at = |x: u32, y: u32, z: u32| {
+            BlockPosInWorld::from_chunk_pos(chunk_pos, &BlockPosInChunk::new(x, y, z).unwrap())
+        };
+        let mut sources = LightKind::ALL.map(|_| VecDeque::new());
+
+        for pos in BlockPosInChunk::all() {
+            let pos = at(pos.x, pos.y, pos.z);
+            for (kind, sources) in LightKind::ALL.iter().zip(&mut sources) {
+                let emission = self.emission(*kind, &pos);
+                if emission > 0 {
+                    self.set_level(*kind, &pos, emission);
+                    sources.push_back(pos);
+                }
+            }
+        }
+        for x in 0..16 {
+            for z in 0..16 {
+                let top = at(x, 15, z);
+                if !self.is_opaque(&top) && self.is_under_open_sky(&top) {
+                    self.set_level(LightKind::Sky, &top, MAX_LIGHT_LEVEL);
+                    sources[0].push_back(top);
+                }
+            }
+        }
+        for (kind, sources) in LightKind::ALL.iter().zip(sources) {
+            self.propagate(*kind, sources);
+        }
+        self.chunks.get_mut(chunk_pos).unwrap().mark_lit();
+    }
+
+    /// 光を計算済みのチャンクと、読み込まれている隣のチャンクとの間で光を広げる
+    pub fn connect_chunk(&mut self, chunk_pos: &ChunkPos) {
+        let mut sources = LightKind::ALL.map(|_| VecDeque::new());
+        for (inside, outside) in self.border(chunk_pos) {
+            for (kind, sources) in LightKind::ALL.iter().zip(&mut sources) {
+                for pos in [inside, outside] {
+                    if self.level(*kind, &pos).unwrap() > 0 {
+                        sources.push_back(pos);
+                    }
+                }
+            }
+        }
+        for (kind, sources) in LightKind::ALL.iter().zip(sources) {
+            self.propagate(*kind, sources);
+        }
+
+        // 上下のチャンクは、もう一方が読み込まれる前は空に開いているとみなしていたかもしれない
+        self.cover_sky(chunk_pos);
+        let above = chunk_pos.neighbor(Side::TOP);
+        if self.chunks.contains_key(&above) {
+            self.cover_sky(&above);
+        }
+    }
+
+    /// 保存されていた光のうち、読み込まれている隣のチャンクとの境界のブロックの光を計算し直す
+    ///
+    /// 保存されていた光には、解放されている間に変わった隣のチャンクから届いていた光が
+    /// 残っているかもしれない。境界のブロックの光を一度取り除いてから、残った光を広げ直す
+    pub fn relight_border(&mut self, chunk_pos: &ChunkPos) {
+        let border: Vec<_> = self
+            .border(chunk_pos)
+            .into_iter()
+            .map(|(inside, _)| inside)
+            .collect();
+        for kind in LightKind::ALL {
+            let mut removed = VecDeque::new();
+            for pos in &border {
+                let level = self.level(kind, pos).unwrap();
+                if level > 0 {
+                    self.set_level(kind, pos, 0);
+                    removed.push_back((*pos, level));
+                }
+            }
+            let mut sources = self.remove(kind, removed);
+            for pos in &border {
+                let level = if kind == LightKind::Sky
+                    && !self.is_opaque(pos)
+                    && self.is_under_open_sky(pos)
+                {
+                    MAX_LIGHT_LEVEL
+                } else {
+                    self.emission(kind, pos)
+                };
+                if level > self.level(kind, pos).unwrap() {
+                    self.set_level(kind, pos, level);
+                    sources.push_back(*pos);
+                }
+            }
+            self.propagate(kind, sources);
+        }
+    }
+
+    /// チャンクの中で、読み込まれている隣のチャンクに面で接しているブロックと、その外側のブロックの組
+    fn border(&self, chunk_pos: &ChunkPos) -> Vec<(BlockPosInWorld, BlockPosInWorld)> {
+        let mut border = Vec::new();
+        for side in Side::ALL {
+            if !self.chunks.contains_key(&chunk_pos.neighbor(side)) {
+                continue;
+            }
+            for pos in BlockPosInChunk::all() {
+                let pos = BlockPosInWorld::from_chunk_pos(chunk_pos, &pos);
+                let outside = pos.neighbor(side);
+                if outside.chunk_pos() != *chunk_pos {
+                    border.push((pos, outside));
+                }
+            }
+        }
+        border
+    }
+
+    /// `upper`のチャンクの下のチャンクのうち、`upper`に遮られているのに空の光が最も強いブロックの光を取り除く
+    fn cover_sky(&mut self, upper: &ChunkPos) {
+        let mut removed = VecDeque::new();
+        for x in 0..16 {
+            for z in 0..16 {
+                let bottom =
+                    BlockPosInWorld::from_chunk_pos(upper, &BlockPosInChunk::new(x, 0, z).unwrap());
+                let below = bottom.neighbor(Side::BOTTOM);
+                if self.level(LightKind::Sky, &below) == Some(MAX_LIGHT_LEVEL)
+                    && self.level(LightKind::Sky, &bottom) != Some(MAX_LIGHT_LEVEL)
+                {
+                    self.set_level(LightKind::Sky, &below, 0);
+                    removed.push_back((below, MAX_LIGHT_LEVEL));
+                }
+            }
+        }
+        if !removed.is_empty() {
+            let sources = self.remove(LightKind::Sky, removed);
+            self.propagate(LightKind::Sky, sources);
+        }
+    }
+
+    /// `pos`のブロックが変わったときに、影響する範囲の光を計算し直す
+    pub fn update_block(&mut self, pos: &BlockPosInWorld) {
+        for kind in LightKind::ALL {
+            let old = match self.level(kind, pos) {
+                Some(level) => level,
+                None => return,
+            };
+            let mut sources = VecDeque::new();
+            if old > 0 {
+                self.set_level(kind, pos, 0);
+                sources = self.remove(kind, VecDeque::from(vec![(*pos, old)]));
+            }
+            if !self.is_opaque(pos) {
+                for side in Side::ALL {
+                    let neighbor = pos.neighbor(side);
+                    if self.level(kind, &neighbor).is_some_and(|level| level > 0) {
+                        sources.push_back(neighbor);
+                    }
+                }
+                if kind == LightKind::Sky && self.is_under_open_sky(pos) {
+                    self.set_level(kind, pos, MAX_LIGHT_LEVEL);
+                    sources.push_back(*pos);
+                }
+            }
+            let emission = self.emission(kind, pos);
+            if emission > 0 {
+                self.set_level(kind, pos, emission);
+                sources.push_back(*pos);
+            }
+            self.propagate(kind, sources);
+        }
+    }
+
+    /// `queue`のブロックから光を広げる
+    fn propagate(&mut self, kind: LightKind, mut queue: VecDeque<BlockPosInWorld>) {
+        while let Some(pos) = queue.pop_front() {
+            let level = match self.level(kind, &pos) {
+                Some(level) if level > 0 => level,
+                _ => continue,
+            };
+            for side in Side::ALL {
+                let next = pos.neighbor(side);
+                let spread = kind.spread(level, side);
+                if spread == 0 || self.is_opaque(&next) {
+                    continue;
+                }
+                if self
+                    .level(kind, &next)
+                    .is_some_and(|next_level| next_level < spread)
+                {
+                    self.set_level(kind, &next, spread);
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    /// `queue`のブロック(と元の光の強さ)から届いていた光を取り除く
+    ///
+    /// 取り除いた範囲に別のところから届いている光のブロックを返す。
+    /// それらから`propagate`すると、取り除いた範囲が正しい強さで埋まる
+    fn remove(
+        &mut self,
+        kind: LightKind,
+        mut queue: VecDeque<(BlockPosInWorld, u8)>,
+    ) -> VecDeque<BlockPosInWorld> {
+        let mut sources = VecDeque::new();
+        while let Some((pos, level)) = queue.pop_front() {
+            for side in Side::ALL {
+                let next = pos.neighbor(side);
+                let next_level = match self.level(kind, &next) {
+                    Some(next_level) if next_level > 0 => next_level,
+                    _ => continue,
+                };
+                if next_level > kind.spread(level, side) {
+                    sources.push_back(next);
+                    continue;
+                }
+                self.set_level(kind, &next, 0);
+                queue.push_back((next, next_level));
+                let emission = self.emission(kind, &next);
+                if emission > 0 {
+                    self.set_level(kind, &next, emission);
+                    sources.push_back(next);
+                }
+            }
+        }
+        sources
+    }
+}
+
+/// 他のチャンクが無いものとして、`chunk`の中の光を計算する
+///
+/// ワーカースレッドで生成したチャンクに使う。隣のチャンクとの間は、ワールドに加えるときに広げる
+/// (see: `Lighting::connect_chunk`)
+pub fn light_chunk_alone(
+    chunk: Chunk,
+    registry: &BlockRegistry,
+    open_sky: &dyn Fn(i32, i32, i32) -> bool,
+) -> Chunk {
+    let pos = *chunk.position();
+    let mut chunks = HashMap::from([(pos, chunk)]);
+    let mut changed = HashSet::new();
+    Lighting::new(&mut chunks, registry, open_sky, &mut changed).light_chunk(&pos);
+    let mut chunk = chunks.remove(&pos).unwrap();
+    chunk.compact_light();
+    chunk
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point3;
+
+    use super::*;
+    use crate::block::{test_block, test_registry};
+    use crate::mymath::block_pos;
+    use crate::world::GameWorld;
+    use crate::world_generator::{self, FlatGenerator};
+
+    fn sky(world: &GameWorld, x: i32, y: i32, z: i32) -> u8 {
+        world.light(LightKind::Sky, &block_pos(x, y, z)).unwrap()
+    }
+
+    /// ブロックからの赤い光の強さ
+    fn red(world: &GameWorld, x: i32, y: i32, z: i32) -> u8 {
+        world.light(LightKind::Red, &block_pos(x, y, z)).unwrap()
+    }
+
+    fn lights(world: &GameWorld, x: i32, y: i32, z: i32) -> [u8; 3] {
+        [LightKind::Red, LightKind::Green, LightKind::Blue]
+            .map(|kind| world.light(kind, &block_pos(x, y, z)).unwrap())
+    }
+
+    /// 地面の高さが`FlatGenerator`の既定値(y = 0〜2が石、y = 5が草)のワールド
+    fn flat_world() -> GameWorld {
+        let mut world =
+            GameWorld::with_generator(test_registry(), Box::new(FlatGenerator::default()), 0);
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    world
+                        .load_chunk(&ChunkPos::new(Point3::new(x, y, z)))
+                        .unwrap();
+                }
+            }
+        }
+        world
+    }
+
+    #[test]
+    fn sky_light_reaches_the_ground() {
+        let world = flat_world();
+        assert_eq!(sky(&world, 3, 30, 3), 15);
+        assert_eq!(sky(&world, 3, 6, 3), 15);
+        assert_eq!(sky(&world, 3, 5, 3), 0);
+        assert_eq!(sky(&world, -10, -5, 20), 0);
+    }
+
+    #[test]
+    fn caves_are_dark_and_torches_glow() {
+        let mut world = flat_world();
+        // 地下に空洞を掘る
+        for x in 0..5 {
+            world.remove_block(&block_pos(x, 2, 0)).unwrap();
+        }
+        assert_eq!(sky(&world, 2, 2, 0), 0);
+        assert_eq!(red(&world, 2, 2, 0), 0);
+
+        world
+            .set_block(&test_block("torch"), &block_pos(0, 2, 0))
+            .unwrap();
+        assert_eq!(red(&world, 0, 2, 0), 14);
+        assert_eq!(red(&world, 4, 2, 0), 10);
+        // 石の中には届かない
+        assert_eq!(red(&world, 4, 1, 0), 0);
+
+        // 穴を開けると、空の光が真下に差し込み、横に弱まりながら広がる
+        for y in 3..6 {
+            world.remove_block(&block_pos(4, y, 0)).unwrap();
+        }
+        assert_eq!(sky(&world, 4, 2, 0), 15);
+        assert_eq!(sky(&world, 2, 2, 0), 13);
+
+        // 塞ぐと元に戻る
+        world
+            .set_block(&test_block("stone"), &block_pos(4, 5, 0))
+            .unwrap();
+        assert_eq!(sky(&world, 4, 2, 0), 0);
+        assert_eq!(sky(&world, 2, 2, 0), 0);
+
+        world.remove_block(&block_pos(0, 2, 0)).unwrap();
+        assert_eq!(red(&world, 4, 2, 0), 0);
+    }
+
+    #[test]
+    fn light_crosses_chunk_borders() {
+        let mut world = flat_world();
+        for x in -3..3 {
+            world.remove_block(&block_pos(x, 2, 0)).unwrap();
+        }
+        world
+            .set_block(&test_block("torch"), &block_pos(2, 2, 0))
+            .unwrap();
+        assert_eq!(red(&world, -1, 2, 0), 11);
+        assert_eq!(red(&world, -3, 2, 0), 9);
+
+        // 光の届くチャンクを読み込み直しても同じ強さになる
+        let chunk_pos = ChunkPos::new(Point3::new(-1, 0, 0));
+        world.unload_chunk(&chunk_pos).unwrap();
+        world.load_chunk(&chunk_pos).unwrap();
+        assert_eq!(red(&world, -3, 2, 0), 9);
+        world
+            .set_block(&test_block("stone"), &block_pos(0, 2, 0))
+            .unwrap();
+        assert_eq!(red(&world, -1, 2, 0), 0);
+    }
+
+    #[test]
+    fn saved_light_from_removed_sources_does_not_remain() {
+        let mut world = flat_world();
+        for x in -3..3 {
+            world.remove_block(&block_pos(x, 2, 0)).unwrap();
+        }
+        world
+            .set_block(&test_block("torch"), &block_pos(2, 2, 0))
+            .unwrap();
+        let chunk_pos = ChunkPos::new(Point3::new(-1, 0, 0));
+        world.unload_chunk(&chunk_pos).unwrap();
+
+        // 解放している間に、隣のチャンクの光源を取り除く
+        world.remove_block(&block_pos(2, 2, 0)).unwrap();
+        world.load_chunk(&chunk_pos).unwrap();
+        assert!(world.get_chunk(&chunk_pos).unwrap().is_lit());
+        assert_eq!(red(&world, -1, 2, 0), 0);
+        assert_eq!(red(&world, -3, 2, 0), 0);
+        assert_eq!(red(&world, 0, 2, 0), 0);
+        // 保存されていた空の光はそのまま使う
+        assert_eq!(sky(&world, -3, 6, 0), 15);
+    }
+
+    #[test]
+    fn chunks_lit_alone_match_chunks_lit_in_the_world() {
+        let generator = FlatGenerator::default();
+        let open_sky = |x, z, y| world_generator::is_open_sky(&generator, 0, x, z, y);
+        let mut world =
+            GameWorld::with_generator(test_registry(), Box::new(FlatGenerator::default()), 0);
+        for x in -1..=1 {
+            for y in -1..=1 {
+                let pos = ChunkPos::new(Point3::new(x, y, 0));
+                let chunk = world_generator::generate_chunk(&generator, &pos, 0, &test_registry());
+                let chunk = light_chunk_alone(chunk, &test_registry(), &open_sky);
+                world.add_chunk(chunk).unwrap();
+            }
+        }
+        let expected = flat_world();
+        for x in -16..32 {
+            for y in -16..32 {
+                assert_eq!(sky(&world, x, y, 3), sky(&expected, x, y, 3), "{} {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn colored_lights_spread_per_channel() {
+        let mut world = flat_world();
+        for x in 0..9 {
+            world.remove_block(&block_pos(x, 2, 0)).unwrap();
+        }
+        // 不透明なブロックも光を出す
+        world
+            .set_block(&test_block("red_lamp"), &block_pos(0, 2, 0))
+            .unwrap();
+        world
+            .set_block(&test_block("blue_crystal"), &block_pos(8, 2, 0))
+            .unwrap();
+        assert_eq!(lights(&world, 0, 2, 0), [15, 3, 3]);
+        assert_eq!(lights(&world, 1, 2, 0), [14, 2, 6]);
+        assert_eq!(lights(&world, 8, 2, 0), [7, 6, 13]);
+        // それぞれの色は、近い方の光の強さになる
+        assert_eq!(lights(&world, 4, 2, 0), [11, 2, 9]);
+        assert_eq!(lights(&world, 7, 2, 0), [8, 5, 12]);
+
+        world.remove_block(&block_pos(0, 2, 0)).unwrap();
+        assert_eq!(lights(&world, 4, 2, 0), [0, 2, 9]);
+        assert_eq!(lights(&world, 1, 2, 0), [0, 0, 6]);
+    }
+
+    #[test]
+    fn unused_light_levels_are_forgotten() {
+        let mut world = flat_world();
+        for x in 0..9 {
+            world.remove_block(&block_pos(x, 2, 0)).unwrap();
+        }
+        for _ in 0..3 {
+            for name in ["torch", "red_lamp", "blue_crystal"] {
+                world
+                    .set_block(&test_block(name), &block_pos(4, 2, 0))
+                    .unwrap();
+                world.remove_block(&block_pos(4, 2, 0)).unwrap();
+            }
+        }
+        let chunk = world
+            .get_chunk(&ChunkPos::new(Point3::new(0, 0, 0)))
+            .unwrap();
+        let mut compacted = chunk.clone();
+        compacted.compact_light();
+        assert_eq!(chunk.light_heap_size(), compacted.light_heap_size());
+    }
+
+    #[test]
+    fn loading_a_chunk_above_blocks_the_sky() {
+        let mut world = GameWorld::new(test_registry());
+        let below = ChunkPos::new(Point3::new(0, 0, 0));
+        world.load_chunk(&below).unwrap();
+        // 上のチャンクは読み込まれていないので、空に開いているとみなす
+        assert_eq!(sky(&world, 5, 0, 5), 15);
+
+        let mut roof = Chunk::new(below.neighbor(Side::TOP));
+        for pos in BlockPosInChunk::all().filter(|pos| pos.y == 0) {
+            roof.set_block(&test_block("stone"), &pos);
+        }
+        world.add_chunk(roof).unwrap();
+        assert_eq!(sky(&world, 5, 0, 5), 0);
+        assert_eq!(sky(&world, 5, 17, 5), 15);
+    }
+}
@@ -1,4 +1,5 @@
 use std::path::Path;
+use std::sync::Arc;
 
 use imgui_sdl2::ImguiSdl2;
 use nameof::name_of_type;
@@ -12,7 +13,7 @@ use sdl2::Sdl;
 use sdl2::TimerSubsystem;
 use sdl2::VideoSubsystem;
 use specs::DispatcherBuilder;
-use specs::{Builder, World, WorldExt};
+use specs::{Builder, Entity, Join, LazyUpdate, World, WorldExt};
 
 use re::gl;
 use re::gl::Gl;
@@ -26,23 +27,43 @@ use reverie_engine as re;
 pub mod block;
 pub mod camera_computer;
 pub mod chunk;
+pub mod chunk_manager;
+pub mod chunk_renderer;
+pub mod chunk_workers;
 pub mod components;
+pub mod crafting;
+pub mod crafting_screen;
 mod ecs_resources;
 pub mod game_config;
+pub mod item_renderer;
+pub mod light;
 pub mod mymath;
+pub mod raycast;
+pub mod storage;
 mod systems;
+pub mod target_renderer;
 pub mod texture;
 pub mod world;
-use block::Block;
+pub mod world_generator;
+use block::BlockRegistry;
 use camera_computer::CameraComputer;
-use chunk::Chunk;
+use chunk::MeshingMode;
+use chunk_manager::ChunkManager;
+use chunk_renderer::ChunkRenderer;
+use chunk_workers::ChunkWorkers;
 use components::*;
+use crafting::RecipeBook;
+use crafting_screen::CraftingScreen;
 use ecs_resources::*;
+use item_renderer::ItemRenderer;
 use mymath::*;
+use storage::level::PlayerData;
 use systems::*;
+use target_renderer::TargetRenderer;
 use texture::block_texture;
 use texture::block_texture::BlockTextures;
 use world::GameWorld;
+use world_generator::NoiseGenerator;
 
 type Point3 = nalgebra::Point3<f32>;
 type Vector3 = nalgebra::Vector3<f32>;
@@ -56,13 +77,15 @@ struct Game<'a> {
     _gl_context: GLContext, /* GLContextを誰かが所有していないとOpenGLを使えない */
     gl: Gl,
     shader: Program,
+    line_shader: Program,
     imgui: imgui::Context,
     imgui_sdl2: ImguiSdl2,
     imgui_renderer: imgui_opengl_renderer::Renderer,
     event_pump: EventPump,
     _image_manager: ImageManager,
     block_atlas_texture: ImageLoadInfo<'a>,
-    block_textures: BlockTextures,
+    block_textures: Arc<BlockTextures>,
+    recipes: RecipeBook,
     world: GameWorld,
 }
 
@@ -99,6 +122,9 @@ impl<'a> Game<'a> {
         let vert_shader = Shader::from_vert_file(gl.clone(), "rsc/shader/shader.vs").unwrap();
         let frag_shader = Shader::from_frag_file(gl.clone(), "rsc/shader/shader.fs").unwrap();
         let shader = Program::from_shaders(gl.clone(), &[vert_shader, frag_shader]).unwrap();
+        let vert_shader = Shader::from_vert_file(gl.clone(), "rsc/shader/line.vs").unwrap();
+        let frag_shader = Shader::from_frag_file(gl.clone(), "rsc/shader/line.fs").unwrap();
+        let line_shader = Program::from_shaders(gl.clone(), &[vert_shader, frag_shader]).unwrap();
         println!("OK: shader program");
 
         let mut imgui = imgui::Context::create();
@@ -135,12 +161,83 @@ impl<'a> Game<'a> {
             block_atlas_texture.height,
             block_atlas_texture.gl_id
         );
+        let block_registry = Arc::new(
+            BlockRegistry::load(Path::new(game_config::BLOCK_REGISTRY_PATH)).unwrap_or_else(
+                |err| {
+                    panic!(
+                        "ERROR: {} : Failed to load {}",
+                        err,
+                        game_config::BLOCK_REGISTRY_PATH
+                    )
+                },
+            ),
+        );
+        println!(
+            "OK: load {} blocks from {}",
+            block_registry.definitions().count(),
+            game_config::BLOCK_REGISTRY_PATH
+        );
+        let recipes = RecipeBook::load(Path::new(game_config::RECIPES_PATH), &block_registry)
+            .unwrap_or_else(|err| {
+                panic!(
+                    "ERROR: {} : Failed to load {}",
+                    err,
+                    game_config::RECIPES_PATH
+                )
+            });
+        println!(
+            "OK: load {} recipes from {}",
+            recipes.recipes().count(),
+            game_config::RECIPES_PATH
+        );
         let block_textures = block_texture::get_textures_in_atlas(
+            &block_registry,
             block_atlas_texture.width,
             block_atlas_texture.height,
         );
 
-        let world = GameWorld::new();
+        let save_dir = Path::new(game_config::SAVE_DIR);
+        let world = if storage::level::level_path(save_dir).exists() {
+            let world = GameWorld::open(save_dir, block_registry).unwrap_or_else(|err| {
+                panic!(
+                    "ERROR: {} : Failed to open world {}",
+                    err,
+                    save_dir.display()
+                )
+            });
+            println!(
+                "OK: open world '{}' in {}",
+                world.name(),
+                save_dir.display()
+            );
+            world
+        } else {
+            // シード値は現在時刻から決める
+            let seed = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|duration| duration.as_nanos() as u64)
+                .unwrap_or(0);
+            let world = GameWorld::create(
+                save_dir,
+                game_config::WORLD_NAME,
+                block_registry,
+                Box::new(NoiseGenerator::default()),
+                seed,
+            )
+            .unwrap_or_else(|err| {
+                panic!(
+                    "ERROR: {} : Failed to create world {}",
+                    err,
+                    save_dir.display()
+                )
+            });
+            println!(
+                "OK: create world in {} (seed: {})",
+                save_dir.display(),
+                seed
+            );
+            world
+        };
 
         Game {
             sdl,
@@ -150,13 +247,15 @@ impl<'a> Game<'a> {
             _gl_context,
             gl,
             shader,
+            line_shader,
             imgui,
             imgui_sdl2,
             imgui_renderer,
             event_pump,
             _image_manager: image_manager,
             block_atlas_texture,
-            block_textures,
+            block_textures: Arc::new(block_textures),
+            recipes,
             world,
         }
     }
@@ -167,27 +266,52 @@ fn main() {
 
     let gl = &game.gl;
 
-    let chunk_zero_pos = ChunkPos::new(nalgebra::Point3::<i32>::new(0, 0, 0));
-    let mut chunk = Chunk::new(chunk_zero_pos);
-    for i in 0..16 {
-        for j in 0..16 {
-            chunk.set_block(&Block::GrassBlock, &BlockPosInChunk::new(i, 0, j).unwrap());
-            chunk.set_block(&Block::GrassBlock, &BlockPosInChunk::new(0, i, j).unwrap());
-            chunk.set_block(&Block::GrassBlock, &BlockPosInChunk::new(i, j, 0).unwrap());
+    let mut chunk_manager = ChunkManager::new(
+        game_config::CHUNK_LOAD_RADIUS,
+        game_config::CHUNK_LOAD_VERTICAL_RADIUS,
+    );
+    let mut chunk_renderer = ChunkRenderer::new(game_config::MESHING_MODE);
+    let mut target_renderer = TargetRenderer::new(&game.line_shader, &game.shader);
+    let mut item_renderer = ItemRenderer::new(&game.shader);
+    let mut chunk_workers = ChunkWorkers::default();
+    let saved_player = game.world.player().cloned();
+    let spawn_pos = match &saved_player {
+        Some(player) => Point3::from(player.position),
+        None => {
+            let spawn_height = game
+                .world
+                .generator()
+                .surface_height(8, 8, game.world.seed())
+                .unwrap_or(0)
+                + 1;
+            Point3::new(4.0, spawn_height as f32 * 0.5 + 1.0, 4.0)
         }
+    };
+    // 出現位置の周りのチャンクは、描画を始める前にすべて読み込んでおく
+    loop {
+        let (changes, error) =
+            chunk_manager.update(&mut game.world, &spawn_pos, &mut chunk_workers);
+        if let Some(err) = error {
+            panic!("Failed to load chunks: {:?}", err);
+        }
+        chunk_renderer.apply_changes(
+            &changes,
+            &game.world,
+            &mut chunk_workers,
+            &game.block_textures,
+        );
+        if chunk_workers
+            .pending(chunk_workers::JobKind::Generate)
+            .count()
+            == 0
+        {
+            break;
+        }
+        chunk_workers.wait_idle();
     }
-    for i in 1..15 {
-        chunk.set_block(&Block::GrassBlock, &BlockPosInChunk::new(i, i, 15).unwrap());
-    }
-    chunk.set_block(&Block::GrassBlock, &BlockPosInChunk::new(3, 3, 3).unwrap());
-    game.world.add_chunk(chunk).unwrap();
-
-    let vertex_obj = game
-        .world
-        .get_chunk(&chunk_zero_pos)
-        .unwrap()
-        .generate_vertex_obj(gl, &game.block_textures, &game.shader);
-    println!("OK: init main VBO and VAO");
+    chunk_workers.wait_idle();
+    chunk_renderer.upload(&mut chunk_workers, gl, &game.shader, usize::MAX);
+    println!("OK: load {} chunks", chunk_renderer.len());
 
     let mut world = World::new();
     world.register::<Position>();
@@ -197,22 +321,55 @@ fn main() {
     world.register::<Input>();
     world.register::<Collider>();
     world.register::<OnGround>();
+    world.register::<Inventory>();
+    world.register::<Mining>();
+    world.register::<DroppedItem>();
+    let inventory = match &saved_player {
+        Some(player) => Inventory::from_data(
+            &player.inventory,
+            player.selected_slot,
+            game.world.registry(),
+        ),
+        None => Inventory::new(),
+    };
     world.insert(DeltaTick(0));
     world.insert(game.world);
     println!("OK: init ECS World");
     let player = world
         .create_entity()
-        .with(Position(Point3::new(4.0, 2.5, 4.0)))
-        .with(Velocity::default())
+        .with(Position(spawn_pos))
+        .with(Velocity(
+            saved_player
+                .as_ref()
+                .map_or_else(Vector3::zeros, |player| Vector3::from(player.velocity)),
+        ))
         .with(Acceleration::gravity())
-        .with(Angle2::new(Deg(225.0f32), Deg(0.0f32)))
+        .with(match &saved_player {
+            Some(player) => Angle2::new(Deg(player.pitch), Deg(player.yaw)),
+            None => Angle2::new(Deg(225.0f32), Deg(0.0f32)),
+        })
         .with(Input::new())
         .with(Collider(Cuboid::new(Vector3::new(0.15, 0.45, 0.15))))
         .with(OnGround(false))
+        .with(inventory)
+        .with(Mining::default())
         .build();
+    // 前回クラフトの枠から戻しきれなかったアイテムを足元に落とす
+    if let Some(saved_player) = &saved_player {
+        let leftovers = {
+            let game_world = world.read_resource::<GameWorld>();
+            saved_player
+                .leftovers
+                .iter()
+                .filter_map(|data| ItemStack::from_data(data, game_world.registry()))
+                .collect()
+        };
+        drop_at_player(&world, player, leftovers);
+    }
     println!("OK: spawn player");
     let mut dispatcher = DispatcherBuilder::new()
         .with(AngleController, name_of_type!(AngleController), &[])
+        .with(HotbarController, name_of_type!(HotbarController), &[])
         .with(
             VelocityController,
             name_of_type!(VelocityController),
@@ -233,6 +390,19 @@ fn main() {
             name_of_type!(PositionUpdater),
             &[name_of_type!(CollisionHandler)],
         )
+        .with(
+            BlockEditor,
+            name_of_type!(BlockEditor),
+            &[
+                name_of_type!(PositionUpdater),
+                name_of_type!(HotbarController),
+            ],
+        )
+        .with(
+            ItemPickup,
+            name_of_type!(ItemPickup),
+            &[name_of_type!(BlockEditor)],
+        )
         .build();
     println!("OK: init ECS Dispatcher");
 
@@ -248,11 +418,12 @@ fn main() {
 
     /* デバッグ用 */
     let mut depth_test = true;
-    let mut blend = true;
     let mut wireframe = false;
     let mut culling = true;
+    let mut greedy_meshing = game_config::MESHING_MODE == MeshingMode::Greedy;
     let mut alpha: f32 = 1.0;
     let mut is_paused = false;
+    let mut crafting_screen = CraftingScreen::new();
     let mut show_imgui = false;
     /* ベクトルではなく色 */
     let mut material_specular = Vector3::new(0.2, 0.2, 0.2);
@@ -266,6 +437,9 @@ fn main() {
     let mut last_tick = game.timer_subsystem.ticks();
 
     'main: loop {
+        let mut clicked_buttons = Vec::new();
+        let mut wheel = 0;
+        let mut toggle_crafting = false;
         for event in game.event_pump.poll_iter() {
             game.imgui_sdl2.handle_event(&mut game.imgui, &event);
             if game.imgui_sdl2.ignore_event(&event) {
@@ -280,7 +454,17 @@ fn main() {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => {
-                    is_paused = !is_paused;
+                    if crafting_screen.is_open() {
+                        toggle_crafting = true;
+                    } else {
+                        is_paused = !is_paused;
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::E),
+                    ..
+                } if !is_paused => {
+                    toggle_crafting = true;
                 }
                 Event::KeyDown {
                     keycode: Some(Keycode::F1),
@@ -288,12 +472,30 @@ fn main() {
                 } => {
                     show_imgui = !show_imgui;
                 }
+                Event::MouseButtonDown { mouse_btn, .. } => clicked_buttons.push(mouse_btn),
+                Event::MouseWheel { y, .. } => wheel += y,
                 _ => {}
             }
         }
 
+        if toggle_crafting {
+            if crafting_screen.is_open() {
+                let leftovers = {
+                    let mut inventories = world.write_storage::<Inventory>();
+                    crafting_screen.close(
+                        inventories.get_mut(player).unwrap(),
+                        world.read_resource::<GameWorld>().registry(),
+                    )
+                };
+                drop_at_player(&world, player, leftovers);
+            } else {
+                crafting_screen.open();
+            }
+        }
+        let in_menu = is_paused || crafting_screen.is_open();
+
         // カーソルを非表示
-        game.sdl.mouse().show_cursor(is_paused);
+        game.sdl.mouse().show_cursor(in_menu);
 
         let (width, height) = game.window.drawable_size();
 
@@ -303,9 +505,14 @@ fn main() {
             let current_tick = game.timer_subsystem.ticks();
             delta_tick.0 = current_tick - last_tick;
             last_tick = current_tick;
+            if !is_paused {
+                world
+                    .write_resource::<GameWorld>()
+                    .advance_time(delta_tick.0 as u64);
+            }
         }
         // Inputコンポーネントを更新
-        if !is_paused {
+        if !in_menu {
             let mut input = world.write_storage::<Input>();
             let mouse = MouseState::new(&game.event_pump);
             let keyboard = KeyboardState::new(&game.event_pump);
@@ -317,13 +524,68 @@ fn main() {
                     center_y - mouse.y(),
                 ),
                 pressed_keys: keyboard.pressed_scancodes().collect(),
+                pressed_buttons: mouse.pressed_mouse_buttons().collect(),
+                clicked_buttons,
+                wheel,
             };
             // マウスを中心に戻す
             game.sdl
                 .mouse()
                 .warp_mouse_in_window(&game.window, center_x, center_y);
+        } else {
+            // ポーズやクラフトの画面を開いている間はプレイヤーを操作しない
+            let mut input = world.write_storage::<Input>();
+            *input.get_mut(player).unwrap() = Input::new();
         }
         dispatcher.dispatch(&mut world);
+        // 壊したブロックから落ちたアイテムを追加し、拾われたアイテムを消す
+        world.maintain();
+        {
+            let player_pos = world.read_storage::<Position>().get(player).unwrap().0;
+            let mut game_world = world.write_resource::<GameWorld>();
+            let (changes, error) =
+                chunk_manager.update(&mut game_world, &player_pos, &mut chunk_workers);
+            chunk_renderer.apply_changes(
+                &changes,
+                &game_world,
+                &mut chunk_workers,
+                &game.block_textures,
+            );
+            if let Some(err) = error {
+                eprintln!("ERROR: {} : Failed to update chunks", err);
+            }
+            chunk_renderer.upload(
+                &mut chunk_workers,
+                gl,
+                &game.shader,
+                game_config::MESH_UPLOADS_PER_FRAME,
+            );
+
+            // 視線の先のブロックと、それを壊し終わるまでの進み具合
+            let target = {
+                let pos = world.read_storage::<Position>();
+                let angle = world.read_storage::<Angle2>();
+                raycast::target_block(
+                    &game_world,
+                    pos.get(player).unwrap(),
+                    angle.get(player).unwrap(),
+                )
+            };
+            let progress = match (&target, world.read_storage::<Mining>().get(player)) {
+                (Some(hit), Some(mining)) if mining.target == Some(hit.pos) => {
+                    let block = game_world.get_block(&hit.pos).unwrap();
+                    mining.progress(game_world.registry().get(block).hardness)
+                }
+                _ => 0.0,
+            };
+            target_renderer.update(
+                gl,
+                &game_world,
+                target.as_ref().map(|hit| &hit.pos),
+                progress,
+                &game.block_textures,
+            );
+        }
         let player_pos = world.read_storage::<Position>();
         let player_pos = player_pos.get(player).unwrap();
         let player_angle = world.read_storage::<Angle2>();
@@ -342,13 +604,6 @@ fn main() {
                 gl.Disable(gl::DEPTH_TEST);
             }
 
-            if blend {
-                gl.Enable(gl::BLEND);
-                gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
-            } else {
-                gl.Disable(gl::BLEND);
-            }
-
             if wireframe {
                 gl.PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
             } else {
@@ -379,7 +634,7 @@ fn main() {
             100.0,
         );
 
-        let uniforms = {
+        let mut uniforms = {
             use c_str_macro::c_str;
             use re::shader::Uniform::*;
             let mut uniforms = UniformVariables::new();
@@ -387,6 +642,7 @@ fn main() {
             uniforms.add(c_str!("uView"), Matrix4(&view_matrix));
             uniforms.add(c_str!("uProjection"), Matrix4(&projection_matrix));
             uniforms.add(c_str!("uAlpha"), Float(alpha));
+            uniforms.add(c_str!("uLightScale"), QuadFloat(1.0, 1.0, 1.0, 1.0));
             uniforms.add(
                 c_str!("uViewPosition"),
                 TripleFloat(player_pos.0.x, player_pos.0.y, player_pos.0.z),
@@ -402,18 +658,77 @@ fn main() {
 
         unsafe {
             gl.BindTexture(gl::TEXTURE_2D, game.block_atlas_texture.gl_id);
-            vertex_obj.draw_triangles(&uniforms);
+            let positions = world.read_storage::<Position>();
+            let items = world.read_storage::<DroppedItem>();
+            item_renderer.draw(
+                gl,
+                &uniforms,
+                &world.read_resource::<GameWorld>(),
+                (&positions, &items)
+                    .join()
+                    .map(|(pos, item)| (&pos.0, item)),
+                &game.block_textures,
+            );
+            chunk_renderer.draw(gl, &mut uniforms, &CameraComputer::eye_position(player_pos));
+            target_renderer.draw(gl, &mut uniforms);
             gl.BindTexture(gl::TEXTURE_2D, 0);
         }
-        if cfg!(debug_assertions) && show_imgui {
-            game.imgui_sdl2.prepare_frame(
-                game.imgui.io_mut(),
-                &game.window,
-                &game.event_pump.mouse_state(),
-            );
+        game.imgui_sdl2.prepare_frame(
+            game.imgui.io_mut(),
+            &game.window,
+            &game.event_pump.mouse_state(),
+        );
 
-            let ui = game.imgui.frame();
-            use imgui::im_str;
+        let ui = game.imgui.frame();
+        use imgui::im_str;
+        let leftovers = {
+            let mut inventories = world.write_storage::<Inventory>();
+            crafting_screen.show(
+                &ui,
+                &game.recipes,
+                inventories.get_mut(player).unwrap(),
+                world.read_resource::<GameWorld>().registry(),
+            )
+        };
+        drop_at_player(&world, player, leftovers);
+        {
+            let game_world = world.read_resource::<GameWorld>();
+            let inventories = world.read_storage::<Inventory>();
+            let inventory = inventories.get(player).unwrap();
+            let display_size = ui.io().display_size;
+            imgui::Window::new(im_str!("Hotbar"))
+                .position(
+                    [display_size[0] / 2.0, display_size[1] - 10.0],
+                    imgui::Condition::Always,
+                )
+                .position_pivot([0.5, 1.0])
+                .no_decoration()
+                .no_inputs()
+                .always_auto_resize(true)
+                .bg_alpha(0.5)
+                .build(&ui, || {
+                    for (i, slot) in inventory.hotbar().iter().enumerate() {
+                        if i > 0 {
+                            ui.same_line(0.0);
+                        }
+                        let label = match slot {
+                            Some(stack) => format!(
+                                "{}: {} x{}",
+                                i + 1,
+                                game_world.registry().get(stack.block).name,
+                                stack.count
+                            ),
+                            None => format!("{}: -", i + 1),
+                        };
+                        if i == inventory.selected() {
+                            ui.text_colored([1.0, 1.0, 0.0, 1.0], label);
+                        } else {
+                            ui.text(label);
+                        }
+                    }
+                });
+        }
+        if cfg!(debug_assertions) && show_imgui {
             imgui::Window::new(im_str!("Information"))
                 .size([300.0, 340.0], imgui::Condition::FirstUseEver)
                 .position([5.0, 5.0], imgui::Condition::FirstUseEver)
@@ -437,9 +752,9 @@ fn main() {
                     ui.separator();
 
                     ui.checkbox(im_str!("Depth Test"), &mut depth_test);
-                    ui.checkbox(im_str!("Blend"), &mut blend);
                     ui.checkbox(im_str!("Wireframe"), &mut wireframe);
                     ui.checkbox(im_str!("Culling"), &mut culling);
+                    ui.checkbox(im_str!("Greedy Meshing"), &mut greedy_meshing);
 
                     ui.separator();
 
@@ -545,13 +860,80 @@ fn main() {
                         .range(0.0..=1.0)
                         .build(&ui, &mut specular.z);
                 });
+        }
+        game.imgui_sdl2.prepare_render(&ui, &game.window);
+        game.imgui_renderer.render(ui);
 
-            game.imgui_sdl2.prepare_render(&ui, &game.window);
-            game.imgui_renderer.render(ui);
+        let meshing = if greedy_meshing {
+            MeshingMode::Greedy
+        } else {
+            MeshingMode::Naive
+        };
+        if meshing != chunk_renderer.meshing() {
+            chunk_renderer.set_meshing(
+                meshing,
+                &world.read_resource::<GameWorld>(),
+                &mut chunk_workers,
+                &game.block_textures,
+            );
         }
 
         game.window.gl_swap_window();
 
         std::thread::sleep(std::time::Duration::new(0, 1_000_000_000u32 / 60)); // 60FPS
     }
+
+    // クラフトの枠に置いていたアイテムはインベントリに戻す。入りきらなかった分は一緒に保存する
+    let leftovers = if crafting_screen.is_open() {
+        let mut inventories = world.write_storage::<Inventory>();
+        crafting_screen.close(
+            inventories.get_mut(player).unwrap(),
+            world.read_resource::<GameWorld>().registry(),
+        )
+    } else {
+        Vec::new()
+    };
+
+    // 終了時にワールドとプレイヤーの状態を保存する
+    let player_data = {
+        let position = world.read_storage::<Position>().get(player).unwrap().0;
+        let velocity = world.read_storage::<Velocity>().get(player).unwrap().0;
+        let angles = world.read_storage::<Angle2>();
+        let angle = angles.get(player).unwrap();
+        let inventories = world.read_storage::<Inventory>();
+        let inventory = inventories.get(player).unwrap();
+        let game_world = world.read_resource::<GameWorld>();
+        let registry = game_world.registry();
+        PlayerData {
+            position: [position.x, position.y, position.z],
+            pitch: angle.pitch().0,
+            yaw: angle.yaw().0,
+            velocity: [velocity.x, velocity.y, velocity.z],
+            inventory: inventory.to_data(registry),
+            selected_slot: inventory.selected(),
+            leftovers: leftovers
+                .iter()
+                .map(|stack| stack.to_data(registry))
+                .collect(),
+        }
+    };
+    let mut game_world = world.write_resource::<GameWorld>();
+    game_world.set_player(player_data);
+    match game_world.save() {
+        Ok(()) => println!("OK: save world"),
+        Err(err) => eprintln!("ERROR: {} : Failed to save world", err),
+    }
+}
+
+/// インベントリに入りきらなかったアイテムを`player`の位置に落とす
+fn drop_at_player(world: &World, player: Entity, stacks: Vec<ItemStack>) {
+    let pos = world.read_storage::<Position>().get(player).unwrap().0;
+    for stack in stacks {
+        DroppedItem::spawn(
+            &world.read_resource::<LazyUpdate>(),
+            &world.entities(),
+            pos,
+            stack,
+        );
+    }
 }
@@ -2,6 +2,8 @@ use std::ops::{Deref, DerefMut};
 
 use nalgebra::{Point3, Vector3};
 
+use crate::block::Side;
+
 #[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub struct BlockPosInChunk(nalgebra::Point3<u32>);
 
@@ -32,6 +34,21 @@ impl BlockPosInChunk {
     pub fn index(&self) -> usize {
         (16 * 16 * self.y + 16 * self.z + self.x) as usize
     }
+
+    /// `index()`の逆
+    ///
+    /// # Panics
+    ///
+    /// `index`が16^3以上のとき
+    pub fn from_index(index: usize) -> Self {
+        let index = index as u32;
+        Self::new(index % 16, index / (16 * 16), (index / 16) % 16).unwrap()
+    }
+
+    /// チャンク内のすべての位置を`index()`の順に列挙する
+    pub fn all() -> impl Iterator<Item = BlockPosInChunk> {
+        (0..16 * 16 * 16).map(Self::from_index)
+    }
 }
 impl Deref for BlockPosInChunk {
     type Target = Point3<u32>;
@@ -45,6 +62,11 @@ impl ChunkPos {
     pub fn new(pos: Point3<i32>) -> Self {
         Self { 0: pos }
     }
+
+    /// 面`side`で接している隣のチャンク
+    pub fn neighbor(&self, side: Side) -> Self {
+        Self::new(self.0 + Vector3::from(side.normal()))
+    }
 }
 impl Deref for ChunkPos {
     type Target = Point3<i32>;
@@ -71,9 +93,70 @@ impl BlockPosInWorld {
         return Self::new(block_pos + chunk_pos);
     }
 
+    /// 描画や当たり判定に使う座標系での点を含むブロック
+    ///
+    /// その座標系では1ブロックの大きさは0.5
+    pub fn from_point(point: &Point3<f32>) -> Self {
+        Self::new(Point3::new(
+            (point.x * 2.0).floor() as i32,
+            (point.y * 2.0).floor() as i32,
+            (point.z * 2.0).floor() as i32,
+        ))
+    }
+
     pub fn inner(&self) -> Point3<i32> {
         self.0
     }
+
+    /// このブロックを含むチャンクの位置
+    ///
+    /// 負の座標でも正しく動くように、切り捨てではなく床関数で割る
+    pub fn chunk_pos(&self) -> ChunkPos {
+        ChunkPos::new(Point3::new(
+            self.x.div_euclid(16),
+            self.y.div_euclid(16),
+            self.z.div_euclid(16),
+        ))
+    }
+
+    /// チャンク内での位置
+    pub fn pos_in_chunk(&self) -> BlockPosInChunk {
+        BlockPosInChunk::new(
+            self.x.rem_euclid(16) as u32,
+            self.y.rem_euclid(16) as u32,
+            self.z.rem_euclid(16) as u32,
+        )
+        .unwrap()
+    }
+
+    /// `ChunkPos`と`BlockPosInChunk`に分解する。`from_chunk_pos`の逆
+    pub fn split(&self) -> (ChunkPos, BlockPosInChunk) {
+        (self.chunk_pos(), self.pos_in_chunk())
+    }
+
+    /// 面`side`で接している隣のブロック
+    pub fn neighbor(&self, side: Side) -> Self {
+        Self::new(self.0 + Vector3::from(side.normal()))
+    }
+
+    /// このブロックを含むチャンクと、このブロックに面や辺、頂点で接しているブロックを含む隣のチャンク
+    ///
+    /// ブロックや光が変わったときに、頂点データを作り直さなければならないチャンク。
+    /// 角の明るさは斜めに接しているブロックでも変わる
+    pub fn adjacent_chunks(&self) -> Vec<ChunkPos> {
+        let mut chunks = vec![self.chunk_pos()];
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    let chunk_pos = Self::new(self.0 + Vector3::new(x, y, z)).chunk_pos();
+                    if !chunks.contains(&chunk_pos) {
+                        chunks.push(chunk_pos);
+                    }
+                }
+            }
+        }
+        chunks
+    }
 }
 impl Deref for BlockPosInWorld {
     type Target = Point3<i32>;
@@ -126,3 +209,69 @@ impl Deref for Rad {
         &self.0
     }
 }
+
+/// テストで使うワールド座標
+#[cfg(test)]
+pub(crate) fn block_pos(x: i32, y: i32, z: i32) -> BlockPosInWorld {
+    BlockPosInWorld::new(Point3::new(x, y, z))
+}
+
+/// テストで使うチャンクの位置
+#[cfg(test)]
+pub(crate) fn chunk_pos(x: i32, y: i32, z: i32) -> ChunkPos {
+    ChunkPos::new(Point3::new(x, y, z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world(x: i32, y: i32, z: i32) -> BlockPosInWorld {
+        BlockPosInWorld::new(Point3::new(x, y, z))
+    }
+
+    #[test]
+    fn block_pos_in_chunk_index_roundtrip() {
+        for (i, pos) in BlockPosInChunk::all().enumerate() {
+            assert_eq!(pos.index(), i);
+        }
+        assert_eq!(BlockPosInChunk::all().count(), 16 * 16 * 16);
+    }
+
+    #[test]
+    fn split_positive() {
+        let (chunk, block) = world(17, 0, 35).split();
+        assert_eq!(chunk, ChunkPos::new(Point3::new(1, 0, 2)));
+        assert_eq!(block, BlockPosInChunk::new(1, 0, 3).unwrap());
+    }
+
+    #[test]
+    fn split_negative() {
+        let (chunk, block) = world(-1, -16, -17).split();
+        assert_eq!(chunk, ChunkPos::new(Point3::new(-1, -1, -2)));
+        assert_eq!(block, BlockPosInChunk::new(15, 0, 15).unwrap());
+    }
+
+    #[test]
+    fn split_and_from_chunk_pos_roundtrip() {
+        for &x in &[-33, -17, -16, -15, -1, 0, 1, 15, 16, 31, 32] {
+            let pos = world(x, -x, x * 3);
+            let (chunk, block) = pos.split();
+            assert_eq!(BlockPosInWorld::from_chunk_pos(&chunk, &block), pos);
+        }
+    }
+
+    #[test]
+    fn adjacent_chunks_include_diagonals() {
+        let chunk = |x, y, z| ChunkPos::new(Point3::new(x, y, z));
+        assert_eq!(world(5, 5, 5).adjacent_chunks(), vec![chunk(0, 0, 0)]);
+        assert_eq!(
+            world(15, 5, 5).adjacent_chunks(),
+            vec![chunk(0, 0, 0), chunk(1, 0, 0)]
+        );
+        // 角のブロックは、斜めに接しているチャンクも含めた8つのチャンクに接する
+        let corner = world(0, 15, 0).adjacent_chunks();
+        assert_eq!(corner.len(), 8);
+        assert!(corner.contains(&chunk(-1, 1, -1)));
+    }
+}
@@ -0,0 +1,181 @@
+//! 視線の先にあるブロックを探す
+
+use nalgebra::{Point3, Vector3};
+
+use crate::block::Side;
+use crate::camera_computer::CameraComputer;
+use crate::components::{Angle2, Position};
+use crate::game_config;
+use crate::mymath::BlockPosInWorld;
+use crate::world::GameWorld;
+
+/// `raycast`で見つかったブロック
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RaycastHit {
+    pub pos: BlockPosInWorld,
+    /// 視線が当たった面
+    pub side: Side,
+    /// 視線の始点から当たったところまでの距離
+    pub distance: f32,
+}
+
+/// `origin`から`direction`の向きに、`max_distance`以内で最初に当たるブロックを探す
+///
+/// 座標と距離は描画や当たり判定に使う座標系(1ブロックの大きさは0.5)で表す。
+/// ブロックの形は考えず、ブロックがあるマスに入ったら当たったとする。
+/// `origin`があるマスのブロックと、読み込まれていないチャンクは無視する
+pub fn raycast(
+    world: &GameWorld,
+    origin: &Point3<f32>,
+    direction: &Vector3<f32>,
+    max_distance: f32,
+) -> Option<RaycastHit> {
+    let direction = direction.try_normalize(f32::EPSILON)?;
+    // ここからはブロック単位で計算する
+    let origin = origin.coords * 2.0;
+    let max_distance = max_distance * 2.0;
+
+    let mut cell = origin.map(|c| c.floor() as i32);
+    let step = direction.map(|d| {
+        if d > 0.0 {
+            1
+        } else if d < 0.0 {
+            -1
+        } else {
+            0
+        }
+    });
+    // 各軸で、次のマスの境界に届くまでの距離
+    let mut next = Vector3::from_fn(|i, _| match step[i] {
+        1 => (cell[i] as f32 + 1.0 - origin[i]) / direction[i],
+        -1 => (cell[i] as f32 - origin[i]) / direction[i],
+        _ => f32::INFINITY,
+    });
+    // 各軸で、1マス進むのにかかる距離
+    let delta = direction.map(|d| 1.0 / d.abs());
+
+    loop {
+        let axis = next.imin();
+        let distance = next[axis];
+        if distance > max_distance {
+            return None;
+        }
+        cell[axis] += step[axis];
+        next[axis] += delta[axis];
+
+        let pos = BlockPosInWorld::new(cell.into());
+        if world.get_block(&pos).is_some() {
+            let mut normal = [0; 3];
+            normal[axis] = -step[axis];
+            return Some(RaycastHit {
+                pos,
+                side: Side::from_normal(normal).unwrap(),
+                distance: distance * 0.5,
+            });
+        }
+    }
+}
+
+/// `pos`にいるプレイヤーが`angle`の向きに見ている、手の届くブロック
+pub fn target_block(world: &GameWorld, pos: &Position, angle: &Angle2) -> Option<RaycastHit> {
+    raycast(
+        world,
+        &CameraComputer::eye_position(pos),
+        angle.front(),
+        game_config::REACH,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{test_block, test_registry};
+    use crate::mymath::{block_pos, Deg};
+
+    fn world_with_stone(positions: &[BlockPosInWorld]) -> GameWorld {
+        let mut world = GameWorld::new(test_registry());
+        for pos in positions {
+            world.set_block(&test_block("stone"), pos).unwrap();
+        }
+        world
+    }
+
+    fn assert_hit(hit: Option<RaycastHit>, pos: BlockPosInWorld, side: Side, distance: f32) {
+        let hit = hit.unwrap();
+        assert_eq!(hit.pos, pos);
+        assert_eq!(hit.side, side);
+        assert!(
+            (hit.distance - distance).abs() < 1e-4,
+            "{} != {}",
+            hit.distance,
+            distance
+        );
+    }
+
+    #[test]
+    fn axis_aligned_rays() {
+        let world = world_with_stone(&[block_pos(5, 0, 0), block_pos(0, -3, 0)]);
+        let origin = Point3::new(0.25, 0.25, 0.25);
+
+        let hit = raycast(&world, &origin, &Vector3::new(1.0, 0.0, 0.0), 10.0);
+        assert_hit(hit, block_pos(5, 0, 0), Side::SOUTH, 2.25);
+        let hit = raycast(&world, &origin, &Vector3::new(0.0, -2.0, 0.0), 10.0);
+        assert_hit(hit, block_pos(0, -3, 0), Side::TOP, 1.25);
+        assert!(raycast(&world, &origin, &Vector3::new(0.0, 0.0, 1.0), 10.0).is_none());
+        assert!(raycast(&world, &origin, &Vector3::zeros(), 10.0).is_none());
+    }
+
+    #[test]
+    fn max_reach() {
+        let world = world_with_stone(&[block_pos(5, 0, 0)]);
+        let origin = Point3::new(0.25, 0.25, 0.25);
+        let direction = Vector3::new(1.0, 0.0, 0.0);
+        assert!(raycast(&world, &origin, &direction, 2.2).is_none());
+        assert!(raycast(&world, &origin, &direction, 2.3).is_some());
+    }
+
+    #[test]
+    fn diagonal_rays() {
+        // ブロック単位で(0.5, 0.2, 0.5)から斜め上に進むと、x = 1、y = 1、x = 2、y = 2の順に境界を越える
+        let origin = Point3::new(0.25, 0.1, 0.25);
+        let direction = Vector3::new(1.0, 1.0, 0.0);
+        let sqrt2 = 2.0f32.sqrt();
+
+        let world = world_with_stone(&[block_pos(1, 1, 0)]);
+        let hit = raycast(&world, &origin, &direction, 10.0);
+        assert_hit(hit, block_pos(1, 1, 0), Side::BOTTOM, 0.8 * sqrt2 * 0.5);
+
+        let world = world_with_stone(&[block_pos(2, 1, 0)]);
+        let hit = raycast(&world, &origin, &direction, 10.0);
+        assert_hit(hit, block_pos(2, 1, 0), Side::SOUTH, 1.5 * sqrt2 * 0.5);
+
+        // 通り道に無いブロックには当たらない
+        let world = world_with_stone(&[block_pos(0, 1, 0), block_pos(2, 0, 0)]);
+        assert!(raycast(&world, &origin, &direction, 10.0).is_none());
+    }
+
+    #[test]
+    fn negative_coordinates() {
+        let world = world_with_stone(&[block_pos(-1, -1, -4), block_pos(-20, -1, -1)]);
+        // ブロック単位で(-0.6, -0.6, -0.6)
+        let origin = Point3::new(-0.3, -0.3, -0.3);
+
+        let hit = raycast(&world, &origin, &Vector3::new(0.0, 0.0, -1.0), 10.0);
+        assert_hit(hit, block_pos(-1, -1, -4), Side::EAST, 1.2);
+        let hit = raycast(&world, &origin, &Vector3::new(-1.0, 0.0, 0.0), 10.0);
+        assert_hit(hit, block_pos(-20, -1, -1), Side::NORTH, 9.2);
+    }
+
+    #[test]
+    fn player_looks_at_the_ground() {
+        let world = world_with_stone(&[block_pos(0, 5, 0)]);
+        // 目の高さはブロック単位で7.0
+        let pos = Position(Point3::new(0.25, 3.2, 0.25));
+        let down = Angle2::new(Deg(0.0), Deg(-90.0));
+        let hit = target_block(&world, &pos, &down);
+        assert_hit(hit, block_pos(0, 5, 0), Side::TOP, 0.5);
+
+        let up = Angle2::new(Deg(0.0), Deg(90.0));
+        assert!(target_block(&world, &pos, &up).is_none());
+    }
+}
@@ -0,0 +1,121 @@
+//! ワールドのセーブデータのディレクトリ
+//!
+//! ```text
+//! <world>/
+//!   level.json   ワールドの情報(LevelData)
+//!   player.json  プレイヤーの状態(PlayerData)
+//!   region/      リージョンファイル(see: storage::region)
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// `level.json`の形式のバージョン。これより新しいバージョンは読めない
+pub const LEVEL_FORMAT_VERSION: u32 = 1;
+
+/// ワールドの情報
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct LevelData {
+    pub format_version: u32,
+    pub name: String,
+    pub seed: u64,
+    /// `WorldGenerator::name()`
+    pub generator: String,
+    /// ゲーム内の経過時間(ミリ秒)
+    pub game_time: u64,
+}
+
+/// プレイヤーの状態
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct PlayerData {
+    pub position: [f32; 3],
+    /// 度数法
+    pub pitch: f32,
+    /// 度数法
+    pub yaw: f32,
+    pub velocity: [f32; 3],
+    /// インベントリのスロット。古いセーブデータには無い
+    #[serde(default)]
+    pub inventory: Vec<Option<ItemStackData>>,
+    /// ホットバーで選んでいるスロット
+    #[serde(default)]
+    pub selected_slot: usize,
+    /// クラフトの枠を開いたまま終了したときに、インベントリに入りきらなかったアイテム。
+    /// 次に開いたときにプレイヤーの足元に落とす
+    #[serde(default)]
+    pub leftovers: Vec<ItemStackData>,
+}
+
+/// インベントリの1つのスロットに入っているアイテム
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ItemStackData {
+    /// ブロックの名前
+    pub item: String,
+    pub count: u32,
+}
+
+pub fn level_path(world_dir: &Path) -> PathBuf {
+    world_dir.join("level.json")
+}
+
+pub fn player_path(world_dir: &Path) -> PathBuf {
+    world_dir.join("player.json")
+}
+
+pub fn region_dir(world_dir: &Path) -> PathBuf {
+    world_dir.join("region")
+}
+
+/// `level.json`を読み込む
+pub fn read_level(world_dir: &Path) -> io::Result<LevelData> {
+    let level: LevelData = read_json(&level_path(world_dir))?;
+    if level.format_version > LEVEL_FORMAT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "level format version {} is newer than supported version {}",
+                level.format_version, LEVEL_FORMAT_VERSION
+            ),
+        ));
+    }
+    Ok(level)
+}
+
+pub fn write_level(world_dir: &Path, level: &LevelData) -> io::Result<()> {
+    write_json(&level_path(world_dir), level)
+}
+
+/// `player.json`を読み込む。まだ保存されていなければ`Ok(None)`
+pub fn read_player(world_dir: &Path) -> io::Result<Option<PlayerData>> {
+    let path = player_path(world_dir);
+    if !path.exists() {
+        return Ok(None);
+    }
+    read_json(&path).map(Some)
+}
+
+pub fn write_player(world_dir: &Path, player: &PlayerData) -> io::Result<()> {
+    write_json(&player_path(world_dir), player)
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> io::Result<T> {
+    let text = fs::read_to_string(path)?;
+    serde_json::from_str(&text).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), err),
+        )
+    })
+}
+
+/// 書き込みの途中で終了しても元のファイルが壊れないように、一時ファイルに書いてから置き換える
+fn write_json<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    let text = serde_json::to_string_pretty(value)?;
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, text)?;
+    fs::rename(&temp_path, path)
+}
@@ -0,0 +1,105 @@
+//! チャンクの保存先
+
+use std::collections::HashMap;
+use std::io;
+
+use crate::chunk::Chunk;
+use crate::mymath::ChunkPos;
+
+pub use region::RegionStorage;
+
+pub mod level;
+pub mod region;
+
+/// 解放したチャンクを保存し、再び読み込むための保存先
+pub trait ChunkStorage: Send + Sync {
+    /// 保存されているチャンクを読み込む。保存されていなければ`Ok(None)`
+    fn load_chunk(&mut self, pos: &ChunkPos) -> io::Result<Option<Chunk>>;
+
+    /// チャンクを保存する。同じ位置のチャンクがすでに保存されていれば上書きする
+    fn save_chunk(&mut self, chunk: &Chunk) -> io::Result<()>;
+}
+
+/// メモリ上にチャンクを保存する
+///
+/// ゲームを終了すると失われるが、遠くへ行って戻ってきたときに変更が消えないようにできる
+#[derive(Default)]
+pub struct MemoryChunkStorage {
+    chunks: HashMap<ChunkPos, Chunk>,
+}
+
+impl MemoryChunkStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ChunkStorage for MemoryChunkStorage {
+    fn load_chunk(&mut self, pos: &ChunkPos) -> io::Result<Option<Chunk>> {
+        Ok(self.chunks.get(pos).cloned())
+    }
+
+    fn save_chunk(&mut self, chunk: &Chunk) -> io::Result<()> {
+        self.chunks.insert(*chunk.position(), chunk.clone());
+        Ok(())
+    }
+}
+
+/// 読み込みにも保存にも失敗する保存先
+#[cfg(test)]
+pub(crate) struct BrokenStorage;
+
+#[cfg(test)]
+impl ChunkStorage for BrokenStorage {
+    fn load_chunk(&mut self, _pos: &ChunkPos) -> io::Result<Option<Chunk>> {
+        Err(io::Error::new(io::ErrorKind::InvalidData, "broken chunk"))
+    }
+
+    fn save_chunk(&mut self, _chunk: &Chunk) -> io::Result<()> {
+        Err(io::Error::other("broken storage"))
+    }
+}
+
+/// テストで使う一時ディレクトリ。`Drop`で中身ごと削除する
+#[cfg(test)]
+pub(crate) struct TempDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl std::ops::Deref for TempDir {
+    type Target = std::path::Path;
+
+    fn deref(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<std::path::Path> for TempDir {
+    fn as_ref(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// テストごとに別の一時ディレクトリを作る
+#[cfg(test)]
+pub(crate) fn temp_dir(name: &str) -> TempDir {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "rustycraft-test-{}-{}-{}",
+        std::process::id(),
+        name,
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    TempDir(dir)
+}
@@ -0,0 +1,400 @@
+//! リージョンファイル
+//!
+//! 水平方向に`REGION_SIZE`×`REGION_SIZE`個のチャンクを1つのファイルにまとめて保存する。
+//! 垂直方向(ChunkPosのy)はファイルを分ける。
+//! ファイル名は`r.{region_x}.{chunk_y}.{region_z}.rcr`。
+//!
+//! ファイルは`SECTOR_SIZE`バイトのセクターに区切られている。数値はすべてリトルエンディアン。
+//!
+//! ```text
+//! header   (u32 × 2) × REGION_SIZE^2
+//!          各チャンクの(開始セクター, セクター数)。チャンクの添字は local_x + local_z * REGION_SIZE。
+//!          開始セクターが0のチャンクはまだ保存されていない
+//! chunks   各チャンクはセクターの先頭から始まる
+//!          length      u32  compression以降のバイト数
+//!          compression u8   COMPRESSION_ZLIB
+//!          data        [u8; length - 1]  Chunk::write_toで書き出したデータを圧縮したもの
+//! ```
+//!
+//! チャンクを書き換えるとき、割り当て済みのセクターに収まればその場で上書きする。
+//! 収まらなければ空いているセクターを探し、なければファイルの末尾に追加する。
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::block::BlockRegistry;
+use crate::chunk::Chunk;
+use crate::mymath::ChunkPos;
+
+use super::ChunkStorage;
+
+/// 1つのリージョンに含まれるチャンクの数(x方向とz方向それぞれ)
+pub const REGION_SIZE: i32 = 32;
+const CHUNKS_PER_REGION: usize = (REGION_SIZE * REGION_SIZE) as usize;
+const SECTOR_SIZE: u64 = 4096;
+const HEADER_SECTORS: u32 = ((CHUNKS_PER_REGION * 8) as u64 / SECTOR_SIZE) as u32;
+const COMPRESSION_ZLIB: u8 = 1;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+struct Location {
+    sector: u32,
+    sector_count: u32,
+}
+
+/// 1つのリージョンファイル
+pub struct RegionFile {
+    file: File,
+    locations: Vec<Location>,
+}
+
+impl RegionFile {
+    /// リージョンファイルを開く。存在しなければ作る
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut locations = vec![Location::default(); CHUNKS_PER_REGION];
+        if file.metadata()?.len() == 0 {
+            file.write_all(&vec![0u8; (HEADER_SECTORS as u64 * SECTOR_SIZE) as usize])?;
+        } else {
+            let mut header = vec![0u8; CHUNKS_PER_REGION * 8];
+            file.seek(SeekFrom::Start(0))?;
+            file.read_exact(&mut header)?;
+            for (i, location) in locations.iter_mut().enumerate() {
+                let entry = &header[i * 8..i * 8 + 8];
+                location.sector = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]);
+                location.sector_count =
+                    u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]);
+            }
+        }
+        Ok(Self { file, locations })
+    }
+
+    /// チャンクのデータを読み込んで展開する。保存されていなければ`Ok(None)`
+    ///
+    /// `index`は local_x + local_z * REGION_SIZE
+    pub fn read(&mut self, index: usize) -> io::Result<Option<Vec<u8>>> {
+        let location = self.locations[index];
+        if location.sector == 0 {
+            return Ok(None);
+        }
+        self.file
+            .seek(SeekFrom::Start(location.sector as u64 * SECTOR_SIZE))?;
+        let mut header = [0u8; 5];
+        self.file.read_exact(&mut header)?;
+        let length = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        if length == 0 || 4 + length as u64 > location.sector_count as u64 * SECTOR_SIZE {
+            return Err(invalid_data("chunk length exceeds its sectors"));
+        }
+        if header[4] != COMPRESSION_ZLIB {
+            return Err(invalid_data("unknown compression type"));
+        }
+        let mut compressed = vec![0u8; length as usize - 1];
+        self.file.read_exact(&mut compressed)?;
+        let mut data = Vec::new();
+        ZlibDecoder::new(&compressed[..]).read_to_end(&mut data)?;
+        Ok(Some(data))
+    }
+
+    /// チャンクのデータを圧縮して書き込む
+    pub fn write(&mut self, index: usize, data: &[u8]) -> io::Result<()> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+
+        let length = compressed.len() as u32 + 1;
+        let sector_count = (4 + length as u64).div_ceil(SECTOR_SIZE) as u32;
+
+        let old = self.locations[index];
+        let sector = if old.sector != 0 && sector_count <= old.sector_count {
+            old.sector
+        } else {
+            // 書き終わるまでは古いデータを読めるように、古いセクターも使用中として扱う
+            self.find_free_sectors(sector_count)
+        };
+
+        self.file
+            .seek(SeekFrom::Start(sector as u64 * SECTOR_SIZE))?;
+        let mut buffer = Vec::with_capacity((sector_count as u64 * SECTOR_SIZE) as usize);
+        buffer.extend_from_slice(&length.to_le_bytes());
+        buffer.push(COMPRESSION_ZLIB);
+        buffer.extend_from_slice(&compressed);
+        // セクターの終わりまで埋めておくと、ファイルの長さが常にセクターの倍数になる
+        buffer.resize((sector_count as u64 * SECTOR_SIZE) as usize, 0);
+        self.file.write_all(&buffer)?;
+
+        self.set_location(
+            index,
+            Location {
+                sector,
+                sector_count,
+            },
+        )
+    }
+
+    /// `sector_count`個の連続した空きセクターの先頭を探す。なければファイルの末尾
+    fn find_free_sectors(&self, sector_count: u32) -> u32 {
+        let mut used: Vec<(u32, u32)> = self
+            .locations
+            .iter()
+            .filter(|location| location.sector != 0)
+            .map(|location| (location.sector, location.sector + location.sector_count))
+            .collect();
+        used.sort_unstable();
+        let mut candidate = HEADER_SECTORS;
+        for (begin, end) in used {
+            if begin >= candidate + sector_count {
+                return candidate;
+            }
+            candidate = candidate.max(end);
+        }
+        candidate
+    }
+
+    /// ヘッダーを書き換えてから、メモリ上の位置を変える
+    fn set_location(&mut self, index: usize, location: Location) -> io::Result<()> {
+        let mut entry = [0u8; 8];
+        entry[..4].copy_from_slice(&location.sector.to_le_bytes());
+        entry[4..].copy_from_slice(&location.sector_count.to_le_bytes());
+        self.file.seek(SeekFrom::Start(index as u64 * 8))?;
+        self.file.write_all(&entry)?;
+        self.locations[index] = location;
+        Ok(())
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// ディレクトリ内のリージョンファイルにチャンクを保存する
+pub struct RegionStorage {
+    directory: PathBuf,
+    registry: Arc<BlockRegistry>,
+    regions: HashMap<(i32, i32, i32), RegionFile>,
+}
+
+impl RegionStorage {
+    /// `directory`をリージョンファイルの置き場所にする。存在しなければ作る
+    ///
+    /// チャンクの中のブロックは名前で保存し、読み込むときに`registry`から探す
+    pub fn open(directory: &Path, registry: Arc<BlockRegistry>) -> io::Result<Self> {
+        std::fs::create_dir_all(directory)?;
+        Ok(Self {
+            directory: directory.to_path_buf(),
+            registry,
+            regions: HashMap::new(),
+        })
+    }
+
+    /// リージョンの位置と、リージョン内でのチャンクの添字
+    fn locate(pos: &ChunkPos) -> ((i32, i32, i32), usize) {
+        let region = (
+            pos.x.div_euclid(REGION_SIZE),
+            pos.y,
+            pos.z.div_euclid(REGION_SIZE),
+        );
+        let local_x = pos.x.rem_euclid(REGION_SIZE);
+        let local_z = pos.z.rem_euclid(REGION_SIZE);
+        (region, (local_x + local_z * REGION_SIZE) as usize)
+    }
+
+    fn region(&mut self, region: (i32, i32, i32)) -> io::Result<&mut RegionFile> {
+        if !self.regions.contains_key(&region) {
+            let (x, y, z) = region;
+            let path = self.directory.join(format!("r.{}.{}.{}.rcr", x, y, z));
+            self.regions.insert(region, RegionFile::open(&path)?);
+        }
+        Ok(self.regions.get_mut(&region).unwrap())
+    }
+}
+
+impl ChunkStorage for RegionStorage {
+    fn load_chunk(&mut self, pos: &ChunkPos) -> io::Result<Option<Chunk>> {
+        let (region, index) = Self::locate(pos);
+        match self.region(region)?.read(index)? {
+            Some(data) => Ok(Some(Chunk::read_from(&self.registry, &mut &data[..])?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save_chunk(&mut self, chunk: &Chunk) -> io::Result<()> {
+        let (region, index) = Self::locate(chunk.position());
+        let mut data = Vec::new();
+        chunk.write_to(&self.registry, &mut data)?;
+        self.region(region)?.write(index, &data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point3;
+
+    use super::super::temp_dir;
+    use super::*;
+    use crate::block::{test_block, test_registry};
+    use crate::mymath::BlockPosInChunk;
+
+    /// 圧縮しても小さくならないデータ
+    fn random_bytes(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn region_file_roundtrip() {
+        let dir = temp_dir("roundtrip");
+        let path = dir.join("r.0.0.0.rcr");
+        let mut region = RegionFile::open(&path).unwrap();
+        assert_eq!(region.read(5).unwrap(), None);
+        region.write(5, b"hello").unwrap();
+        region.write(1023, &random_bytes(10000, 1)).unwrap();
+        assert_eq!(region.read(5).unwrap().unwrap(), b"hello");
+
+        let mut reopened = RegionFile::open(&path).unwrap();
+        assert_eq!(reopened.read(5).unwrap().unwrap(), b"hello");
+        assert_eq!(
+            reopened.read(1023).unwrap().unwrap(),
+            random_bytes(10000, 1)
+        );
+        assert_eq!(reopened.read(0).unwrap(), None);
+    }
+
+    #[test]
+    fn rewrite_in_place_and_grow() {
+        let dir = temp_dir("grow");
+        let path = dir.join("r.0.0.0.rcr");
+        let mut region = RegionFile::open(&path).unwrap();
+        region.write(0, b"small").unwrap();
+        region.write(1, b"neighbor").unwrap();
+        let first = region.locations[0];
+        let len = std::fs::metadata(&path).unwrap().len();
+
+        // 同じセクターに収まるならその場で上書きする
+        region.write(0, b"still small").unwrap();
+        assert_eq!(region.locations[0], first);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+
+        // 収まらなくなったら別の場所に移し、隣のチャンクは壊さない
+        let big = random_bytes(3 * SECTOR_SIZE as usize, 2);
+        region.write(0, &big).unwrap();
+        assert_ne!(region.locations[0].sector, first.sector);
+        assert!(region.locations[0].sector_count > 3);
+        assert_eq!(region.read(0).unwrap().unwrap(), big);
+        assert_eq!(region.read(1).unwrap().unwrap(), b"neighbor");
+
+        // 空いたセクターは再利用される
+        region.write(2, b"reuse").unwrap();
+        assert_eq!(region.locations[2].sector, first.sector);
+        assert_eq!(std::fs::metadata(&path).unwrap().len() % SECTOR_SIZE, 0);
+    }
+
+    #[test]
+    fn failed_writes_keep_old_data() {
+        let dir = temp_dir("failed");
+        let path = dir.join("r.0.0.0.rcr");
+        let mut region = RegionFile::open(&path).unwrap();
+        region.write(0, b"old").unwrap();
+        let old = region.locations[0];
+
+        // 大きくなったチャンクは、古いセクターに重ねずに書く
+        let big = random_bytes(SECTOR_SIZE as usize, 3);
+        region.write(0, &big).unwrap();
+        assert!(region.locations[0].sector >= old.sector + old.sector_count);
+
+        // 書き込めなくても、前に保存したデータは読める
+        let mut read_only = RegionFile {
+            file: File::open(&path).unwrap(),
+            locations: region.locations.clone(),
+        };
+        let saved = read_only.locations[0];
+        assert!(read_only
+            .write(0, &random_bytes(3 * SECTOR_SIZE as usize, 4))
+            .is_err());
+        assert_eq!(read_only.locations[0], saved);
+        assert_eq!(read_only.read(0).unwrap().unwrap(), big);
+    }
+
+    #[test]
+    fn storage_saves_and_loads_chunks() {
+        let dir = temp_dir("storage");
+        let positions = [
+            ChunkPos::new(Point3::new(0, 0, 0)),
+            ChunkPos::new(Point3::new(-1, 0, -1)),
+            ChunkPos::new(Point3::new(31, 0, 32)),
+            ChunkPos::new(Point3::new(-33, -2, 5)),
+        ];
+        {
+            let mut storage = RegionStorage::open(&dir, test_registry()).unwrap();
+            for (i, pos) in positions.iter().enumerate() {
+                let mut chunk = Chunk::new(*pos);
+                chunk.set_block(
+                    &test_block("stone"),
+                    &BlockPosInChunk::new(i as u32, 0, 15).unwrap(),
+                );
+                storage.save_chunk(&chunk).unwrap();
+            }
+        }
+
+        let mut storage = RegionStorage::open(&dir, test_registry()).unwrap();
+        for (i, pos) in positions.iter().enumerate() {
+            let chunk = storage.load_chunk(pos).unwrap().unwrap();
+            assert_eq!(chunk.position(), pos);
+            assert_eq!(
+                chunk.get_block(&BlockPosInChunk::new(i as u32, 0, 15).unwrap()),
+                Some(test_block("stone"))
+            );
+        }
+        assert!(storage
+            .load_chunk(&ChunkPos::new(Point3::new(1, 0, 0)))
+            .unwrap()
+            .is_none());
+        // x: -1, 0, 31 と -33 は別のリージョン
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 4);
+    }
+
+    #[test]
+    fn world_reads_chunks_lazily_from_regions() {
+        use crate::mymath::BlockPosInWorld;
+        use crate::world::GameWorld;
+        use crate::world_generator::FlatGenerator;
+
+        let dir = temp_dir("world");
+        let pos = BlockPosInWorld::new(Point3::new(-20, 8, 40));
+        {
+            let mut world =
+                GameWorld::with_generator(test_registry(), Box::new(FlatGenerator::default()), 0);
+            world.set_storage(Box::new(
+                RegionStorage::open(&dir, test_registry()).unwrap(),
+            ));
+            world.set_block(&test_block("dirt"), &pos).unwrap();
+            world.unload_chunk(&pos.chunk_pos()).unwrap();
+        }
+
+        let mut world =
+            GameWorld::with_generator(test_registry(), Box::new(FlatGenerator::default()), 0);
+        world.set_storage(Box::new(
+            RegionStorage::open(&dir, test_registry()).unwrap(),
+        ));
+        assert_eq!(world.get_block(&pos), None);
+        world.load_chunk(&pos.chunk_pos()).unwrap();
+        assert_eq!(world.get_block(&pos), Some(test_block("dirt")));
+    }
+}
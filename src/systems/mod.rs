@@ -1,11 +1,15 @@
 use nalgebra::{Isometry3, Point3, Vector3};
 use parry3d::bounding_volume::{BoundingVolume, AABB};
 use parry3d::query::{Ray, RayCast};
-use specs::ReadExpect;
-use specs::{Join, Read, ReadStorage, System, WriteStorage};
+use sdl2::keyboard::Scancode;
+use sdl2::mouse::MouseButton;
+use specs::{Entities, Join, LazyUpdate, Read, ReadStorage, System, WriteStorage};
+use specs::{ReadExpect, WriteExpect};
 
+use crate::block::{self, Block};
 use crate::game_config;
-use crate::mymath::ChunkPos;
+use crate::mymath::BlockPosInWorld;
+use crate::raycast;
 use crate::world::GameWorld;
 
 use super::components::*;
@@ -126,6 +130,9 @@ impl<'a> System<'a> for VelocityController {
 }
 
 /// 当たり判定を行い、VelocityやOnGroundを更新する
+///
+/// エンティティがこのフレームで動く範囲にあるブロックとだけ判定する。
+/// 読み込まれていないチャンクは固体とみなす(see: `GameWorld::collect_collision_aabbs`)
 pub struct CollisionHandler;
 
 impl<'a> System<'a> for CollisionHandler {
@@ -133,31 +140,30 @@ impl<'a> System<'a> for CollisionHandler {
         Read<'a, DeltaTick>,
         ReadExpect<'a, GameWorld>,
         ReadStorage<'a, Collider>,
-        ReadStorage<'a, Position>,
+        WriteStorage<'a, Position>,
         WriteStorage<'a, Velocity>,
         WriteStorage<'a, OnGround>,
     );
 
-    fn run(&mut self, (delta, world, collider, pos, mut vel, mut is_on_ground): Self::SystemData) {
+    fn run(
+        &mut self,
+        (delta, world, collider, mut pos, mut vel, mut is_on_ground): Self::SystemData,
+    ) {
+        // エンティティごとに使い回す
+        let mut aabbs: Vec<AABB> = Vec::new();
         for (collider, pos, vel, is_on_ground) in
-            (&collider, &pos, &mut vel, &mut is_on_ground).join()
+            (&collider, &mut pos, &mut vel, &mut is_on_ground).join()
         {
-            let aabbs = {
-                let mut aabbs: Vec<AABB> = Vec::new();
-                /* TODO: エンティティの周りのチャンクを取得する処理 */
-                let nearby_chunks = vec![world
-                    .get_chunk(&ChunkPos::new(Point3::<i32>::new(0, 0, 0)))
-                    .unwrap()];
-                for chunk in nearby_chunks {
-                    aabbs.append(&mut chunk.aabbs_for_collision());
-                }
-                aabbs
-            };
-
             let entity_aabb = collider
                 .0
                 .aabb(&Isometry3::new(pos.0.coords, Vector3::zeros()));
 
+            aabbs.clear();
+            world.collect_collision_aabbs(
+                &CollisionHandler::swept_aabb(&entity_aabb, &vel.0, &delta),
+                &mut aabbs,
+            );
+
             let aabbs_and_extended_aabbs: Vec<(AABB, AABB)> = aabbs
                 .iter()
                 .map(|aabb| {
@@ -172,14 +178,32 @@ impl<'a> System<'a> for CollisionHandler {
                 })
                 .collect();
 
+            let original_vel = vel.0;
             while CollisionHandler::get_adjusted_vel(
                 &aabbs_and_extended_aabbs,
-                &pos,
+                pos,
                 vel,
                 &delta,
                 &entity_aabb,
             ) {}
 
+            // 地面にいて横方向に進めなかったときは、段差を登れないか試す
+            let blocked = Vector3::new(original_vel.x - vel.0.x, 0.0, original_vel.z - vel.0.z)
+                .norm()
+                > f32::EPSILON;
+            if is_on_ground.0 && blocked && vel.0.y <= 0.0 {
+                if let Some(stepped) = CollisionHandler::step_up(
+                    &aabbs_and_extended_aabbs,
+                    pos,
+                    &original_vel,
+                    &delta,
+                    &entity_aabb,
+                ) {
+                    *pos = stepped;
+                    vel.0 = Vector3::new(original_vel.x, 0.0, original_vel.z);
+                }
+            }
+
             let mut on_ground_test_vel = Velocity(Vector3::new(0.0f32, -0.001f32, 0.0f32));
 
             if CollisionHandler::get_adjusted_vel(
@@ -198,6 +222,56 @@ impl<'a> System<'a> for CollisionHandler {
 }
 
 impl CollisionHandler {
+    /// このフレームでエンティティが触れるかもしれない範囲
+    ///
+    /// 今の位置と`entity_vel`で動いた先に加えて、段差を登る分と、地面にいるかを調べる分を含む
+    fn swept_aabb(entity_aabb: &AABB, entity_vel: &Vector3<f32>, delta: &DeltaTick) -> AABB {
+        let moved = AABB::new(
+            entity_aabb.mins + entity_vel * delta.0 as f32,
+            entity_aabb.maxs + entity_vel * delta.0 as f32,
+        );
+        let swept = entity_aabb.merged(&moved);
+        AABB::new(
+            swept.mins - Vector3::new(0.001, 0.001 * delta.0 as f32 + 0.001, 0.001),
+            swept.maxs + Vector3::new(0.001, game_config::STEP_HEIGHT + 0.002, 0.001),
+        )
+    }
+
+    /// `game_config::STEP_HEIGHT`だけ上に移動すれば横方向に`entity_vel`で進めるなら、移動した位置
+    fn step_up(
+        aabbs_and_extended_aabbs: &Vec<(AABB, AABB)>,
+        entity_pos: &Position,
+        entity_vel: &Vector3<f32>,
+        delta: &DeltaTick,
+        entity_aabb: &AABB,
+    ) -> Option<Position> {
+        // 段差の上面にちょうど接すると、横方向の当たり判定で引っかかるので少し余分に上げる
+        let stepped =
+            Position(entity_pos.0 + Vector3::new(0.0, game_config::STEP_HEIGHT + 0.001, 0.0));
+        let stepped_aabb = AABB::from_half_extents(stepped.0, entity_aabb.half_extents());
+        let shrunk_aabb = AABB::from_half_extents(
+            stepped.0,
+            entity_aabb.half_extents() - Vector3::repeat(0.001),
+        );
+        if aabbs_and_extended_aabbs
+            .iter()
+            .any(|(aabb, _)| aabb.intersects(&shrunk_aabb))
+        {
+            return None;
+        }
+        let mut stepped_vel = Velocity(Vector3::new(entity_vel.x, 0.0, entity_vel.z));
+        if CollisionHandler::get_adjusted_vel(
+            aabbs_and_extended_aabbs,
+            &stepped,
+            &mut stepped_vel,
+            delta,
+            &stepped_aabb,
+        ) {
+            return None;
+        }
+        Some(stepped)
+    }
+
     /// 戻り値: entity_velが更新されたかどうか
     fn get_adjusted_vel(
         aabbs_and_extended_aabbs: &Vec<(AABB, AABB)>,
@@ -246,3 +320,627 @@ impl CollisionHandler {
         }
     }
 }
+
+/// 数字キーとマウスのホイールで、ホットバーのスロットを選ぶ
+pub struct HotbarController;
+
+impl HotbarController {
+    /// ホットバーの1番目から順に対応するキー
+    const KEYS: [Scancode; HOTBAR_SIZE] = [
+        Scancode::Num1,
+        Scancode::Num2,
+        Scancode::Num3,
+        Scancode::Num4,
+        Scancode::Num5,
+        Scancode::Num6,
+        Scancode::Num7,
+        Scancode::Num8,
+        Scancode::Num9,
+    ];
+}
+
+impl<'a> System<'a> for HotbarController {
+    type SystemData = (ReadStorage<'a, Input>, WriteStorage<'a, Inventory>);
+
+    fn run(&mut self, (input, mut inventory): Self::SystemData) {
+        for (input, inventory) in (&input, &mut inventory).join() {
+            let pressed = HotbarController::KEYS
+                .iter()
+                .position(|key| input.pressed_keys.contains(key));
+            if let Some(slot) = pressed {
+                inventory.select(slot);
+            }
+            // ホイールを上に回すと左のスロットに移る
+            inventory.scroll(-input.wheel);
+        }
+    }
+}
+
+/// 左ボタンを押し続けると視線の先のブロックを壊してアイテムとして落とし、
+/// 右クリックでホットバーで選んでいるブロックをその手前に置く
+///
+/// 壊すのにかかる時間はブロックの硬さで決まる(see: `Mining`)。
+/// 変わったチャンクは`GameWorld::take_changed_chunks`で分かる
+pub struct BlockEditor;
+
+impl<'a> System<'a> for BlockEditor {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, LazyUpdate>,
+        Read<'a, DeltaTick>,
+        WriteExpect<'a, GameWorld>,
+        ReadStorage<'a, Input>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Angle2>,
+        ReadStorage<'a, Collider>,
+        ReadStorage<'a, DroppedItem>,
+        WriteStorage<'a, Inventory>,
+        WriteStorage<'a, Mining>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            lazy,
+            delta,
+            mut world,
+            input,
+            pos,
+            angle,
+            collider,
+            items,
+            mut inventory,
+            mut mining,
+        ): Self::SystemData,
+    ) {
+        // ブロックを置く場所に重なってはいけないもの(落ちているアイテムは埋まってもよい)
+        let bodies: Vec<AABB> = (&pos, &collider, !&items)
+            .join()
+            .map(|(pos, collider, ())| {
+                collider
+                    .0
+                    .aabb(&Isometry3::new(pos.0.coords, Vector3::zeros()))
+            })
+            .collect();
+
+        for (input, pos, angle, inventory, mining) in
+            (&input, &pos, &angle, &mut inventory, &mut mining).join()
+        {
+            let hit = match raycast::target_block(&world, pos, angle) {
+                Some(hit) => hit,
+                None => {
+                    *mining = Mining::default();
+                    continue;
+                }
+            };
+            if input.pressed_buttons.contains(&MouseButton::Left) {
+                // 別のブロックに視線を移したら最初からやり直す
+                if mining.target != Some(hit.pos) {
+                    mining.target = Some(hit.pos);
+                    mining.elapsed = 0;
+                }
+                mining.elapsed += delta.0;
+                let block = world.get_block(&hit.pos).unwrap();
+                if mining.progress(world.registry().get(block).hardness) >= 1.0 {
+                    if let Err(err) = world.remove_block(&hit.pos) {
+                        eprintln!("ERROR: {} : Failed to remove block {:?}", err, hit.pos);
+                        continue;
+                    }
+                    let center = (hit.pos.cast::<f32>().coords + Vector3::repeat(0.5)) * 0.5;
+                    let stack = ItemStack { block, count: 1 };
+                    DroppedItem::spawn(&lazy, &entities, center.into(), stack);
+                    *mining = Mining::default();
+                }
+            } else {
+                *mining = Mining::default();
+            }
+            if input.clicked_buttons.contains(&MouseButton::Right) {
+                let selected = inventory.selected_item().map(|stack| stack.block);
+                if let Some(block) = selected {
+                    if BlockEditor::place(&mut world, block, &hit.pos.neighbor(hit.side), &bodies) {
+                        inventory.take_selected();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl BlockEditor {
+    /// `pos`が空いていて、置いたブロックが`bodies`のどれとも重ならなければ置く
+    ///
+    /// 戻り値: 置いたかどうか
+    fn place(world: &mut GameWorld, block: Block, pos: &BlockPosInWorld, bodies: &[AABB]) -> bool {
+        // 読み込まれていないチャンクには置かない
+        if !world.is_loaded(&pos.chunk_pos()) || world.get_block(pos).is_some() {
+            return false;
+        }
+        let aabbs = block::get_block_aabbs(world.registry().get(block), block.default_state(), pos);
+        // 接しているだけなら重なっているとはみなさない
+        let overlaps = aabbs.iter().any(|aabb| {
+            bodies.iter().any(|body| {
+                let shrunk = AABB::from_half_extents(
+                    body.center(),
+                    body.half_extents() - Vector3::repeat(0.001),
+                );
+                aabb.intersects(&shrunk)
+            })
+        });
+        if overlaps {
+            return false;
+        }
+        world.set_block(&block, pos).is_ok()
+    }
+}
+
+/// 落ちているアイテムを近くの同じアイテムとまとめ、近くにいるプレイヤーのインベントリに入れる
+///
+/// 長い間拾われなかったアイテムは消す
+pub struct ItemPickup;
+
+impl<'a> System<'a> for ItemPickup {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, DeltaTick>,
+        ReadExpect<'a, GameWorld>,
+        ReadStorage<'a, Position>,
+        WriteStorage<'a, DroppedItem>,
+        WriteStorage<'a, Inventory>,
+    );
+
+    fn run(&mut self, (entities, delta, world, pos, mut items, mut inventory): Self::SystemData) {
+        let registry = world.registry();
+        let mut dropped: Vec<(specs::Entity, Point3<f32>, DroppedItem)> = Vec::new();
+        for (entity, pos, item) in (&entities, &pos, &mut items).join() {
+            item.age = item.age.saturating_add(delta.0);
+            if item.age >= game_config::ITEM_LIFETIME {
+                entities.delete(entity).unwrap();
+            } else {
+                dropped.push((entity, pos.0, *item));
+            }
+        }
+
+        // 後から落ちたアイテムを先に落ちたアイテムにまとめる
+        for j in 1..dropped.len() {
+            for i in 0..j {
+                let (_, pos_i, item_i) = dropped[i];
+                let (_, pos_j, item_j) = dropped[j];
+                if item_i.stack.block != item_j.stack.block
+                    || item_i.stack.count == 0
+                    || (pos_i - pos_j).norm() > game_config::ITEM_MERGE_RADIUS
+                {
+                    continue;
+                }
+                let max_stack_size = registry.get(item_i.stack.block).max_stack_size;
+                let moved = item_j
+                    .stack
+                    .count
+                    .min(max_stack_size.saturating_sub(item_i.stack.count));
+                dropped[i].2.stack.count += moved;
+                dropped[j].2.stack.count -= moved;
+            }
+        }
+
+        for (pos, inventory) in (&pos, &mut inventory).join() {
+            for (_, item_pos, item) in &mut dropped {
+                if item.stack.count == 0
+                    || !item.can_pick_up()
+                    || (*item_pos - pos.0).norm() > game_config::ITEM_PICKUP_RADIUS
+                {
+                    continue;
+                }
+                item.stack.count = inventory.add(item.stack.block, item.stack.count, registry);
+            }
+        }
+
+        for (entity, _, item) in dropped {
+            if item.stack.count == 0 {
+                entities.delete(entity).unwrap();
+            } else {
+                *items.get_mut(entity).unwrap() = item;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::{Builder, RunNow, World, WorldExt};
+
+    use super::*;
+    use crate::block::{test_block, test_registry};
+    use crate::mymath::{block_pos, chunk_pos, Deg};
+    use parry3d::shape::Cuboid;
+
+    /// `pos`から落ちていくエンティティを`frames`フレームだけ動かし、最後の位置と地面にいるかを返す
+    fn fall(game_world: GameWorld, pos: Point3<f32>, frames: usize) -> (Point3<f32>, bool) {
+        let mut world = World::new();
+        world.register::<Position>();
+        world.register::<Velocity>();
+        world.register::<Acceleration>();
+        world.register::<Collider>();
+        world.register::<OnGround>();
+        world.insert(DeltaTick(16));
+        world.insert(game_world);
+        let entity = world
+            .create_entity()
+            .with(Position(pos))
+            .with(Velocity(Vector3::zeros()))
+            .with(Acceleration::gravity())
+            .with(Collider(Cuboid::new(Vector3::new(0.15, 0.45, 0.15))))
+            .with(OnGround(false))
+            .build();
+        for _ in 0..frames {
+            VelocityUpdater.run_now(&world);
+            CollisionHandler.run_now(&world);
+            PositionUpdater.run_now(&world);
+        }
+        let pos = world.read_storage::<Position>().get(entity).unwrap().0;
+        let on_ground = world.read_storage::<OnGround>().get(entity).unwrap().0;
+        (pos, on_ground)
+    }
+
+    #[test]
+    fn lands_on_blocks() {
+        let mut game_world = GameWorld::new(test_registry());
+        game_world
+            .set_block(&test_block("stone"), &block_pos(0, 0, 0))
+            .unwrap();
+        game_world.load_chunk(&chunk_pos(0, -1, 0)).unwrap();
+        let (pos, on_ground) = fall(game_world, Point3::new(0.25, 1.5, 0.25), 100);
+        assert!((pos.y - 0.95).abs() < 0.01, "{}", pos.y);
+        assert!(on_ground);
+    }
+
+    #[test]
+    fn unloaded_chunks_are_solid() {
+        let mut game_world = GameWorld::new(test_registry());
+        game_world.load_chunk(&chunk_pos(0, 0, 0)).unwrap();
+        // 下のチャンクは読み込まれていないので、チャンクの境界で止まる
+        let (pos, on_ground) = fall(game_world, Point3::new(4.0, 1.5, 4.0), 100);
+        assert!((pos.y - 0.45).abs() < 0.01, "{}", pos.y);
+        assert!(on_ground);
+    }
+
+    /// (0, 0, 0)に石がある世界と、`pos`から真下を見ているプレイヤー
+    fn setup(pos: Point3<f32>) -> (World, specs::Entity) {
+        let mut game_world = GameWorld::new(test_registry());
+        game_world
+            .set_block(&test_block("stone"), &block_pos(0, 0, 0))
+            .unwrap();
+        game_world.load_chunk(&chunk_pos(-1, 0, 0)).unwrap();
+        game_world.load_chunk(&chunk_pos(0, -1, 0)).unwrap();
+        game_world.take_changed_chunks();
+
+        let mut world = World::new();
+        world.register::<Input>();
+        world.register::<Position>();
+        world.register::<Angle2>();
+        world.register::<Collider>();
+        world.register::<Inventory>();
+        world.register::<Mining>();
+        world.register::<Velocity>();
+        world.register::<Acceleration>();
+        world.register::<OnGround>();
+        world.register::<DroppedItem>();
+        let mut inventory = Inventory::new();
+        inventory.add(test_block("oak_planks"), 1, game_world.registry());
+        world.insert(DeltaTick(0));
+        world.insert(game_world);
+        let player = world
+            .create_entity()
+            .with(Input::new())
+            .with(Position(pos))
+            .with(Angle2::new(Deg(0.0), Deg(-90.0)))
+            .with(Collider(Cuboid::new(Vector3::new(0.15, 0.45, 0.15))))
+            .with(inventory)
+            .with(Mining::default())
+            .build();
+        (world, player)
+    }
+
+    /// `pressed`を押したまま`milliseconds`だけ経ったフレームを進める
+    fn run_frame(
+        world: &mut World,
+        player: specs::Entity,
+        pressed: &[MouseButton],
+        clicked: &[MouseButton],
+        milliseconds: u32,
+    ) {
+        {
+            let mut input = world.write_storage::<Input>();
+            let input = input.get_mut(player).unwrap();
+            input.pressed_buttons = pressed.to_vec();
+            input.clicked_buttons = clicked.to_vec();
+        }
+        world.write_resource::<DeltaTick>().0 = milliseconds;
+        BlockEditor.run_now(world);
+        world.maintain();
+    }
+
+    fn click(world: &mut World, player: specs::Entity, button: MouseButton) {
+        run_frame(world, player, &[button], &[button], 0);
+    }
+
+    fn hold_left(world: &mut World, player: specs::Entity, milliseconds: u32) {
+        run_frame(world, player, &[MouseButton::Left], &[], milliseconds);
+    }
+
+    #[test]
+    fn breaks_and_places_blocks() {
+        let (mut world, player) = setup(Point3::new(0.25, 2.0, 0.25));
+
+        click(&mut world, player, MouseButton::Right);
+        let game_world = world.read_resource::<GameWorld>();
+        assert_eq!(
+            game_world.get_block(&block_pos(0, 1, 0)),
+            Some(test_block("oak_planks"))
+        );
+        drop(game_world);
+        // 置いたブロックはインベントリから無くなる
+        assert!(world
+            .read_storage::<Inventory>()
+            .get(player)
+            .unwrap()
+            .selected_item()
+            .is_none());
+
+        // 硬さ2.0の板材は2秒、硬さ1.5の石は1.5秒で壊れる
+        hold_left(&mut world, player, 1000);
+        hold_left(&mut world, player, 900);
+        assert!(world
+            .read_resource::<GameWorld>()
+            .get_block(&block_pos(0, 1, 0))
+            .is_some());
+        {
+            let mining = world.read_storage::<Mining>();
+            let mining = mining.get(player).unwrap();
+            assert_eq!(mining.target, Some(block_pos(0, 1, 0)));
+            assert!((mining.progress(2.0) - 1900.0 / 2000.0).abs() < 1e-4);
+        }
+        hold_left(&mut world, player, 100);
+        assert!(world
+            .read_storage::<Mining>()
+            .get(player)
+            .unwrap()
+            .target
+            .is_none());
+        hold_left(&mut world, player, 1500);
+        let mut game_world = world.write_resource::<GameWorld>();
+        assert!(game_world.get_block(&block_pos(0, 1, 0)).is_none());
+        assert!(game_world.get_block(&block_pos(0, 0, 0)).is_none());
+        // 境界にあるブロックを変えると、隣のチャンクも作り直す
+        let changed = game_world.take_changed_chunks();
+        assert!(changed.contains(&chunk_pos(0, 0, 0)));
+        assert!(changed.contains(&chunk_pos(-1, 0, 0)));
+        assert!(changed.contains(&chunk_pos(0, -1, 0)));
+        drop(game_world);
+
+        // 壊したブロックはブロックの中心にアイテムとして落ちる
+        let positions = world.read_storage::<Position>();
+        let items = world.read_storage::<DroppedItem>();
+        let mut dropped: Vec<_> = (&positions, &items)
+            .join()
+            .map(|(pos, item)| (pos.0, item.stack))
+            .collect();
+        dropped.sort_by(|a, b| a.0.y.total_cmp(&b.0.y));
+        assert_eq!(
+            dropped,
+            [
+                (
+                    Point3::new(0.25, 0.25, 0.25),
+                    ItemStack {
+                        block: test_block("stone"),
+                        count: 1
+                    }
+                ),
+                (
+                    Point3::new(0.25, 0.75, 0.25),
+                    ItemStack {
+                        block: test_block("oak_planks"),
+                        count: 1
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn places_blocks_over_dropped_items() {
+        let (mut world, player) = setup(Point3::new(0.25, 2.0, 0.25));
+        drop_item(&mut world, Point3::new(0.25, 0.75, 0.25), "stone", 1);
+
+        click(&mut world, player, MouseButton::Right);
+        assert_eq!(
+            world
+                .read_resource::<GameWorld>()
+                .get_block(&block_pos(0, 1, 0)),
+            Some(test_block("oak_planks"))
+        );
+    }
+
+    /// `pos`に`count`個の`name`のブロックを落とす
+    fn drop_item(world: &mut World, pos: Point3<f32>, name: &str, count: u32) -> specs::Entity {
+        let stack = ItemStack {
+            block: test_block(name),
+            count,
+        };
+        let entity = DroppedItem::spawn(
+            &world.read_resource::<LazyUpdate>(),
+            &world.entities(),
+            pos,
+            stack,
+        );
+        world.maintain();
+        entity
+    }
+
+    fn run_pickup(world: &mut World, milliseconds: u32) {
+        world.write_resource::<DeltaTick>().0 = milliseconds;
+        ItemPickup.run_now(world);
+        world.maintain();
+    }
+
+    fn item_count(world: &World, entity: specs::Entity) -> Option<u32> {
+        world
+            .read_storage::<DroppedItem>()
+            .get(entity)
+            .map(|item| item.stack.count)
+    }
+
+    #[test]
+    fn merges_nearby_items() {
+        let (mut world, _) = setup(Point3::new(0.25, 20.0, 0.25));
+        let first = drop_item(&mut world, Point3::new(0.0, 0.0, 0.0), "stone", 60);
+        let second = drop_item(&mut world, Point3::new(0.3, 0.0, 0.0), "stone", 10);
+        let far = drop_item(&mut world, Point3::new(3.0, 0.0, 0.0), "stone", 1);
+        let dirt = drop_item(&mut world, Point3::new(0.0, 0.3, 0.0), "dirt", 1);
+        run_pickup(&mut world, 0);
+        // 64個より多くはまとまらない
+        assert_eq!(item_count(&world, first), Some(64));
+        assert_eq!(item_count(&world, second), Some(6));
+        assert_eq!(item_count(&world, far), Some(1));
+        assert_eq!(item_count(&world, dirt), Some(1));
+
+        let third = drop_item(&mut world, Point3::new(0.0, 0.0, 0.3), "stone", 6);
+        run_pickup(&mut world, 0);
+        assert_eq!(item_count(&world, second), Some(12));
+        assert_eq!(item_count(&world, third), None);
+    }
+
+    #[test]
+    fn picks_up_nearby_items() {
+        let (mut world, player) = setup(Point3::new(0.25, 1.0, 0.25));
+        let near = drop_item(&mut world, Point3::new(0.25, 0.6, 0.5), "stone", 3);
+        let far = drop_item(&mut world, Point3::new(2.0, 0.6, 0.25), "dirt", 1);
+
+        // 落ちてすぐには拾えない
+        run_pickup(&mut world, game_config::ITEM_PICKUP_DELAY - 1);
+        assert_eq!(item_count(&world, near), Some(3));
+        run_pickup(&mut world, 1);
+        assert_eq!(item_count(&world, near), None);
+        assert_eq!(item_count(&world, far), Some(1));
+        let stacks: Vec<_> = world
+            .read_storage::<Inventory>()
+            .get(player)
+            .unwrap()
+            .slots()
+            .iter()
+            .flatten()
+            .map(|stack| (stack.block, stack.count))
+            .collect();
+        assert_eq!(
+            stacks,
+            [(test_block("oak_planks"), 1), (test_block("stone"), 3)]
+        );
+
+        // 入りきらなかった分は落ちたまま残る
+        {
+            let mut inventory = world.write_storage::<Inventory>();
+            let inventory = inventory.get_mut(player).unwrap();
+            let registry = test_registry();
+            inventory.add(test_block("glass"), INVENTORY_SIZE as u32 * 64, &registry);
+            assert_eq!(inventory.add(test_block("stone"), 70, &registry), 9);
+        }
+        let full = drop_item(&mut world, Point3::new(0.25, 0.6, 0.25), "stone", 5);
+        run_pickup(&mut world, game_config::ITEM_PICKUP_DELAY);
+        assert_eq!(item_count(&world, full), Some(5));
+
+        // 長い間拾われなかったアイテムは消える
+        run_pickup(&mut world, game_config::ITEM_LIFETIME);
+        assert_eq!(item_count(&world, far), None);
+        assert_eq!(item_count(&world, full), None);
+    }
+
+    #[test]
+    fn does_not_place_without_items() {
+        let (mut world, player) = setup(Point3::new(0.25, 2.0, 0.25));
+        world
+            .write_storage::<Inventory>()
+            .get_mut(player)
+            .unwrap()
+            .select(1);
+        click(&mut world, player, MouseButton::Right);
+        assert!(world
+            .read_resource::<GameWorld>()
+            .get_block(&block_pos(0, 1, 0))
+            .is_none());
+    }
+
+    #[test]
+    fn selects_hotbar_slots() {
+        let (world, player) = setup(Point3::new(0.25, 2.0, 0.25));
+        let select = |keys: &[Scancode], wheel: i32| {
+            {
+                let mut input = world.write_storage::<Input>();
+                let input = input.get_mut(player).unwrap();
+                input.pressed_keys = keys.to_vec();
+                input.wheel = wheel;
+            }
+            HotbarController.run_now(&world);
+            world
+                .read_storage::<Inventory>()
+                .get(player)
+                .unwrap()
+                .selected()
+        };
+        assert_eq!(select(&[Scancode::Num5], 0), 4);
+        assert_eq!(select(&[], 1), 3);
+        assert_eq!(select(&[], -2), 5);
+        assert_eq!(select(&[Scancode::Num1], 0), 0);
+        assert_eq!(select(&[], 1), HOTBAR_SIZE - 1);
+    }
+
+    #[test]
+    fn breaking_restarts_when_released() {
+        let (mut world, player) = setup(Point3::new(0.25, 2.0, 0.25));
+        hold_left(&mut world, player, 1400);
+        run_frame(&mut world, player, &[], &[], 100);
+        assert!(world
+            .read_storage::<Mining>()
+            .get(player)
+            .unwrap()
+            .target
+            .is_none());
+        hold_left(&mut world, player, 1400);
+        assert!(world
+            .read_resource::<GameWorld>()
+            .get_block(&block_pos(0, 0, 0))
+            .is_some());
+    }
+
+    #[test]
+    fn does_not_place_blocks_inside_entities() {
+        // (0, 0, 0)の石の上に立っている
+        let (mut world, player) = setup(Point3::new(0.25, 0.95, 0.25));
+        click(&mut world, player, MouseButton::Right);
+        let mut game_world = world.write_resource::<GameWorld>();
+        assert!(game_world.get_block(&block_pos(0, 1, 0)).is_none());
+        assert!(game_world.take_changed_chunks().is_empty());
+
+        // 接しているだけなら置ける
+        let body = AABB::new(Point3::new(0.5, 0.5, 0.0), Point3::new(0.8, 1.4, 0.3));
+        let planks = test_block("oak_planks");
+        assert!(BlockEditor::place(
+            &mut game_world,
+            planks,
+            &block_pos(0, 1, 0),
+            &[body]
+        ));
+        // 空いていない場所には置けない
+        assert!(!BlockEditor::place(
+            &mut game_world,
+            planks,
+            &block_pos(0, 1, 0),
+            &[]
+        ));
+        // 読み込まれていないチャンクには置けない
+        assert!(!BlockEditor::place(
+            &mut game_world,
+            planks,
+            &block_pos(100, 0, 0),
+            &[]
+        ));
+    }
+}
@@ -0,0 +1,247 @@
+//! 視線の先のブロックの枠と、壊している途中のひび割れの描画
+
+use c_str_macro::c_str;
+use nalgebra::{Point3, Vector3};
+use re::gl;
+use re::gl::Gl;
+use re::shader::{Program, Uniform, UniformVariables};
+use re::vao::line_builder::LineBuilder;
+use re::vao::mesh::Mesh;
+use re::vao::vao_builder::{CuboidFaces, CuboidRotations, CuboidTextures, VaoBuilder};
+use re::vao::Vao;
+use reverie_engine as re;
+
+use crate::block::{BlockDefinition, BlockState};
+use crate::mymath::BlockPosInWorld;
+use crate::texture::block_texture::BlockTextures;
+use crate::world::GameWorld;
+
+/// ひび割れのテクスチャの数。`crack_0`から順に割れていく
+pub const CRACK_STAGES: usize = 8;
+
+/// 直方体を持たないブロック(草花など)の枠
+const CROSS_BOX: ([f32; 3], [f32; 3]) = ([0.15, 0.0, 0.15], [0.85, 1.0, 0.85]);
+
+/// ブロックの面と重ならないように、枠を外側に広げる幅(ブロック単位)
+const OUTLINE_MARGIN: f32 = 0.005;
+
+/// ひび割れを外側に広げる幅(ブロック単位)。枠よりは内側にする
+const CRACK_MARGIN: f32 = 0.002;
+
+/// 視線の先のブロックの枠とひび割れを持ち、ブロックや進み具合が変わったときだけ作り直す
+pub struct TargetRenderer<'a> {
+    line_shader: &'a Program,
+    block_shader: &'a Program,
+    /// 今の`Vao`を作ったときのブロックとひび割れの段階
+    current: Option<(BlockPosInWorld, BlockState, Option<usize>)>,
+    outline: Option<Vao<'a>>,
+    crack: Option<Vao<'a>>,
+}
+
+impl<'a> TargetRenderer<'a> {
+    /// 枠は`line_shader`、ひび割れはチャンクと同じ`block_shader`で描画する
+    pub fn new(line_shader: &'a Program, block_shader: &'a Program) -> Self {
+        Self {
+            line_shader,
+            block_shader,
+            current: None,
+            outline: None,
+            crack: None,
+        }
+    }
+
+    /// `target`の枠と、壊し終わるまでの進み具合`progress`(0.0〜1.0)に応じたひび割れを描画するようにする
+    ///
+    /// `target`が`None`か、ブロックが無ければ何も描画しない
+    pub fn update(
+        &mut self,
+        gl: &Gl,
+        world: &GameWorld,
+        target: Option<&BlockPosInWorld>,
+        progress: f32,
+        textures: &BlockTextures,
+    ) {
+        let current =
+            target.and_then(|pos| Some((*pos, world.get_state(pos)?, crack_stage(progress))));
+        if current == self.current {
+            return;
+        }
+        self.current = current;
+        self.outline = None;
+        self.crack = None;
+        let (pos, state, stage) = match current {
+            Some(current) => current,
+            None => return,
+        };
+        let definition = world.registry().get(state.block());
+        self.outline = Some(Vao::from_mesh(
+            gl,
+            &outline_mesh(definition, state, &pos),
+            self.line_shader,
+        ));
+        let crack_texture = stage.and_then(|stage| textures.get(&format!("crack_{}", stage)));
+        if let Some(texture) = crack_texture {
+            let textures = CuboidTextures {
+                top: texture,
+                bottom: texture,
+                south: texture,
+                north: texture,
+                west: texture,
+                east: texture,
+                rotations: CuboidRotations::NONE,
+            };
+            self.crack = Some(Vao::from_mesh(
+                gl,
+                &crack_mesh(definition, state, &pos, &textures),
+                self.block_shader,
+            ));
+        }
+    }
+
+    /// チャンクを描画した後に呼ぶ
+    pub fn draw(&self, gl: &Gl, uniforms: &mut UniformVariables) {
+        if let Some(crack) = &self.crack {
+            uniforms.add(c_str!("uAlphaCutoff"), Uniform::Float(1.0 / 255.0));
+            unsafe {
+                gl.Enable(gl::BLEND);
+                gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+                gl.DepthMask(gl::FALSE);
+            }
+            crack.draw_triangles(uniforms);
+            unsafe {
+                gl.DepthMask(gl::TRUE);
+                gl.Disable(gl::BLEND);
+            }
+        }
+        if let Some(outline) = &self.outline {
+            uniforms.add(c_str!("uLineColor"), Uniform::TripleFloat(0.0, 0.0, 0.0));
+            outline.draw_lines(uniforms);
+        }
+    }
+}
+
+/// 進み具合`progress`(0.0〜1.0)でのひび割れの段階。壊し始めていなければ`None`
+pub fn crack_stage(progress: f32) -> Option<usize> {
+    if progress <= 0.0 {
+        None
+    } else {
+        Some(((progress * CRACK_STAGES as f32) as usize).min(CRACK_STAGES - 1))
+    }
+}
+
+/// ブロックの形に合わせた枠にする直方体(ブロック内の座標、0.0〜1.0)
+fn outline_boxes(definition: &BlockDefinition, state: BlockState) -> Vec<([f32; 3], [f32; 3])> {
+    let boxes = definition.boxes(state);
+    if boxes.is_empty() {
+        vec![CROSS_BOX]
+    } else {
+        boxes
+    }
+}
+
+/// `boxes`を`pos`に置いて、各辺を`margin`だけ外側に広げた直方体
+fn expanded_boxes(
+    boxes: Vec<([f32; 3], [f32; 3])>,
+    pos: &BlockPosInWorld,
+    margin: f32,
+) -> impl Iterator<Item = (Point3<f32>, Point3<f32>)> {
+    let origin = pos.cast::<f32>();
+    boxes.into_iter().map(move |(begin, end)| {
+        (
+            origin + Vector3::from(begin) - Vector3::repeat(margin),
+            origin + Vector3::from(end) + Vector3::repeat(margin),
+        )
+    })
+}
+
+/// `pos`にある`state`のブロックの枠
+pub fn outline_mesh(
+    definition: &BlockDefinition,
+    state: BlockState,
+    pos: &BlockPosInWorld,
+) -> Mesh {
+    let mut builder = LineBuilder::new();
+    for (begin, end) in expanded_boxes(outline_boxes(definition, state), pos, OUTLINE_MARGIN) {
+        builder.add_cuboid_edges(&begin, &end);
+    }
+    builder.build_mesh()
+}
+
+/// `pos`にある`state`のブロックに重ねるひび割れ
+fn crack_mesh(
+    definition: &BlockDefinition,
+    state: BlockState,
+    pos: &BlockPosInWorld,
+    textures: &CuboidTextures,
+) -> Mesh {
+    let mut builder = VaoBuilder::new();
+    for (begin, end) in expanded_boxes(outline_boxes(definition, state), pos, CRACK_MARGIN) {
+        builder.add_tiled_cuboid_faces(&begin, &end, textures, &CuboidFaces::ALL);
+    }
+    builder.build_mesh()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{test_block, test_registry};
+
+    /// 枠の頂点の座標の範囲
+    fn bounds(mesh: &Mesh) -> ([f32; 3], [f32; 3]) {
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        for i in 0..mesh.vertex_count() {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(mesh.vertex(i)[axis]);
+                max[axis] = max[axis].max(mesh.vertex(i)[axis]);
+            }
+        }
+        (min, max)
+    }
+
+    fn assert_bounds(mesh: &Mesh, min: [f32; 3], max: [f32; 3]) {
+        let (actual_min, actual_max) = bounds(mesh);
+        for axis in 0..3 {
+            assert!((actual_min[axis] - (min[axis] - OUTLINE_MARGIN)).abs() < 1e-5);
+            assert!((actual_max[axis] - (max[axis] + OUTLINE_MARGIN)).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn outlines_follow_block_shapes() {
+        let registry = test_registry();
+        let pos = BlockPosInWorld::new(Point3::new(-2, 3, 4));
+        let outline = |name: &str| {
+            let block = test_block(name);
+            outline_mesh(registry.get(block), block.default_state(), &pos)
+        };
+
+        let stone = outline("stone");
+        assert_eq!(stone.indices.len(), 2 * 12);
+        assert_bounds(&stone, [-2.0, 3.0, 4.0], [-1.0, 4.0, 5.0]);
+
+        let slab = outline("stone_slab");
+        assert_eq!(slab.indices.len(), 2 * 12);
+        assert_bounds(&slab, [-2.0, 3.0, 4.0], [-1.0, 3.5, 5.0]);
+
+        // 階段は2つの直方体
+        assert_eq!(outline("oak_stairs").indices.len(), 2 * 2 * 12);
+
+        let poppy = outline("poppy");
+        assert_bounds(&poppy, [-1.85, 3.0, 4.15], [-1.15, 4.0, 4.85]);
+    }
+
+    #[test]
+    fn crack_stages() {
+        for stage in 0..CRACK_STAGES {
+            let name = format!("crack_{}", stage);
+            assert!(test_registry().tiles().any(|(tile, _)| tile == name));
+        }
+
+        assert_eq!(crack_stage(0.0), None);
+        assert_eq!(crack_stage(0.01), Some(0));
+        assert_eq!(crack_stage(0.5), Some(CRACK_STAGES / 2));
+        assert_eq!(crack_stage(0.99), Some(CRACK_STAGES - 1));
+        assert_eq!(crack_stage(1.0), Some(CRACK_STAGES - 1));
+    }
+}
@@ -1,63 +1,141 @@
 use std::collections::HashMap;
 
-use crate::block::{Block, Side};
+use crate::block::{BlockDefinition, BlockRegistry, BlockState, Side};
 
 use re::texture::texture_atlas::TextureUV;
-use re::vao::vao_builder::CuboidTextures;
+use re::vao::vao_builder::{CuboidRotations, CuboidTextures};
 use reverie_engine as re;
 
-pub type BlockTextures = HashMap<&'static str, TextureUV>;
-
-pub fn get_textures_in_atlas(atlas_width: u32, atlas_height: u32) -> BlockTextures {
-    let mut dic = HashMap::new();
-    dic.insert(
-        "grass_side",
-        TextureUV::of_atlas(0, 0, 64, 64, atlas_width, atlas_height),
-    );
-    dic.insert(
-        "grass_top",
-        TextureUV::of_atlas(0, 1, 64, 64, atlas_width, atlas_height),
-    );
-    dic.insert(
-        "grass_bottom",
-        TextureUV::of_atlas(0, 2, 64, 64, atlas_width, atlas_height),
-    );
-    dic
-}
+pub type BlockTextures = HashMap<String, TextureUV>;
 
-// TODO: jsonで宣言したり、luaで計算したりさせたい
-pub fn get_texture_name(block: &Block, side: Side) -> &str {
-    match block {
-        Block::GrassBlock => match side {
-            Side::TOP => "grass_top",
-            Side::BOTTOM => "grass_bottom",
-            _ => "grass_side",
-        },
-    }
+/// `registry`で宣言されたテクスチャのテクスチャアトラス上の位置
+pub fn get_textures_in_atlas(
+    registry: &BlockRegistry,
+    atlas_width: u32,
+    atlas_height: u32,
+) -> BlockTextures {
+    let tile_size = registry.tile_size();
+    registry
+        .tiles()
+        .map(|(name, (row, column))| {
+            (
+                name.to_string(),
+                TextureUV::of_atlas(row, column, tile_size, tile_size, atlas_width, atlas_height),
+            )
+        })
+        .collect()
 }
 
+/// `state`の向きに合わせて回転させた各面のテクスチャ
 pub fn generate_cuboid_texture<'a>(
-    block: &Block,
+    block: &BlockDefinition,
+    state: BlockState,
     block_textures: &'a BlockTextures,
 ) -> CuboidTextures<'a> {
+    let orientation = block.orientation(state);
+    let get = |side| {
+        let texture = block.textures.get(orientation.source_side(side));
+        block_textures.get(texture).unwrap()
+    };
+    CuboidTextures {
+        top: get(Side::TOP),
+        bottom: get(Side::BOTTOM),
+        south: get(Side::SOUTH),
+        north: get(Side::NORTH),
+        west: get(Side::WEST),
+        east: get(Side::EAST),
+        rotations: cuboid_rotations(block, state),
+    }
+}
+
+/// `state`の向きに合わせて、各面のテクスチャを回転させる回数
+pub fn cuboid_rotations(block: &BlockDefinition, state: BlockState) -> CuboidRotations {
+    let orientation = block.orientation(state);
+    CuboidRotations {
+        top: orientation.texture_rotation(Side::TOP),
+        bottom: orientation.texture_rotation(Side::BOTTOM),
+        south: orientation.texture_rotation(Side::SOUTH),
+        north: orientation.texture_rotation(Side::NORTH),
+        west: orientation.texture_rotation(Side::WEST),
+        east: orientation.texture_rotation(Side::EAST),
+    }
+}
+
+/// `generate_cuboid_texture`のうち、ブロック内の直方体`from`〜`to`(0.0〜1.0)の各面に当たる部分
+///
+/// `Side::ALL`の順。面ごとのUV座標の向きは`VaoBuilder::add_cuboid`に合わせている。
+/// 回転させたテクスチャでは、回転させる前のテクスチャ上の範囲になる
+pub fn generate_box_textures(
+    block: &BlockDefinition,
+    state: BlockState,
+    block_textures: &BlockTextures,
+    from: [f32; 3],
+    to: [f32; 3],
+) -> [TextureUV; 6] {
+    let textures = generate_cuboid_texture(block, state, block_textures);
+    let [x0, y0, z0] = from;
+    let [x1, y1, z1] = to;
+    let rotations = textures.rotations;
+    Side::ALL.map(|side| match side {
+        Side::TOP => crop(textures.top, rotations.top, (x0, x1), (1.0 - z1, 1.0 - z0)),
+        Side::BOTTOM => crop(
+            textures.bottom,
+            rotations.bottom,
+            (1.0 - x1, 1.0 - x0),
+            (1.0 - z1, 1.0 - z0),
+        ),
+        Side::NORTH => crop(
+            textures.north,
+            rotations.north,
+            (1.0 - z1, 1.0 - z0),
+            (y0, y1),
+        ),
+        Side::SOUTH => crop(textures.south, rotations.south, (z0, z1), (y0, y1)),
+        Side::WEST => crop(
+            textures.west,
+            rotations.west,
+            (1.0 - x1, 1.0 - x0),
+            (y0, y1),
+        ),
+        Side::EAST => crop(textures.east, rotations.east, (x0, x1), (y0, y1)),
+    })
+}
+
+/// `generate_box_textures`の結果を`VaoBuilder::add_cuboid`に渡せる形にする
+///
+/// `rotations`は`generate_box_textures`に渡したブロックの状態での回転(see: `cuboid_rotations`)
+pub fn cuboid_textures(
+    textures: &[TextureUV; 6],
+    rotations: CuboidRotations,
+) -> CuboidTextures<'_> {
+    let [top, bottom, north, south, west, east] = textures;
     CuboidTextures {
-        top: block_textures
-            .get(get_texture_name(block, Side::TOP))
-            .unwrap(),
-        bottom: block_textures
-            .get(get_texture_name(block, Side::BOTTOM))
-            .unwrap(),
-        south: block_textures
-            .get(get_texture_name(block, Side::SOUTH))
-            .unwrap(),
-        north: block_textures
-            .get(get_texture_name(block, Side::NORTH))
-            .unwrap(),
-        west: block_textures
-            .get(get_texture_name(block, Side::WEST))
-            .unwrap(),
-        east: block_textures
-            .get(get_texture_name(block, Side::EAST))
-            .unwrap(),
+        top,
+        bottom,
+        south,
+        north,
+        west,
+        east,
+        rotations,
+    }
+}
+
+/// `uv`のうち、面の上での割合で表した範囲`u`、`v`に貼られる部分
+///
+/// `rotation`は`uv`を面に貼るときに回転させる回数(see: `CuboidRotations`)
+fn crop(uv: &TextureUV, rotation: u8, u: (f32, f32), v: (f32, f32)) -> TextureUV {
+    // テクスチャを反時計回りに回転させて貼るので、面の上の範囲を逆に回してテクスチャ上の範囲にする
+    let (u, v) = match rotation % 4 {
+        0 => (u, v),
+        1 => (v, (1.0 - u.1, 1.0 - u.0)),
+        2 => ((1.0 - u.1, 1.0 - u.0), (1.0 - v.1, 1.0 - v.0)),
+        _ => ((1.0 - v.1, 1.0 - v.0), u),
+    };
+    let lerp = |begin: f32, end: f32, t: f32| begin + (end - begin) * t;
+    TextureUV {
+        begin_u: lerp(uv.begin_u, uv.end_u, u.0),
+        end_u: lerp(uv.begin_u, uv.end_u, u.1),
+        begin_v: lerp(uv.begin_v, uv.end_v, v.0),
+        end_v: lerp(uv.begin_v, uv.end_v, v.1),
     }
 }
@@ -1,26 +1,187 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use nalgebra::{Point3, Vector3};
+use parry3d::bounding_volume::AABB;
+
+use crate::block::{self, Block, BlockModel, BlockRegistry, BlockState, Side};
+use crate::light::{LightKind, Lighting};
+use crate::mymath::BlockPosInWorld;
 use crate::mymath::ChunkPos;
+use crate::storage::level::{self, LevelData, PlayerData, LEVEL_FORMAT_VERSION};
+use crate::storage::{ChunkStorage, MemoryChunkStorage, RegionStorage};
+use crate::world_generator::{self, VoidGenerator, WorldGenerator};
 
-use super::chunk::Chunk;
+use super::chunk::{neighbor_offset, Chunk, ChunkNeighbors};
 
 pub struct GameWorld {
     chunks: HashMap<ChunkPos, Chunk>,
+    registry: Arc<BlockRegistry>,
+    /// ワーカースレッドと共有する(see: `ChunkWorkers`)
+    generator: Arc<dyn WorldGenerator>,
+    seed: u64,
+    storage: Box<dyn ChunkStorage>,
+    name: String,
+    /// セーブデータのディレクトリ。`None`ならメモリ上にしか存在しない
+    directory: Option<PathBuf>,
+    /// ゲーム内の経過時間(ミリ秒)
+    game_time: u64,
+    /// 最後に保存または設定したプレイヤーの状態
+    player: Option<PlayerData>,
+    /// ブロックや光が変わって、頂点データを作り直さなければならないチャンク
+    changed: HashSet<ChunkPos>,
 }
 
 impl GameWorld {
-    pub fn new() -> GameWorld {
+    /// 何も生成されない空のワールドを作る
+    pub fn new(registry: Arc<BlockRegistry>) -> GameWorld {
+        GameWorld::with_generator(registry, Box::new(VoidGenerator), 0)
+    }
+
+    /// 読み込まれていないチャンクを`generator`で生成するワールドを作る
+    ///
+    /// 解放したチャンクはメモリ上に保存される(see: `set_storage`)
+    pub fn with_generator(
+        registry: Arc<BlockRegistry>,
+        generator: Box<dyn WorldGenerator>,
+        seed: u64,
+    ) -> GameWorld {
         GameWorld {
             chunks: HashMap::new(),
+            registry,
+            generator: Arc::from(generator),
+            seed,
+            storage: Box::new(MemoryChunkStorage::new()),
+            name: String::new(),
+            directory: None,
+            game_time: 0,
+            player: None,
+            changed: HashSet::new(),
         }
     }
 
+    /// `directory`に新しいワールドを作る
+    pub fn create(
+        directory: &Path,
+        name: &str,
+        registry: Arc<BlockRegistry>,
+        generator: Box<dyn WorldGenerator>,
+        seed: u64,
+    ) -> io::Result<GameWorld> {
+        std::fs::create_dir_all(directory)?;
+        let storage = RegionStorage::open(&level::region_dir(directory), registry.clone())?;
+        let mut world = GameWorld::with_generator(registry, generator, seed);
+        world.set_storage(Box::new(storage));
+        world.name = name.to_string();
+        world.directory = Some(directory.to_path_buf());
+        level::write_level(directory, &world.level_data())?;
+        Ok(world)
+    }
+
+    /// `directory`に保存されているワールドを開く
+    ///
+    /// チャンクは必要になったときに読み込まれる
+    pub fn open(directory: &Path, registry: Arc<BlockRegistry>) -> io::Result<GameWorld> {
+        let level = level::read_level(directory)?;
+        let generator = world_generator::from_name(&level.generator).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown world generator '{}'", level.generator),
+            )
+        })?;
+        let storage = RegionStorage::open(&level::region_dir(directory), registry.clone())?;
+        let mut world = GameWorld::with_generator(registry, generator, level.seed);
+        world.set_storage(Box::new(storage));
+        world.name = level.name;
+        world.directory = Some(directory.to_path_buf());
+        world.game_time = level.game_time;
+        world.player = level::read_player(directory)?;
+        Ok(world)
+    }
+
+    /// ワールドの情報、プレイヤーの状態、変更されたチャンクを保存する
+    ///
+    /// `open`や`create`で作ったワールドでなければ、チャンクを保存先に書き出すだけ
+    ///
+    /// どれかの保存に失敗しても残りは保存して、最初のエラーを返す
+    pub fn save(&mut self) -> io::Result<()> {
+        let mut result = self.save_all();
+        if let Some(directory) = &self.directory {
+            result = result.and(level::write_level(directory, &self.level_data()));
+            if let Some(player) = &self.player {
+                result = result.and(level::write_player(directory, player));
+            }
+        }
+        result
+    }
+
+    fn level_data(&self) -> LevelData {
+        LevelData {
+            format_version: LEVEL_FORMAT_VERSION,
+            name: self.name.clone(),
+            seed: self.seed,
+            generator: self.generator.name().to_string(),
+            game_time: self.game_time,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// ゲーム内の経過時間(ミリ秒)
+    pub fn game_time(&self) -> u64 {
+        self.game_time
+    }
+
+    pub fn advance_time(&mut self, milliseconds: u64) {
+        self.game_time += milliseconds;
+    }
+
+    /// 保存されていたプレイヤーの状態。まだ一度も保存していなければ`None`
+    pub fn player(&self) -> Option<&PlayerData> {
+        self.player.as_ref()
+    }
+
+    /// 次に`save`するときに保存するプレイヤーの状態を設定する
+    pub fn set_player(&mut self, player: PlayerData) {
+        self.player = Some(player);
+    }
+
+    /// チャンクの保存先を変更する
+    pub fn set_storage(&mut self, storage: Box<dyn ChunkStorage>) {
+        self.storage = storage;
+    }
+
+    pub fn registry(&self) -> &Arc<BlockRegistry> {
+        &self.registry
+    }
+
+    pub fn generator(&self) -> &dyn WorldGenerator {
+        self.generator.as_ref()
+    }
+
+    /// 別のスレッドでチャンクを生成するための`generator`
+    pub fn shared_generator(&self) -> Arc<dyn WorldGenerator> {
+        Arc::clone(&self.generator)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// 生成したチャンクを加える。すでに読み込まれていれば`Err`
+    ///
+    /// ワーカースレッドで中の光を計算済みなら(see: `light::light_chunk_alone`)、
+    /// ここでは隣のチャンクとの境界だけ計算する
     pub fn add_chunk(&mut self, chunk: Chunk) -> Result<(), ()> {
         if self.chunks.contains_key(chunk.position()) {
             return Err(());
         }
 
-        self.chunks.insert(*chunk.position(), chunk);
+        self.insert_chunk(chunk, false);
 
         Ok(())
     }
@@ -28,4 +189,555 @@ impl GameWorld {
     pub fn get_chunk(&self, pos: &ChunkPos) -> Option<&Chunk> {
         self.chunks.get(pos)
     }
+
+    pub fn get_chunk_mut(&mut self, pos: &ChunkPos) -> Option<&mut Chunk> {
+        self.chunks.get_mut(pos)
+    }
+
+    /// `pos`のチャンクの周りの、読み込まれているチャンク
+    pub fn chunk_neighbors(&self, pos: &ChunkPos) -> ChunkNeighbors<'_> {
+        std::array::from_fn(|index| match neighbor_offset(index) {
+            [0, 0, 0] => None,
+            offset => self.get_chunk(&ChunkPos::new(**pos + Vector3::from(offset))),
+        })
+    }
+
+    /// 読み込まれているチャンクの位置
+    pub fn loaded_chunks(&self) -> impl Iterator<Item = &ChunkPos> {
+        self.chunks.keys()
+    }
+
+    pub fn is_loaded(&self, pos: &ChunkPos) -> bool {
+        self.chunks.contains_key(pos)
+    }
+
+    /// チャンクを読み込む
+    ///
+    /// 保存先にあればそれを読み込み、なければ生成する。すでに読み込まれていれば何もしない
+    pub fn load_chunk(&mut self, pos: &ChunkPos) -> io::Result<()> {
+        if !self.load_saved_chunk(pos)? {
+            let chunk = world_generator::generate_chunk(
+                self.generator.as_ref(),
+                pos,
+                self.seed,
+                &self.registry,
+            );
+            self.insert_chunk(chunk, false);
+        }
+        Ok(())
+    }
+
+    /// 保存先にチャンクがあれば読み込む
+    ///
+    /// 読み込んだか、すでに読み込まれていれば`true`。保存先に無ければ生成せずに`false`を返す
+    pub fn load_saved_chunk(&mut self, pos: &ChunkPos) -> io::Result<bool> {
+        if self.chunks.contains_key(pos) {
+            return Ok(true);
+        }
+        match self.storage.load_chunk(pos)? {
+            Some(chunk) => {
+                self.insert_chunk(chunk, true);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// チャンクを解放する
+    ///
+    /// 変更されていれば先に保存する。保存に失敗したときは解放しない
+    pub fn unload_chunk(&mut self, pos: &ChunkPos) -> io::Result<()> {
+        if let Some(chunk) = self.chunks.get_mut(pos) {
+            if chunk.is_dirty() {
+                self.storage.save_chunk(chunk)?;
+                chunk.clear_dirty();
+            }
+            self.chunks.remove(pos);
+        }
+        Ok(())
+    }
+
+    /// 読み込まれているチャンクのうち、変更されたものをすべて保存する
+    pub fn save_all(&mut self) -> io::Result<()> {
+        for chunk in self.chunks.values_mut() {
+            if chunk.is_dirty() {
+                self.storage.save_chunk(chunk)?;
+                chunk.clear_dirty();
+            }
+        }
+        Ok(())
+    }
+
+    /// 読み込んだチャンクを加えて、隣のチャンクとの間で光を広げる
+    ///
+    /// 中の光を計算していないチャンクは、先に中の光を計算する。
+    /// `saved`なら保存先から読み込んだチャンクで、保存されていた光の境界を計算し直す
+    fn insert_chunk(&mut self, chunk: Chunk, saved: bool) {
+        let pos = *chunk.position();
+        let lit = chunk.is_lit();
+        self.chunks.insert(pos, chunk);
+        self.with_lighting(|lighting| {
+            if !lit {
+                lighting.light_chunk(&pos);
+            } else if saved {
+                lighting.relight_border(&pos);
+            }
+            lighting.connect_chunk(&pos);
+        });
+    }
+
+    fn with_lighting(&mut self, f: impl FnOnce(&mut Lighting)) {
+        let (generator, seed) = (&self.generator, self.seed);
+        let open_sky = |x, z, y| world_generator::is_open_sky(generator.as_ref(), seed, x, z, y);
+        let mut changed = HashSet::new();
+        let mut lighting = Lighting::new(&mut self.chunks, &self.registry, &open_sky, &mut changed);
+        f(&mut lighting);
+        for pos in &changed {
+            if let Some(chunk) = self.chunks.get_mut(pos) {
+                chunk.compact_light();
+            }
+        }
+        self.changed.extend(changed);
+    }
+
+    /// 前回呼んでから、ブロックや光が変わった読み込まれているチャンク
+    pub fn take_changed_chunks(&mut self) -> Vec<ChunkPos> {
+        let chunks = &self.chunks;
+        self.changed
+            .drain()
+            .filter(|pos| chunks.contains_key(pos))
+            .collect()
+    }
+
+    /// ワールド座標での光の強さ
+    ///
+    /// 読み込まれていないチャンクでは`None`
+    pub fn light(&self, kind: LightKind, pos: &BlockPosInWorld) -> Option<u8> {
+        let (chunk_pos, block_pos) = pos.split();
+        Some(self.chunks.get(&chunk_pos)?.light(kind, &block_pos))
+    }
+
+    /// ワールド座標でブロックを取得する
+    ///
+    /// 読み込まれていないチャンクのブロックは`None`
+    pub fn get_block(&self, pos: &BlockPosInWorld) -> Option<Block> {
+        let (chunk_pos, block_pos) = pos.split();
+        self.chunks
+            .get(&chunk_pos)
+            .and_then(|chunk| chunk.get_block(&block_pos))
+    }
+
+    /// ワールド座標でブロックの状態を取得する
+    ///
+    /// 読み込まれていないチャンクのブロックは`None`
+    pub fn get_state(&self, pos: &BlockPosInWorld) -> Option<BlockState> {
+        let (chunk_pos, block_pos) = pos.split();
+        self.chunks
+            .get(&chunk_pos)
+            .and_then(|chunk| chunk.get_state(&block_pos))
+    }
+
+    /// `region`と重なるマスにあるブロックの当たり判定の直方体を`out`に追加する
+    ///
+    /// 座標は描画や当たり判定に使う座標系(1ブロックの大きさは0.5)で表す。
+    /// エンティティが落ちていかないように、読み込まれていないチャンクのマスは全体を固体とする
+    pub fn collect_collision_aabbs(&self, region: &AABB, out: &mut Vec<AABB>) {
+        let min = (region.mins * 2.0).map(|c| c.floor() as i32);
+        let max = (region.maxs * 2.0).map(|c| c.floor() as i32);
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let pos = BlockPosInWorld::new(Point3::new(x, y, z));
+                    let (chunk_pos, block_pos) = pos.split();
+                    let chunk = match self.chunks.get(&chunk_pos) {
+                        Some(chunk) => chunk,
+                        None => {
+                            let begin = pos.cast::<f32>();
+                            out.push(AABB::new(begin * 0.5, (begin + Vector3::repeat(1.0)) * 0.5));
+                            continue;
+                        }
+                    };
+                    if let Some(state) = chunk.get_state(&block_pos) {
+                        out.extend(block::get_block_aabbs(
+                            self.registry.get(state.block()),
+                            state,
+                            &pos,
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    /// ワールド座標でブロックをデフォルトの状態で置く
+    ///
+    /// チャンクが読み込まれていない場合は読み込む。
+    /// 保存先から読み込めなかったときは、保存されているチャンクを上書きしないように何もせずにエラーを返す
+    pub fn set_block(&mut self, block: &Block, pos: &BlockPosInWorld) -> io::Result<()> {
+        self.set_state(&block.default_state(), pos)
+    }
+
+    /// ワールド座標でブロックを置く(see: `set_block`)
+    ///
+    /// 置いたブロックとその隣の、`connects`なブロックのつながりも更新する
+    pub fn set_state(&mut self, state: &BlockState, pos: &BlockPosInWorld) -> io::Result<()> {
+        self.set_state_without_update(Some(*state), pos)?;
+        self.update_neighbors(pos)
+    }
+
+    /// ワールド座標でブロックを取り除く(see: `set_block`)
+    pub fn remove_block(&mut self, pos: &BlockPosInWorld) -> io::Result<()> {
+        self.set_state_without_update(None, pos)?;
+        self.update_neighbors(pos)
+    }
+
+    fn update_neighbors(&mut self, pos: &BlockPosInWorld) -> io::Result<()> {
+        self.update_connections(pos)?;
+        for &side in &CONNECTING_SIDES {
+            self.update_connections(&pos.neighbor(side))?;
+        }
+        Ok(())
+    }
+
+    /// `pos`のブロックが`connects`なら、`north`などのプロパティを隣のブロックに合わせる
+    fn update_connections(&mut self, pos: &BlockPosInWorld) -> io::Result<()> {
+        let state = match self.get_state(pos) {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+        let registry = Arc::clone(&self.registry);
+        let definition = registry.get(state.block());
+        if !definition.connects {
+            return Ok(());
+        }
+        let mut new_state = state;
+        for &side in &CONNECTING_SIDES {
+            let connected = self.get_state(&pos.neighbor(side)).is_some_and(|neighbor| {
+                let neighbor = registry.get(neighbor.block());
+                neighbor.connects || (neighbor.solid && neighbor.model == BlockModel::Cube)
+            });
+            let property = side_property(side);
+            new_state = definition
+                .with_value(
+                    new_state,
+                    property,
+                    if connected { "true" } else { "false" },
+                )
+                .unwrap();
+        }
+        if new_state != state {
+            self.set_state_without_update(Some(new_state), pos)?;
+        }
+        Ok(())
+    }
+
+    /// `state`が`None`ならブロックを取り除く
+    fn set_state_without_update(
+        &mut self,
+        state: Option<BlockState>,
+        pos: &BlockPosInWorld,
+    ) -> io::Result<()> {
+        let (chunk_pos, block_pos) = pos.split();
+        self.load_chunk(&chunk_pos)?;
+        let chunk = self.chunks.get_mut(&chunk_pos).unwrap();
+        match state {
+            Some(state) => chunk.set_state(&state, &block_pos),
+            None => chunk.remove_block(&block_pos),
+        }
+        self.changed.extend(pos.adjacent_chunks());
+        self.with_lighting(|lighting| lighting.update_block(pos));
+        Ok(())
+    }
+}
+
+/// フェンスなどがつながる向き
+const CONNECTING_SIDES: [Side; 4] = [Side::NORTH, Side::SOUTH, Side::WEST, Side::EAST];
+
+fn side_property(side: Side) -> &'static str {
+    match side {
+        Side::NORTH => "north",
+        Side::SOUTH => "south",
+        Side::WEST => "west",
+        Side::EAST => "east",
+        Side::TOP => "up",
+        Side::BOTTOM => "down",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point3;
+
+    use super::*;
+    use crate::block::{test_block, test_registry};
+    use crate::mymath::{block_pos, BlockPosInChunk};
+    use crate::storage::level::ItemStackData;
+    use crate::storage::{temp_dir, BrokenStorage};
+    use crate::world_generator::{FlatGenerator, NoiseGenerator};
+
+    #[test]
+    fn set_block_creates_chunk() {
+        let mut world = GameWorld::new(test_registry());
+        let chunk_pos = ChunkPos::new(Point3::new(2, 0, -1));
+        assert!(world.get_chunk(&chunk_pos).is_none());
+
+        world
+            .set_block(&test_block("grass_block"), &block_pos(40, 3, -5))
+            .unwrap();
+
+        let chunk = world.get_chunk(&chunk_pos).unwrap();
+        assert!(chunk
+            .get_block(&BlockPosInChunk::new(8, 3, 11).unwrap())
+            .is_some());
+    }
+
+    #[test]
+    fn get_block_across_chunk_borders() {
+        let mut world = GameWorld::new(test_registry());
+        let positions = [
+            block_pos(0, 0, 0),
+            block_pos(-1, 0, 0),
+            block_pos(15, 15, 15),
+            block_pos(16, -1, -16),
+            block_pos(-17, -33, 47),
+        ];
+        for pos in &positions {
+            world.set_block(&test_block("grass_block"), pos).unwrap();
+        }
+        for pos in &positions {
+            assert!(world.get_block(pos).is_some(), "{:?}", pos);
+        }
+        assert!(world.get_block(&block_pos(1, 0, 0)).is_none());
+        assert!(world.get_block(&block_pos(-2, 0, 0)).is_none());
+        assert!(world.get_block(&block_pos(1000, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn fences_connect_to_neighbors() {
+        let registry = test_registry();
+        let fence = registry.get(test_block("oak_fence"));
+        let mut world = GameWorld::new(test_registry());
+        world
+            .set_block(&test_block("stone"), &block_pos(1, 0, 0))
+            .unwrap();
+        world
+            .set_block(&test_block("poppy"), &block_pos(-1, 0, 0))
+            .unwrap();
+        world.set_block(&fence.block, &block_pos(0, 0, 0)).unwrap();
+        world.set_block(&fence.block, &block_pos(0, 0, 1)).unwrap();
+
+        let value = |world: &GameWorld, pos, property| {
+            fence.bool_value(world.get_state(&pos).unwrap(), property)
+        };
+        assert_eq!(value(&world, block_pos(0, 0, 0), "north"), Some(true));
+        assert_eq!(value(&world, block_pos(0, 0, 0), "south"), Some(false));
+        assert_eq!(value(&world, block_pos(0, 0, 0), "east"), Some(true));
+        assert_eq!(value(&world, block_pos(0, 0, 0), "west"), Some(false));
+        assert_eq!(value(&world, block_pos(0, 0, 1), "west"), Some(true));
+        assert_eq!(value(&world, block_pos(0, 0, 1), "north"), Some(false));
+    }
+
+    #[test]
+    fn load_chunk_generates() {
+        let mut world =
+            GameWorld::with_generator(test_registry(), Box::new(FlatGenerator::default()), 0);
+        let chunk_pos = ChunkPos::new(Point3::new(-3, 0, 8));
+        world.load_chunk(&chunk_pos).unwrap();
+        assert!(world.is_loaded(&chunk_pos));
+        assert_eq!(
+            world.get_block(&block_pos(-40, 0, 130)),
+            Some(test_block("stone"))
+        );
+        assert!(!world.get_chunk(&chunk_pos).unwrap().is_dirty());
+    }
+
+    #[test]
+    fn unloaded_changes_are_kept() {
+        let mut world =
+            GameWorld::with_generator(test_registry(), Box::new(FlatGenerator::default()), 0);
+        let pos = block_pos(3, 10, 3);
+        world.set_block(&test_block("stone"), &pos).unwrap();
+        let chunk_pos = pos.chunk_pos();
+        assert!(world.get_chunk(&chunk_pos).unwrap().is_dirty());
+
+        world.unload_chunk(&chunk_pos).unwrap();
+        assert!(!world.is_loaded(&chunk_pos));
+        assert_eq!(world.get_block(&pos), None);
+
+        world.load_chunk(&chunk_pos).unwrap();
+        assert_eq!(world.get_block(&pos), Some(test_block("stone")));
+        assert_eq!(
+            world.get_block(&block_pos(3, 5, 3)),
+            Some(test_block("grass_block"))
+        );
+    }
+
+    #[test]
+    fn unreadable_chunks_are_not_replaced() {
+        let mut world =
+            GameWorld::with_generator(test_registry(), Box::new(FlatGenerator::default()), 0);
+        world.set_storage(Box::new(BrokenStorage));
+        let pos = block_pos(3, 10, 3);
+        assert!(world.set_block(&test_block("stone"), &pos).is_err());
+        assert!(world.remove_block(&pos).is_err());
+        // 生成し直したチャンクで保存されているチャンクを上書きしない
+        assert!(!world.is_loaded(&pos.chunk_pos()));
+        world.save_all().unwrap();
+    }
+
+    #[test]
+    fn collision_aabbs_near_region() {
+        let mut world = GameWorld::new(test_registry());
+        world
+            .set_block(&test_block("stone"), &block_pos(0, 0, 0))
+            .unwrap();
+        world
+            .set_block(&test_block("stone_slab"), &block_pos(1, 0, 0))
+            .unwrap();
+        world
+            .set_block(&test_block("poppy"), &block_pos(0, 1, 0))
+            .unwrap();
+        world
+            .set_block(&test_block("stone"), &block_pos(5, 0, 0))
+            .unwrap();
+
+        // (0, 0, 0)〜(1, 1, 0)のマスと重なる範囲
+        let region = AABB::new(Point3::new(0.1, 0.1, 0.1), Point3::new(0.9, 0.9, 0.4));
+        let mut aabbs = Vec::new();
+        world.collect_collision_aabbs(&region, &mut aabbs);
+        // 草花は固体でなく、範囲の外のブロックは含まない
+        assert_eq!(
+            aabbs,
+            [
+                AABB::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.5, 0.5, 0.5)),
+                AABB::new(Point3::new(0.5, 0.0, 0.0), Point3::new(1.0, 0.25, 0.5)),
+            ]
+        );
+
+        // 読み込まれていないチャンクのマスは全体が固体
+        aabbs.clear();
+        let region = AABB::new(Point3::new(0.1, -0.4, 0.1), Point3::new(0.2, -0.1, 0.2));
+        world.collect_collision_aabbs(&region, &mut aabbs);
+        assert_eq!(
+            aabbs,
+            [AABB::new(
+                Point3::new(0.0, -0.5, 0.0),
+                Point3::new(0.5, 0.0, 0.5)
+            )]
+        );
+    }
+
+    #[test]
+    fn save_and_open() {
+        let temp = temp_dir("save");
+        let dir = temp.join("my world");
+        let pos = block_pos(-5, 20, 100);
+        let player = PlayerData {
+            position: [1.0, 2.5, -3.0],
+            pitch: 90.0,
+            yaw: -10.0,
+            velocity: [0.0, -0.001, 0.0],
+            inventory: vec![
+                None,
+                Some(ItemStackData {
+                    item: "stone".to_string(),
+                    count: 12,
+                }),
+            ],
+            selected_slot: 1,
+            leftovers: vec![ItemStackData {
+                item: "dirt".to_string(),
+                count: 64,
+            }],
+        };
+        {
+            let mut world = GameWorld::create(
+                &dir,
+                "My World",
+                test_registry(),
+                Box::new(NoiseGenerator::default()),
+                99,
+            )
+            .unwrap();
+            world.set_block(&test_block("stone"), &pos).unwrap();
+            world.advance_time(1234);
+            world.set_player(player.clone());
+            world.save().unwrap();
+        }
+
+        let mut world = GameWorld::open(&dir, test_registry()).unwrap();
+        assert_eq!(world.name(), "My World");
+        assert_eq!(world.seed(), 99);
+        assert_eq!(world.generator().name(), "noise");
+        assert_eq!(world.game_time(), 1234);
+        assert_eq!(world.player(), Some(&player));
+        assert!(!world.is_loaded(&pos.chunk_pos()));
+        world.load_chunk(&pos.chunk_pos()).unwrap();
+        assert_eq!(world.get_block(&pos), Some(test_block("stone")));
+    }
+
+    #[test]
+    fn player_is_saved_even_if_chunks_are_not() {
+        let dir = temp_dir("broken");
+        let player = PlayerData {
+            position: [1.0, 2.0, 3.0],
+            pitch: 0.0,
+            yaw: 0.0,
+            velocity: [0.0; 3],
+            inventory: Vec::new(),
+            selected_slot: 0,
+            leftovers: Vec::new(),
+        };
+        {
+            let mut world = GameWorld::create(
+                &dir,
+                "broken",
+                test_registry(),
+                Box::new(FlatGenerator::default()),
+                0,
+            )
+            .unwrap();
+            world
+                .set_block(&test_block("stone"), &block_pos(0, 20, 0))
+                .unwrap();
+            world.set_storage(Box::new(BrokenStorage));
+            world.advance_time(1234);
+            world.set_player(player.clone());
+            assert!(world.save().is_err());
+        }
+
+        let world = GameWorld::open(&dir, test_registry()).unwrap();
+        assert_eq!(world.game_time(), 1234);
+        assert_eq!(world.player(), Some(&player));
+    }
+
+    #[test]
+    fn open_rejects_future_version() {
+        let dir = temp_dir("future");
+        GameWorld::create(
+            &dir,
+            "future",
+            test_registry(),
+            Box::new(FlatGenerator::default()),
+            0,
+        )
+        .unwrap();
+        let path = level::level_path(&dir);
+        let text = std::fs::read_to_string(&path).unwrap().replace(
+            &format!("\"format_version\": {}", LEVEL_FORMAT_VERSION),
+            &format!("\"format_version\": {}", LEVEL_FORMAT_VERSION + 1),
+        );
+        std::fs::write(&path, text).unwrap();
+
+        let err = GameWorld::open(&dir, test_registry()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn open_missing_world() {
+        let temp = temp_dir("missing");
+        let dir = temp.join("nothing");
+        let err = GameWorld::open(&dir, test_registry()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
 }
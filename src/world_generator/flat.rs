@@ -0,0 +1,106 @@
+use crate::block::BlockRegistry;
+use crate::chunk::Chunk;
+use crate::mymath::{BlockPosInChunk, BlockPosInWorld};
+
+use super::WorldGenerator;
+
+/// 同じ層を水平に敷き詰めるジェネレーター
+pub struct FlatGenerator {
+    /// y=0から上に向かって順に積む層。(ブロックの名前, 厚さ)
+    layers: Vec<(String, u32)>,
+}
+
+impl FlatGenerator {
+    pub const NAME: &'static str = "flat";
+
+    pub fn new(layers: Vec<(String, u32)>) -> Self {
+        Self { layers }
+    }
+
+    /// ワールド座標でy番目の高さにあるブロック
+    fn block_at(&self, y: i32) -> Option<&str> {
+        if y < 0 {
+            return None;
+        }
+        let mut top = 0;
+        for (block, thickness) in &self.layers {
+            top += *thickness as i32;
+            if y < top {
+                return Some(block);
+            }
+        }
+        None
+    }
+}
+
+impl Default for FlatGenerator {
+    fn default() -> Self {
+        Self::new(vec![
+            ("stone".to_string(), 3),
+            ("dirt".to_string(), 2),
+            ("grass_block".to_string(), 1),
+        ])
+    }
+}
+
+impl WorldGenerator for FlatGenerator {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn surface_height(&self, _x: i32, _z: i32, _seed: u64) -> Option<i32> {
+        let thickness: u32 = self.layers.iter().map(|(_, thickness)| thickness).sum();
+        if thickness == 0 {
+            None
+        } else {
+            Some(thickness as i32 - 1)
+        }
+    }
+
+    fn fill_chunk(&self, chunk: &mut Chunk, _seed: u64, registry: &BlockRegistry) {
+        let chunk_pos = *chunk.position();
+        for y in 0..16 {
+            let world_y = BlockPosInWorld::from_chunk_pos(
+                &chunk_pos,
+                &BlockPosInChunk::new(0, y, 0).unwrap(),
+            )
+            .y;
+            if let Some(block) = self
+                .block_at(world_y)
+                .and_then(|name| registry.by_name(name))
+            {
+                for x in 0..16 {
+                    for z in 0..16 {
+                        chunk.set_block(&block, &BlockPosInChunk::new(x, y, z).unwrap());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::generate;
+    use super::*;
+    use crate::block::test_block;
+
+    #[test]
+    fn default_layers() {
+        let chunk = generate(&FlatGenerator::default(), 3, 0, -2, 0);
+        let at = |y| chunk.get_block(&BlockPosInChunk::new(7, y, 9).unwrap());
+        assert_eq!(at(0), Some(test_block("stone")));
+        assert_eq!(at(2), Some(test_block("stone")));
+        assert_eq!(at(3), Some(test_block("dirt")));
+        assert_eq!(at(4), Some(test_block("dirt")));
+        assert_eq!(at(5), Some(test_block("grass_block")));
+        assert_eq!(at(6), None);
+        assert_eq!(FlatGenerator::default().surface_height(0, 0, 0), Some(5));
+    }
+
+    #[test]
+    fn nothing_below_zero() {
+        let chunk = generate(&FlatGenerator::default(), 0, -1, 0, 0);
+        assert!(BlockPosInChunk::all().all(|pos| chunk.get_block(&pos).is_none()));
+    }
+}
@@ -0,0 +1,100 @@
+//! チャンクの地形生成
+
+use crate::block::BlockRegistry;
+use crate::chunk::Chunk;
+use crate::mymath::ChunkPos;
+
+pub use flat::FlatGenerator;
+pub use noise::NoiseGenerator;
+pub use void::VoidGenerator;
+
+mod flat;
+mod noise;
+mod void;
+
+/// チャンクの中身を生成する
+///
+/// 同じ`ChunkPos`とシード値に対しては常に同じ中身を生成しなければならない。
+/// OpenGLには依存しないので、テストから直接呼び出せる
+pub trait WorldGenerator: Send + Sync {
+    /// ジェネレーターの名前。セーブデータに記録される
+    fn name(&self) -> &'static str;
+
+    /// `chunk.position()`の位置にあるチャンクの中身を埋める
+    ///
+    /// ブロックは名前で`registry`から探す。`registry`に無いブロックは置かない
+    fn fill_chunk(&self, chunk: &mut Chunk, seed: u64, registry: &BlockRegistry);
+
+    /// ワールド座標(x, z)で一番上にあるブロックのy座標。ブロックが無ければ`None`
+    ///
+    /// プレイヤーの出現位置を決めるのに使う
+    fn surface_height(&self, x: i32, z: i32, seed: u64) -> Option<i32>;
+}
+
+/// `pos`の位置にあるチャンクを生成する
+///
+/// 生成したチャンクは同じシードから再び作れるので、変更されていないものとして扱う
+pub fn generate_chunk(
+    generator: &dyn WorldGenerator,
+    pos: &ChunkPos,
+    seed: u64,
+    registry: &BlockRegistry,
+) -> Chunk {
+    let mut chunk = Chunk::new(*pos);
+    generator.fill_chunk(&mut chunk, seed, registry);
+    chunk.compact();
+    chunk.clear_dirty();
+    chunk
+}
+
+/// 生成される地形で、ワールド座標(x, z)の列の高さ`y`より上にブロックが無いか
+///
+/// 真上のチャンクが読み込まれていないときに、空の光が届くとみなすのに使う(see: `Lighting`)
+pub fn is_open_sky(generator: &dyn WorldGenerator, seed: u64, x: i32, z: i32, y: i32) -> bool {
+    generator
+        .surface_height(x, z, seed)
+        .is_none_or(|height| height < y)
+}
+
+/// 名前からジェネレーターを作る
+pub fn from_name(name: &str) -> Option<Box<dyn WorldGenerator>> {
+    match name {
+        FlatGenerator::NAME => Some(Box::new(FlatGenerator::default())),
+        NoiseGenerator::NAME => Some(Box::new(NoiseGenerator::default())),
+        VoidGenerator::NAME => Some(Box::new(VoidGenerator)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point3;
+
+    use super::*;
+    use crate::block::test_registry;
+    use crate::mymath::BlockPosInChunk;
+
+    pub(super) fn generate(
+        generator: &dyn WorldGenerator,
+        x: i32,
+        y: i32,
+        z: i32,
+        seed: u64,
+    ) -> Chunk {
+        let mut chunk = Chunk::new(ChunkPos::new(Point3::new(x, y, z)));
+        generator.fill_chunk(&mut chunk, seed, &test_registry());
+        chunk
+    }
+
+    pub(super) fn same_blocks(a: &Chunk, b: &Chunk) -> bool {
+        BlockPosInChunk::all().all(|pos| a.get_block(&pos) == b.get_block(&pos))
+    }
+
+    #[test]
+    fn from_name_returns_generator_with_same_name() {
+        for name in &["flat", "noise", "void"] {
+            assert_eq!(from_name(name).unwrap().name(), *name);
+        }
+        assert!(from_name("unknown").is_none());
+    }
+}
@@ -0,0 +1,227 @@
+use crate::block::BlockRegistry;
+use crate::chunk::Chunk;
+use crate::mymath::{BlockPosInChunk, BlockPosInWorld};
+
+use super::WorldGenerator;
+
+/// パーリンノイズの高さマップで起伏のある地形を作るジェネレーター
+///
+/// 地表は草ブロック、その下`dirt_depth`ブロックは土、さらに下は石になる
+pub struct NoiseGenerator {
+    /// 平均の地表の高さ
+    pub base_height: i32,
+    /// 地表の高さの振れ幅
+    pub amplitude: f64,
+    /// 地形の水平方向の大きさ。大きいほどなだらかになる
+    pub scale: f64,
+    /// ノイズを重ねる回数
+    pub octaves: u32,
+    /// 土の層の厚さ
+    pub dirt_depth: i32,
+}
+
+impl NoiseGenerator {
+    pub const NAME: &'static str = "noise";
+    const SURFACE_BLOCK: &'static str = "grass_block";
+    const DIRT_BLOCK: &'static str = "dirt";
+    const STONE_BLOCK: &'static str = "stone";
+
+    /// ワールド座標(x, z)での地表のy座標
+    pub fn terrain_height(&self, x: i32, z: i32, seed: u64) -> i32 {
+        let noise = fractal_noise(
+            seed,
+            x as f64 / self.scale,
+            z as f64 / self.scale,
+            self.octaves,
+        );
+        self.base_height + (noise * self.amplitude).round() as i32
+    }
+}
+
+impl Default for NoiseGenerator {
+    fn default() -> Self {
+        Self {
+            base_height: 6,
+            amplitude: 8.0,
+            scale: 32.0,
+            octaves: 4,
+            dirt_depth: 3,
+        }
+    }
+}
+
+impl WorldGenerator for NoiseGenerator {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn surface_height(&self, x: i32, z: i32, seed: u64) -> Option<i32> {
+        Some(self.terrain_height(x, z, seed))
+    }
+
+    fn fill_chunk(&self, chunk: &mut Chunk, seed: u64, registry: &BlockRegistry) {
+        let chunk_pos = *chunk.position();
+        let grass = registry.by_name(Self::SURFACE_BLOCK);
+        let dirt = registry.by_name(Self::DIRT_BLOCK);
+        let stone = registry.by_name(Self::STONE_BLOCK);
+        for x in 0..16 {
+            for z in 0..16 {
+                let column = BlockPosInWorld::from_chunk_pos(
+                    &chunk_pos,
+                    &BlockPosInChunk::new(x, 0, z).unwrap(),
+                );
+                let surface = self.terrain_height(column.x, column.z, seed);
+                for y in 0..16 {
+                    let world_y = column.y + y as i32;
+                    let block = if world_y > surface {
+                        continue;
+                    } else if world_y == surface {
+                        grass
+                    } else if world_y >= surface - self.dirt_depth {
+                        dirt
+                    } else {
+                        stone
+                    };
+                    if let Some(block) = block {
+                        chunk.set_block(&block, &BlockPosInChunk::new(x, y, z).unwrap());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// シード値と格子点から決まる64bitのハッシュ値 (SplitMix64)
+fn hash(seed: u64, x: i64, z: i64) -> u64 {
+    let mut h = seed
+        ^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (z as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^ (h >> 31)
+}
+
+/// 格子点の勾配ベクトル
+///
+/// 三角関数は環境によって結果がずれる可能性があるので、決まった8方向から選ぶ
+fn gradient(seed: u64, x: i64, z: i64) -> (f64, f64) {
+    const D: f64 = std::f64::consts::FRAC_1_SQRT_2;
+    const GRADIENTS: [(f64, f64); 8] = [
+        (1.0, 0.0),
+        (-1.0, 0.0),
+        (0.0, 1.0),
+        (0.0, -1.0),
+        (D, D),
+        (D, -D),
+        (-D, D),
+        (-D, -D),
+    ];
+    GRADIENTS[(hash(seed, x, z) >> 61) as usize]
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+/// 2次元のパーリンノイズ。おおよそ[-1, 1]の値を返す
+fn perlin(seed: u64, x: f64, z: f64) -> f64 {
+    let x0 = x.floor();
+    let z0 = z.floor();
+    let (fx, fz) = (x - x0, z - z0);
+    let (ix, iz) = (x0 as i64, z0 as i64);
+
+    let dot = |gx: i64, gz: i64, dx: f64, dz: f64| {
+        let (gradient_x, gradient_z) = gradient(seed, gx, gz);
+        gradient_x * dx + gradient_z * dz
+    };
+    let n00 = dot(ix, iz, fx, fz);
+    let n10 = dot(ix + 1, iz, fx - 1.0, fz);
+    let n01 = dot(ix, iz + 1, fx, fz - 1.0);
+    let n11 = dot(ix + 1, iz + 1, fx - 1.0, fz - 1.0);
+
+    let (u, v) = (fade(fx), fade(fz));
+    lerp(lerp(n00, n10, u), lerp(n01, n11, u), v) * std::f64::consts::SQRT_2
+}
+
+/// 周波数を倍にしながらパーリンノイズを重ねる。おおよそ[-1, 1]の値を返す
+fn fractal_noise(seed: u64, x: f64, z: f64, octaves: u32) -> f64 {
+    let mut total = 0.0;
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+    let mut max = 0.0;
+    for octave in 0..octaves {
+        let octave_seed = seed.wrapping_add(octave as u64);
+        total += perlin(octave_seed, x * frequency, z * frequency) * amplitude;
+        max += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    total / max
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{generate, same_blocks};
+    use super::*;
+    use crate::block::test_block;
+
+    #[test]
+    fn deterministic_per_seed() {
+        let generator = NoiseGenerator::default();
+        for &(x, y, z) in &[(0, 0, 0), (-3, 0, 5), (7, -1, -7)] {
+            let a = generate(&generator, x, y, z, 42);
+            let b = generate(&generator, x, y, z, 42);
+            assert!(same_blocks(&a, &b));
+        }
+    }
+
+    #[test]
+    fn different_seeds_give_different_terrain() {
+        let generator = NoiseGenerator::default();
+        let heights = |seed| {
+            (0..64)
+                .map(|i| generator.terrain_height(i * 5, i * 3, seed))
+                .collect::<Vec<_>>()
+        };
+        assert_ne!(heights(1), heights(2));
+    }
+
+    #[test]
+    fn layers_below_surface() {
+        let generator = NoiseGenerator::default();
+        let seed = 12345;
+        let chunk = generate(&generator, 0, 0, 0, seed);
+        for x in 0..16 {
+            for z in 0..16 {
+                let surface = generator.terrain_height(x, z, seed);
+                for y in 0..16 {
+                    let expected = if y > surface {
+                        None
+                    } else if y == surface {
+                        Some(test_block("grass_block"))
+                    } else if y >= surface - generator.dirt_depth {
+                        Some(test_block("dirt"))
+                    } else {
+                        Some(test_block("stone"))
+                    };
+                    let pos = BlockPosInChunk::new(x as u32, y as u32, z as u32).unwrap();
+                    assert_eq!(chunk.get_block(&pos), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn noise_is_continuous() {
+        let generator = NoiseGenerator::default();
+        for x in -20..20 {
+            let a = generator.terrain_height(x, 0, 7);
+            let b = generator.terrain_height(x + 1, 0, 7);
+            assert!((a - b).abs() <= 2, "{} -> {}", a, b);
+        }
+    }
+}
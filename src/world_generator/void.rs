@@ -0,0 +1,23 @@
+use crate::block::BlockRegistry;
+use crate::chunk::Chunk;
+
+use super::WorldGenerator;
+
+/// 何も置かないジェネレーター
+pub struct VoidGenerator;
+
+impl VoidGenerator {
+    pub const NAME: &'static str = "void";
+}
+
+impl WorldGenerator for VoidGenerator {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn fill_chunk(&self, _chunk: &mut Chunk, _seed: u64, _registry: &BlockRegistry) {}
+
+    fn surface_height(&self, _x: i32, _z: i32, _seed: u64) -> Option<i32> {
+        None
+    }
+}